@@ -11,6 +11,29 @@ The Simulation reads `ClientEvent`s and outputs `ServerEvent`s. It is loaded as
 
 Each Dimension has it own `BevyWorld`, Resources, and Systems, but some resources are shared across Dimensions and are syncronized internally by Bevy. The Simulation will load the Overworld on start, but will not load any other Dimensions until explicitly instructed otherwise. 
 
+The `SimulationPlugin` creates a `SubApp` for every Dimension with `dimensions::new_dimension`, each with its own `World` resource, `DimensionSettings` and the `DimensionPlugin`. Every frame, each Dimension extracts the number of ticks that are due from the main app, and runs one tick for each of them.
+
+Dimensions are loaded and unloaded at runtime through the `LoadedDimensions` resource of the main app, which starts out with only the Overworld. Dimensions that aren't loaded aren't ticked. When a Dimension is unloaded, every one of its Chunks is saved through its `ChunkStreamer`, if it has one, and every Chunk is removed from its `World`, which keeps its `HeightmapTable` and `SubChunkPool` for when the Dimension is loaded again.
+
+=== Ticks
+The Simulation advances in fixed steps called ticks. The `tick::TickClock` resource of the main app counts the ticks that are due every frame from real time, at the plugin's `tps`, which is 20 ticks per second by default. Time left over from a frame is carried into the next, so the Simulation ticks at the same rate in a headless server driven by a `ScheduleRunnerPlugin` and in the Client, whatever its frame rate.
+
+When frames fall behind, the clock catches up by running several ticks in one frame, but never more than `max_steps`. The ticks past that budget are dropped and counted by `TickClock::skipped`, so a server that can't keep up falls behind instead of spending ever longer frames catching up. The clock can be paused, and stepped by hand with `TickClock::step`, which runs the ticks in the next frame whether the clock is paused or not.
+
+Each tick runs the tick schedules of a Dimension in order:
+
+- `PreTick` streams Chunks in and out, and advances world generation.
+- `BlockUpdates` changes blocks.
+- `Entities` moves entities.
+- `PostTick` lights the Chunks and blocks that changed during the tick.
+
+The main app and every Dimension have a `TickCounter` resource. The main app's counts every tick the clock counted, and a Dimension's counts the ticks it has run, which leaves out the ticks while it wasn't loaded. During a tick, the counter is the number of the tick, starting at 0.
+
+=== Events
+The events are defined in `simulation::events`. A `ClientEvent` is something a client asked for: joining or leaving a Dimension, moving, placing or breaking a block, or sending a chat message. The Server queues the events of the clients in each Dimension in its `ClientEvents` resource, and the Dimension applies all of them on its next tick. Block events are applied in `BlockUpdates`, so blocks are only placed into air and air can't be broken. The rest are applied in `Entities`, which empties the queue. Joining spawns a `Player` with a `ChunkLoader`, so Chunks are streamed around it.
+
+A `ServerEvent` is a mutation that clients need to know about: chunk data, a block change, an entity spawning, moving or despawning, or a chat message. Every Dimension pushes them into its `ServerEvents` resource, which the Server drains after the Dimension is updated and sends to the clients near each event. Block changes are produced from the World's change log in `PostTick`, so a block changed by any system is sent, and the changes are queued in the `LightQueue` to be relit. Only changes in Chunks that have reached `ChunkStatus::Light` are sent, since clients get the Chunks that are still generating whole once they are lit. Chunks are sent as `encode_chunk` data once the `LightQueue` has lit them, or as soon as they are inserted if the Dimension has no light queue. Blocks are named by GlobalID in both directions, so a Dimension needs the `Registry<Block>` resource for block events and for recording. Chunk data names its biomes by GlobalID as well, so Chunks are only sent if the Dimension also has the `Registry<Biome>` resource.
+
 == The Server
 
 == The Client
@@ -69,20 +92,45 @@ For light color, I rejected using RGB because the majority of the color space wo
 === SubChunks
 A `SubChunk` is a 32x32x32 volume of blocks in the world. Its origin is considered to be the minimum coordinate within the subchunk, for example the SubChunk with origin [0,32,64] is all blocks between that origin and [32, 64, 96], exclusively. SubChunk origins _must_ always be multiples of 32. This means that the coordinate [32, 32, 1] is not a valid origin because 1 is not a multiple of 32.  
 
-A SubChunk is internally a `[BlockState; 32768]`. Each element is a `BlockState`, which has a size of 4 bytes, meaning that a SubChunk has a size of ~131kb. Indexing this array with a 3-D coordinate relative to subchunk origin (in the range [0, 31]) can be done with the following formula: 
+A SubChunk is internally a `[LocalID; 32768]` and a parallel `[Light; 32768]`. Each element of both arrays is 2 bytes, meaning that a SubChunk has a size of ~131kb. The `BlockState` of a block is reconstructed from the two arrays when it is read, so `SubChunk::get_block`, `WorldReader`, `Volume` and `Neighbours` still return BlockStates. Storing light separately means light updates don't rewrite the block ids, and blocks that only differ in light can share a palette entry when the SubChunk is compressed. Use `SubChunk::ids()` and `SubChunk::lights()` to access the arrays directly. Indexing these arrays with a 3-D coordinate relative to subchunk origin (in the range [0, 31]) can be done with the following formula: 
 
 #align(center, $y + x dot 32 + z dot 32 dot 32$)
 
 SubChunk data is Y-Major. This means that the BlockStates are _contiguous_ (linear) on the Y-axis. When iterating over the elements of a subchunk, it will be significantly faster (due to cache optimization) to iterate on the Y-Axis. 
 
+==== Compressed SubChunks
+Most SubChunks only have a handful of distinct blocks, and many (all air, all stone) only have one. A `CompressedSubChunk` stores its blocks in a `PalettedContainer`, which keeps a local palette of the distinct values and packs an index into that palette for every block, using the fewest bits that can index the palette. A SubChunk with only one value has no indices at all. Indices never span two 64-bit words, so a word holds `64 / bits` indices.
+
+A CompressedSubChunk has one PalettedContainer for block ids and one for light. Use `SubChunk::compress()` to get a CompressedSubChunk and `CompressedSubChunk::decompress()` to get a SubChunk back. Reading from a CompressedSubChunk is slower than a SubChunk, so it is meant for SubChunks that are stored or rarely accessed. Run `cargo bench -p simulation` to compare the two layouts.
+
 === Chunks
 Chunks are vertical columns of subchunks. MCRE makes the assumption, that, if a SubChunk exists, every subchunk between that subchunk and $y=0$ also exists. The number of SubChunks that a Chunk has is equal to the highest block in the Chunks' y-coordinate divided by 32 plus 1. So if the highest block in Chunk [0, 0] is 32, there are two SubChunks - one at [0,0,0] and one at [0,32,0].
 
 The origin of a Chunk is a 2D coordinate containing the Chunks' lowest [x,z] coordinate. The Y value is excluded because all Chunks have a lowest y-value of 0. The coordinate's components must be multiples of 32, just like SubChunks' origin.
 
+==== Heightmaps
+Every Chunk keeps three heightmaps, which store the height of the highest block in each column that isn't air, that blocks skylight, and that is solid. A height is one above the highest block, so an empty column has a height of 0. Which heightmaps a block counts towards is decided by the World's `HeightmapTable`, which is built from the block registry with `HeightmapTable::from_registry` and set with `World::set_heightmap_table`. Writes through a WorldWriter or a mutable Cluster update the heightmaps, and `World::insert` rebuilds them, so they are always up to date for Chunks in the World. Use `WorldReader::height` or `WorldReader::highest_block` to query them.
+
+==== Biomes
+Every Chunk stores the biome of each of its columns in a `BiomeMap`, as the LocalID of the biome in the biome registry. Biomes are set by the terrain generator, and read with `Chunk::biome_at` or `WorldReader::biome`. Biomes are saved with the Chunk by GlobalID, like blocks, so saves stay valid when biomes are added or registered in a different order.
+
+==== Serialization
+Chunks are saved with `world::codec::encode_chunk` and loaded with `world::codec::decode_chunk`, which can be passed straight to `World::insert`. The encoding stores each SubChunk as a pair of PalettedContainers, with a palette of `GlobalID`s for the whole Chunk, so saves stay valid when blocks are added and LocalIDs change. The Chunk's `ChunkStatus` and `BiomeMap` are saved alongside its SubChunks, with the biomes as a palette of `GlobalID`s and runs of columns, so `encode_chunk` and `decode_chunk` need the `Registry<Biome>` as well. The encoding starts with a magic number and a format version, and ends with an xxh32 checksum. Data that is corrupt, written by an unknown version, or that uses a block or biome that isn't registered is rejected with a `ChunkDecodeError`. A Chunk with more than `MAX_SUBCHUNKS` SubChunks is rejected before any SubChunk is taken from the pool, so a corrupt save can't allocate more than one Chunk's worth of SubChunks. `encode_subchunk` and `decode_subchunk` do the same for a single SubChunk.
+
+==== Region Files
+Saved Chunks are stored on disk in region files by `world::region::RegionStorage`. A region is 32x32 Chunks stored in one file, named after the coordinates of the region, so Chunks with negative coordinates are in regions with negative coordinates. Each file is made of 4KB sectors, and starts with an offset table that has the first sector and the number of sectors of every Chunk in the region. When a Chunk is saved, it is written to free sectors before the offset table is updated, and the sectors it used before are freed and reused by later saves, so an interrupted save leaves the old Chunk in place. `RegionStorage::unload_from` saves a Chunk and removes it from the World, and `RegionStorage::load_into` puts it back, so the World doesn't need to keep every Chunk in memory.
+
 === World
 The `World` data structure is internally a `BTreeMap<u64, Chunk>`. 
 
+==== Streaming
+A World doesn't hold every Chunk at once. The `ChunkStreamer` resource keeps the Chunks within a view distance of every entity with a `ChunkLoader` component loaded. Missing Chunks are loaded or generated by a `ChunkSource` on the rayon thread pool, closest first, and are inserted into the World on a later tick. Chunks that are too far from every player are taken out of the World and saved by the `ChunkSource` on the thread pool. A Chunk isn't loaded again until its save has finished, so a player who walks away and straight back doesn't load a stale save. `RegionSource` loads Chunks from region files and falls back to another source, such as a terrain generator, for Chunks that were never saved.
+
+When a Chunk is removed from the World, its SubChunk boxes are returned to the World's `SubChunkPool`. The streamer splits a few boxes off the pool for each load and merges them back once the load is finished, so once it warms up, moving around the World doesn't allocate SubChunks. Call `ChunkStreamer::wait` before exiting so every unloaded Chunk is saved.
+
+==== SubChunk Pool
+A SubChunk is ~131kb, so SubChunks are never allocated directly. `World::alloc_subchunk` takes a box from the pool, resetting it, or allocates one if the pool is empty, and `World::free_subchunk` returns it. `ChunkBuilder` builds a Chunk with SubChunks from a pool, which is how generators and decoders should create Chunks. The pool keeps at most `SubChunkPool::cap` boxes, dropping the rest, and `SubChunkPool::stats` counts how many allocations were served by the pool.
+
 == Interacting with The World
 When you use `World::get_chunk()`, the chunk map has to be searched. This, while fast, isn't something you want to be doing thousands of times per frame. Because of this, we provide sets of iterators and readers for accessing world data more efficiently.
 
@@ -107,6 +155,8 @@ The Fragments iterator accesses each subchunk the volume overlaps, returning a F
 
 The Columns iterator returns a Column, which is an iterator over the blocks in a column, in the order of bottom-to-top. If you want to iterate from top-to-bottom, use `Column::rev()`. Columns is slower than Fragments, but the order blocks are visited is guaranteed. 
 
+`Volume::par_fragments()` visits the fragments on the rayon thread pool. For passes over the entire world, `World::par_map_subchunks()` maps every subchunk in parallel and returns the results in a fixed order, and `World::par_fold_subchunks()` folds those results on the calling thread, so the result of a reduction is the same on every run even if the reduction is not associative.
+
 ```rs
 use bevy::math::IVec3;
 fn read_volume(world: Res<World>) {
@@ -144,10 +194,25 @@ fn read_neighbours(world: Res<World>) {
 ```
 
 === WorldBuffer
-WorldBuffers are 
+WorldBuffers are dense copies of a box-shaped region of the world. Unlike the other readers, a `WorldBuffer` owns its data, so it can be sent to another thread, operated on, and written back to the world. This makes it the input format for jobs like meshing and lighting, which need a subchunk and the blocks around it. `World::read_buffer(origin, extent)` copies any region, and `World::read_subchunk_padded(origin, padding)` copies a subchunk along with `padding` blocks of its neighbours on every side. Blocks that are not in the world are `BlockState::default()`. A buffer is indexed with a `WorldPos3`, and is laid out like a SubChunk, linear on the y axis, then x, then z.
+
+`WorldWriter::write_buffer` writes a buffer back to the world, skipping blocks that have not changed, so writing back a buffer with padding only records changes for the blocks that were actually modified.
+
+```rs
+use bevy::math::IVec3;
+fn relight(world: &mut World) {
+    // the subchunk at the origin and a 1-block apron.
+    let mut buffer = world.read_subchunk_padded(IVec3::ZERO, 1);
+    let buffer = std::thread::spawn(move || {
+        buffer[IVec3::new(4, 4, 4)].light = Light::ZERO;
+        buffer
+    }).join().unwrap();
+    world.writer().write_buffer(&buffer);
+}
+```
 
 === Clusters
-This is the reader used internally for intense operations on entire chunks, such as computing chunk meshes or light updates. Clusters are limited because they require all 8 neighbouring chunks to be loaded and generated. However, accessing the world this way is much faster than other methods because we don't have to check if the chunk exists. Clusters come in 2x2 and 3x3 forms, where a 3x3 cluster is all chunks within 32 blocks of a block and a 2x2 Cluster is all blocks within 16 blocks of a block. Mutable Clusters, in the form `ClusterMut2x2` and `ClusterMut3x3` use unsafe logic internally because they hold multiple mutable references to the World, but are safe to use.
+This is the reader used internally for intense operations on entire chunks, such as computing chunk meshes or light updates. Clusters are limited because they require all 8 neighbouring chunks to be loaded and generated. However, accessing the world this way is much faster than other methods because we don't have to check if the chunk exists. Clusters come in 2x2 and 3x3 forms, where a 3x3 cluster is all chunks within 32 blocks of a block and a 2x2 Cluster is all blocks within 16 blocks of a block. Mutable Clusters, in the form `ClusterMut2x2` and `ClusterMut3x3` use unsafe logic internally because they hold multiple mutable references to the World, but are safe to use. Like a `WorldWriter`, blocks written with `set_block` on a Mutable Cluster are recorded in the World's change log.
 
 ```rs
 use bevy::math::IVec3;
@@ -159,4 +224,106 @@ fn read_from_cluster(world: &mut World) {
 ```
 
 === WorldWriter
-The WorldWriter is the main way the World should be mutated. 
\ No newline at end of file
+The WorldWriter is the main way the World should be mutated. Like the WorldReader, it caches the most recently accessed chunk, so writes that are close to each other are fast. `WorldWriter::set_block` returns the `BlockState` that was replaced, or `None` if the chunk containing the position isn't loaded. Writing above the highest SubChunk of a Chunk creates every missing SubChunk up to the target Y, so the assumption that all SubChunks between a SubChunk and $y=0$ exist still holds. The World is `MAX_HEIGHT` blocks tall, which is `MAX_SUBCHUNKS` SubChunks, so `set_block` returns `None` at or above it like it does below $y=0$, and `fill` skips the part of its box that is outside of the World.
+
+`WorldWriter::fill(bounds, state)` sets every block in a `BlockBox`, which is the blocks from `min` up to but not including `max`. A Volume borrows the World to read it, so it can't be passed to a WorldWriter. Instead, `Volume::bounds` gives the `BlockBox` of its selection, and `BlockBox::from_extent(origin, extent)` selects the same blocks as `WorldReader::volume`. `fill` only looks up each Chunk once, but like `set_block` it records a `BlockChange` for every block it writes, and the `LightQueue` relights each of them on its own, so filling a large box in a lit Chunk is expensive.
+
+```rs
+use bevy::math::IVec3;
+fn write_some_blocks(mut world: ResMut<World>) {
+    let mut writer = world.writer();
+    let old = writer.set_block(IVec3::new(0, 4, 0), BlockState::default());
+    println!("Replaced {:?}", old);
+    // clear [8,8,8] to [40,40,40]
+    writer.fill(BlockBox::new(IVec3::splat(8), IVec3::splat(40)), BlockState::default());
+}
+```
+
+=== Change Tracking
+Every block changed by a WorldWriter is recorded in the World's `ChangeLog` as a `BlockChange`, which holds the position, the old state and the new state. Writes that don't change the state are not recorded. Each SubChunk also has a 64-bit dirty mask, where each bit is an 8x8x8 section of the SubChunk, so systems like meshing and lighting only have to look at the sections that were modified instead of rescanning the whole SubChunk.
+
+`World::drain_changes()` takes the block changes and `World::drain_dirty()` takes the dirty SubChunks and their masks, clearing them as it goes. Both should be drained once per tick.
+
+```rs
+fn react_to_changes(mut world: ResMut<World>) {
+    for change in world.drain_changes() {
+        println!("{:?} changed from {:?} to {:?}", change.pos, change.old, change.new);
+    }
+    for (origin, mask) in world.drain_dirty() {
+        for section in dirty_sections(origin, mask) {
+            println!("The section at {section:?} needs to be remeshed.");
+        }
+    }
+}
+``` 
+= Light
+Light is computed by engines in `world::light`, which read the `Transparency` and emitted light of each block from a `LightTable`. The table is built from the block registry with `LightTable::from_registry`. Light only moves between two blocks if the face it leaves through and the face it enters through are both transparent, so a bottom slab lets light in from the sides but not from above.
+
+== Skylight
+Skylight is stored in the ambient nibble of each block's `Light`. `SkyLight::light_chunk` seeds an ambient of 15 down each column of a Chunk until it reaches a block that light can't pass through, then flood-fills from those blocks, losing one level per block. Light travelling straight down at 15 doesn't lose a level, so columns open to the sky are fully lit. The flood-fill crosses SubChunk and Chunk borders, and pulls in the light of the neighbouring Chunks, so Chunks can be lit in any order as they are loaded.
+
+When a block is set, pass the block that was replaced to `SkyLight::update_block`. Light that reached other blocks through the old block is removed, and the light of the surrounding blocks flows back in, so placing a block casts a shadow and breaking it lets the sky back in.
+
+```rs
+fn place(world: &mut World, sky: &mut SkyLight, pos: IVec3, state: BlockState) {
+    if let Some(old) = world.writer().set_block(pos, state) {
+        sky.update_block(world, pos, old);
+    }
+}
+```
+
+== Block Light
+Block light is stored in the intensity, hue and lightness nibbles of each block's `Light`. Every block whose `Block::emits_light` is set emits light at an intensity of 15, with the hue and lightness of its color. `BlockLight` spreads this light like skylight, one level per block, but it also carries the color. Light is spread from the brightest blocks to the dimmest, so when a block is colored, all of its brighter neighbours are already final. A block's color is the mix of the colors of the neighbours that light it, which are the neighbours one level brighter. Where the light of two emitters meets at the same level, their colors are mixed, and the mix spreads on from there.
+
+`mix_colors` averages hues around the color wheel, weighted by how saturated each color is, and averages the lightness. The more the hues disagree, the closer the lightness is raised to white, so red and blue mix to magenta, and complementary colors mix to white. `BlockLight::light_chunk` and `BlockLight::update_block` work the same way as their skylight counterparts. When a light is removed, every block that was lit by it is cleared, along with its color, before the light of the remaining emitters flows back in.
+
+== Light Queue
+Each dimension can have a `world::lighting::LightQueue` resource, which owns a `SkyLight` and a `BlockLight` engine. Every Chunk inserted into the World is recorded in the change log, and `LightQueue::update` lights the Chunks that were inserted since the last update. Block changes are queued with `LightQueue::queue_block` or `LightQueue::queue_changes`, and relit in the same update. Blocks in Chunks that aren't lit yet are skipped, both when they are queued and when the queue is drained, since those Chunks are lit whole once they are queued. The `update_light` system runs the queue in `PostTick`, after everything else in the tick. The change log's inserted and removed Chunks are cleared at the end of every tick by `clear_chunk_changes`, so Dimensions without a light queue don't collect them forever.
+
+When light spreads into a Chunk that isn't loaded, or that was inserted but isn't lit yet, the block it came from is deferred under the origin of that Chunk. When the Chunk is lit, the deferred blocks spread their light into it. This means Chunks can be loaded in any order, or many at once, without leaving dark seams along their borders. Light that already spread into a Chunk before it was unloaded isn't deferred, so a Chunk is also lit from the borders of its lit neighbours. When a Chunk is removed from the World, the `LightQueue` forgets the light deferred into it and out of it.
+
+= Terrain
+Chunks that were never saved are generated by a `terrain::ChunkGenerator`. A generator produces a whole Chunk from its origin, taking SubChunks from the pool it is given, like a `ChunkSource`. Generators must be deterministic: the same generator always produces the same Chunk for the same origin, so a Chunk that is unloaded without being saved comes back unchanged. Wrap a generator in a `GeneratorSource` to stream its Chunks, or use it as the fallback of a `RegionSource`.
+
+```rs
+let blocks = TerrainBlocks::from_registry(&registry).unwrap();
+let biomes = BiomeTable::from_registry(&biome_registry, &registry).unwrap();
+let source = RegionSource::new(storage, registry, biome_registry, GeneratorSource(OverworldGenerator::new(seed, blocks, biomes)));
+```
+
+== Biomes
+A `terrain::Biome` is registered in a `Registry<Biome>` like any other registry entry, and describes the climate it is found in, the shape of its terrain, and the blocks that cover it. `register_biomes` adds the default biomes: plains, desert, mountains and tundra. Biomes name their blocks by `Id`, and a `BiomeTable` looks them up in the block registry once, so the generator works with LocalIDs.
+
+Every column has a temperature and a humidity, sampled from two low frequency noises. The column gets the biome whose climate is closest. Choosing biomes this way makes the borders between biomes sharp, so the shape of the terrain is blended instead: `BiomeTable::blend` weights every biome whose climate is almost as close as the closest one, with weights that fall to 0 at `OverworldSettings::biome_blend`. At a border, the biomes on both sides have the same weight, so the terrain rises from plains into mountains without a cliff.
+
+== Overworld
+`OverworldGenerator` samples the height of every column from fractal simplex noise, seeded with the world seed, at the column's world position, and scales it by the blended base height and amplitude of the column's biome. Because every noise only depends on the seed and the position, columns line up across Chunk borders no matter which order Chunks are generated in. Each column is stone, covered by the subsurface blocks of its biome and one surface block. The noise is set with `OverworldSettings`. The stone is looked up by name with `TerrainBlocks::from_registry`, which expects `stone`, `dirt` and `grass` to be registered.
+
+== Nether
+`NetherGenerator` fills the nether with netherrack wherever 3D fractal noise is low enough, and leaves caverns where it is high. The noise is pushed down towards the floor and the ceiling, so the caverns are enclosed, and caverns below `NetherSettings::lava_level` are flooded with lava. The generator is as tall as the height it is built with, and the bottom and top layers are bedrock, and the few layers next to them are broken up by bedrock that gets rarer away from the edge, decided by a hash of the seed and the position. The nether has nothing to carve, so its Chunks come out with the `Carvers` status like the overworld's. Its blocks are looked up with `NetherBlocks::from_registry`, which expects `netherrack`, `bedrock` and `lava` to be registered.
+
+== Dimension Settings
+Every dimension has a `dimensions::DimensionSettings` resource, which holds what makes it different from the others: its height in blocks, and whether it has a sky. The overworld is 256 blocks tall and lit by the sky. The nether is 128 blocks tall and has no sky. `DimensionSettings::light_queue` builds the `LightQueue` of a dimension, with `LightQueue::without_skylight` if it has no sky. `Nether::install_terrain` builds the nether from its settings: a `NetherGenerator` as tall as the dimension, streamed around every `ChunkLoader` by a `ChunkStreamer`, a `GenerationScheduler`, and the light queue.
+
+== Carvers
+Once the columns of a Chunk are filled, the generator's `Carver`s carve air out of it, in order. A carver is given the Chunk and the height of the surface of each column. Carvings may only depend on the seed of the carver and the world position, so a carving that crosses a Chunk border continues into the neighbouring Chunk, whichever Chunk is generated first. The overworld carves noise caves and then ravines, and the carvers can be replaced with `OverworldGenerator::with_carvers`.
+
+`NoiseCaves` samples 3D noise at every block below the surface. Large "cheese" caverns are carved where one noise is high, and long "spaghetti" tunnels where two other noises are both close to 0. A few blocks below the surface are never carved by noise caves, so the surface isn't riddled with holes.
+
+`Ravines` are carved by a worm that winds through the terrain, carving an ellipsoid at every step. Whether a ravine starts in a Chunk, and its whole path, come from a `Pcg32` seeded with the seed and the origin of that Chunk. To carve a Chunk, the ravines of every Chunk within `Ravines::range` are traced from the start, but only the blocks inside the Chunk are carved. Every step draws its random values even when it is outside the Chunk, so each Chunk traces exactly the same path.
+
+== Features
+Once a Chunk and all 8 of its neighbours have generated, the Chunk is decorated with features: ores, trees, boulders, flowers and anything else that is placed on or in the terrain. A `Feature` is data, registered in a `Registry<Feature>`, which says how many times it is tried in each Chunk, the chance that each try is placed, where the tries go, and what is placed. Each `Biome` lists the features placed in it, in order, and `register_features` adds every feature used by the default biomes. A `FeatureTable` looks up the blocks of every feature, and the features of every biome, like a `BiomeTable`.
+
+`Decorator::decorate` places the features of the biome at the center of a Chunk through the `ClusterMut3x3` around it, so a tree on the edge of a Chunk can grow leaves into its neighbour. It returns false if any of the neighbours isn't loaded yet. Each feature of each Chunk has its own `Pcg32`, seeded with the seed, the origin of the Chunk and the GlobalID of the feature, so a Chunk is always decorated the same way, and adding a feature doesn't move the others. Blocks are placed with `ClusterMut3x3::set_block`, so the heightmaps are kept up to date and the changes are recorded for lighting.
+
+== Generation Status
+Every Chunk has a `ChunkStatus`, which says how far it has come through world generation: `Empty`, `Noise`, `Carvers`, `Features`, `Light` and then `Full`. The statuses are ordered, so a Chunk with a status has finished every stage before it. Chunks built by hand start out `Full`, and chunks loaded from a save keep the status they were saved with, so the generation scheduler picks up where it left off.
+
+Filling and carving a Chunk never touch its neighbours, so a generator does both on a worker thread and hands over the Chunk with the `Carvers` status. The later stages read from or write into the neighbours, so the `terrain::GenerationScheduler` resource only advances a Chunk once all 8 of its neighbours have reached the stage before:
+
+- `Features` waits for every neighbour to be carved, because features are placed through the 3x3 cluster around the Chunk.
+- `Light` waits for every neighbour to have its features, because they may write into the Chunk.
+- `Full` waits for every neighbour to be lit, because until then light may still spread in.
+
+The `advance_generation` system advances each waiting Chunk by one stage per update, decorating it with the scheduler's `Decorator` and queueing it in the `LightQueue`. The `LightQueue` leaves Chunks that are inserted before they reach `Light` unlit, and light that spreads into them is deferred until the scheduler queues them.
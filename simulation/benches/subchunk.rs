@@ -0,0 +1,128 @@
+//! Compares the flat SubChunk layout against the palette-compressed layout.
+//!
+//! Run with `cargo bench -p simulation`.
+
+#![feature(test)]
+
+extern crate test;
+
+use bevy::math::IVec3;
+use simulation::blocks::{BlockState, Light};
+use simulation::data::registry::LocalID;
+use simulation::world::SubChunk;
+use test::{black_box, Bencher};
+
+const W: i32 = 32;
+
+fn state(id: u16) -> BlockState {
+    BlockState {
+        block: LocalID::new(id),
+        light: Light::ZERO,
+    }
+}
+
+/// Stone up to y=20, three layers of dirt, grass, then air,
+/// with a few ores scattered through the stone.
+fn terrain() -> Box<SubChunk> {
+    let mut subchunk = Box::new(SubChunk::new(IVec3::ZERO));
+    for z in 0..W {
+        for x in 0..W {
+            for y in 0..W {
+                let id = match y {
+                    _ if y < 20 && (x * 7 + y * 13 + z * 5) % 61 == 0 => 4,
+                    0..20 => 1,
+                    20..23 => 2,
+                    23 => 3,
+                    _ => 0,
+                };
+                subchunk.set_block(IVec3::new(x, y, z), state(id));
+            }
+        }
+    }
+    subchunk
+}
+
+/// Positions in a fixed pseudo-random order.
+fn positions() -> Vec<IVec3> {
+    let mut n = 0x2545_F491u32;
+    (0..4096)
+        .map(|_| {
+            n ^= n << 13;
+            n ^= n >> 17;
+            n ^= n << 5;
+            IVec3::new((n % 32) as i32, ((n >> 8) % 32) as i32, ((n >> 16) % 32) as i32)
+        })
+        .collect()
+}
+
+#[bench]
+fn flat_random_get(b: &mut Bencher) {
+    let subchunk = terrain();
+    let positions = positions();
+    b.iter(|| {
+        for pos in &positions {
+            black_box(subchunk.get_block(*pos));
+        }
+    });
+}
+
+#[bench]
+fn paletted_random_get(b: &mut Bencher) {
+    let subchunk = terrain().compress();
+    let positions = positions();
+    b.iter(|| {
+        for pos in &positions {
+            black_box(subchunk.get_block(*pos));
+        }
+    });
+}
+
+#[bench]
+fn flat_scan(b: &mut Bencher) {
+    let subchunk = terrain();
+    b.iter(|| black_box(subchunk.ids().iter().filter(|id| id.index() == 4).count()));
+}
+
+#[bench]
+fn paletted_scan(b: &mut Bencher) {
+    let subchunk = terrain().compress();
+    b.iter(|| black_box(subchunk.ids().iter().filter(|id| id.index() == 4).count()));
+}
+
+#[bench]
+fn paletted_decode(b: &mut Bencher) {
+    let compressed = terrain().compress();
+    let mut subchunk = Box::new(SubChunk::new(IVec3::ZERO));
+    b.iter(|| {
+        compressed.decompress_into(&mut subchunk);
+        black_box(subchunk.ids()[0]);
+    });
+}
+
+#[bench]
+fn compress(b: &mut Bencher) {
+    let subchunk = terrain();
+    b.iter(|| black_box(subchunk.compress()));
+}
+
+#[bench]
+fn flat_set(b: &mut Bencher) {
+    let mut subchunk = terrain();
+    let positions = positions();
+    b.iter(|| {
+        for pos in &positions {
+            subchunk.set_block(*pos, state(5));
+        }
+    });
+}
+
+#[bench]
+fn paletted_set(b: &mut Bencher) {
+    let mut subchunk = terrain().compress();
+    let positions = positions();
+    b.iter(|| {
+        for pos in &positions {
+            subchunk.set_block(*pos, state(5));
+        }
+    });
+}
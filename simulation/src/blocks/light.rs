@@ -1,5 +1,7 @@
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+use bevy::color::{Color, Hsla};
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Light(u16);
 
 impl Light {
@@ -28,6 +30,19 @@ impl Light {
         )
     }
 
+    /// Construct a Light from its packed representation,
+    /// see `Light::to_bits`.
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    /// Get the packed representation of the light, where
+    /// each of the 4 values is a nibble, from ambient in
+    /// the lowest bits to lightness in the highest.
+    pub const fn to_bits(self) -> u16 {
+        self.0
+    }
+
     /// Get the ambient light level.
     pub fn ambient(&self) -> u8 {
         (self.0 & 0xF) as u8
@@ -71,4 +86,23 @@ impl Light {
         assert!(lightness <= 15, "Lightness must be in range [0, 15]");
         self.0 = (self.0 & !(0xF << 12)) | ((lightness as u16) << 12);
     }
+
+    /// Construct the light emitted by a block with this color, with no
+    /// ambient light. Saturation isn't stored, so colors are treated
+    /// as fully saturated, and colors with no saturation are white.
+    pub fn from_color(color: Color, intensity: u8) -> Self {
+        let hsla = Hsla::from(color);
+        let (hue, lightness) = if hsla.saturation <= f32::EPSILON {
+            (0, 15)
+        } else {
+            let hue = (hsla.hue.rem_euclid(360.0) / 22.5).round() as u8 % 16;
+            (hue, (hsla.lightness.clamp(0.0, 1.0) * 15.0).round() as u8)
+        };
+        Self::from_raw(0, intensity, hue, lightness)
+    }
+
+    /// Get the color of the light, from the hue and lightness.
+    pub fn color(&self) -> Color {
+        Color::hsl(self.hue() as f32 * 22.5, 1.0, self.lightness() as f32 / 15.0)
+    }
 }
@@ -1,7 +1,7 @@
 
 // imports 
-use crate::{data::TagSet, math::collider::BoundingBox};
-use bevy::{color::Color, math::{bounding::Aabb3d, Vec3A}};
+use crate::data::TagSet;
+use bevy::{color::Color, math::bounding::Aabb3d};
 use bevy::math::Vec3;
 
 // exports
@@ -15,7 +15,7 @@ pub use face::Transparency;
 mod light;
 mod state;
 mod face;
-mod tag;
+pub mod tag;
 mod collider;
 
 pub struct Block {
@@ -1,7 +1,7 @@
 use crate::data::registry::LocalID;
 use super::Light;
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct BlockState {
     pub block: LocalID,
     pub light: Light, 
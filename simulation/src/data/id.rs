@@ -1,14 +1,13 @@
 use std::collections::BTreeMap;
 use xxhash_rust::const_xxh32::xxh32;
 use std::sync::Mutex;
-use std::hash::Hash;
 
 /// I don't want to have to store an owned String for every single id, so
 /// I'm just going to store them in this static map. 
 static ID_STORAGE: Mutex<BTreeMap<u32, String>> = Mutex::new(BTreeMap::new());
 const ID_HASH_SEED: u32 = 0xDCA3875F;
 
-#[derive(Copy, Clone, Eq, Ord, Debug)]
+#[derive(Copy, Clone, Eq, Debug)]
 pub struct Id {
     name: &'static str,
     id: u32,
@@ -56,7 +55,7 @@ impl std::ops::Deref for Id {
     type Target = str;
 
     fn deref(&self) -> &Self::Target {
-        &self.name
+        self.name
     }
 }
 
@@ -68,6 +67,12 @@ impl PartialEq for Id {
 
 impl PartialOrd for Id {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.id.partial_cmp(&other.id)
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Id {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.id.cmp(&other.id)
     }
 }
@@ -8,6 +8,12 @@ impl<K, V> SortedMap<K, V> {
     }
 }
 
+impl<K, V> Default for SortedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K, V> SortedMap<K, V>
 where
     K: Ord
@@ -29,14 +35,14 @@ where
     pub fn get(&self, key: &K) -> Option<&V> {
         match self.0.binary_search_by(|(k, _)| k.cmp(key)) {
             Ok(index) => self.0.get(index).map(|(_, v)| v),
-            Err(_) => return None,
+            Err(_) => None,
         }
     }
 
     pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
         match self.0.binary_search_by(|(k, _)| k.cmp(key)) {
             Ok(index) => self.0.get_mut(index).map(|(_, v)| v),
-            Err(_) => return None,
+            Err(_) => None,
         }
     }
 
@@ -60,6 +66,12 @@ where
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct SortedSet<K>(SortedMap<K, ()>);
 
+impl<K: Ord> Default for SortedSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K: Ord> SortedSet<K> {
     pub const fn new() -> Self {
         Self(SortedMap::new())
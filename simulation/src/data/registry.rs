@@ -34,9 +34,28 @@ impl<I: 'static> Registry<I> {
         &self.entries[local.0 as usize]
     }
 
+    /// Get an entry by LocalID, returning None if no entry has the LocalID.
+    pub fn try_get_by_local(&self, local: LocalID) -> Option<&Entry<I>> {
+        self.entries.get(local.0 as usize)
+    }
+
     pub fn get_by_global(&self, global: GlobalID) -> Option<&Entry<I>> {
         self.map.get(&global).map(|index| &self.entries[*index as usize])
     }
+
+    /// Iterate over the entries in order of their LocalID.
+    pub fn iter(&self) -> std::slice::Iter<'_, Entry<I>> {
+        self.entries.iter()
+    }
+
+    /// The number of entries in the registry.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 }
 
 pub struct Entry<I> {
@@ -96,6 +115,12 @@ impl GlobalID {
     pub const fn new(name: &'static str) -> Self {
         Self(Id::new(name).id())
     }
+
+    /// Construct a GlobalID from a hash
+    /// that was read from a save.
+    pub const fn from_hash(hash: u32) -> Self {
+        Self(hash)
+    }
     
     pub fn hash(&self) -> u32 {
         self.0
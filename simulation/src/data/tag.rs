@@ -4,6 +4,12 @@ use super::{Id, SortedSet};
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct TagSet(SortedSet<Tag>);
 
+impl Default for TagSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TagSet {
     pub fn new() -> Self {
         Self(SortedSet::new())
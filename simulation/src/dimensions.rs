@@ -1,7 +1,18 @@
 
+use std::collections::HashSet;
+
 use bevy::prelude::*;
-use bevy::app::AppLabel;
-use crate::world::World;
+use bevy::app::{AppLabel, InternedAppLabel, SubApp};
+use bevy::ecs::schedule::ScheduleLabel;
+use crate::blocks::Block;
+use crate::data::Registry;
+use crate::events::{apply_block_events, apply_entity_events, record_block_changes, record_chunks, ClientEvents, ServerEvents};
+use crate::BevyEcs;
+use crate::terrain::{advance_generation, Biome, GenerationScheduler, GeneratorSource, NetherBlocks, NetherGenerator};
+use crate::world::{clear_chunk_changes, LightTable, World};
+use crate::world::lighting::{update_light, LightQueue};
+use crate::world::stream::{stream_chunks, ChunkStreamer};
+use crate::tick::{run_tick, tick_schedules, BlockUpdates, Entities, PostTick, PreTick, TickClock, TickCounter};
 
 /// Systems, Resources, and Events that
 /// need to exist on all SubApps. 
@@ -9,13 +20,189 @@ pub struct DimensionPlugin;
 
 impl Plugin for DimensionPlugin {
     fn build(&self, app: &mut App) {
+        for label in tick_schedules() {
+            app.init_schedule(label);
+        }
+
         app
             // every dimension has its own world.
             .init_resource::<World>()
+            .init_resource::<DimensionSettings>()
+            .init_resource::<TickCounter>()
+
+            // events go in through a queue, and come out through a stream.
+            .init_resource::<ClientEvents>()
+            .init_resource::<ServerEvents>()
+
+            // stream chunks around players, if the dimension has a streamer.
+            .add_systems(PreTick, stream_chunks.run_if(resource_exists::<ChunkStreamer>))
+
+            // decorate and light generated chunks once their neighbours are ready.
+            .add_systems(PreTick, advance_generation.after(stream_chunks).run_if(resource_exists::<GenerationScheduler>))
+
+            // apply the events of clients, blocks first. Blocks are named by GlobalID, so they need the block registry.
+            .add_systems(BlockUpdates, apply_block_events.run_if(resource_exists::<Registry<Block>>))
+            .add_systems(Entities, apply_entity_events)
+
+            // light chunks and changed blocks once everything else in the tick is done.
+            .add_systems(PostTick, update_light.run_if(resource_exists::<LightQueue>))
+
+            // send the blocks that changed this tick, which queues them to be relit, and the chunks that were lit.
+            // chunks name their biomes by GlobalID too, so sending them also needs the biome registry.
+            .add_systems(PostTick, record_block_changes.before(update_light).run_if(resource_exists::<Registry<Block>>))
+            .add_systems(PostTick, record_chunks.after(update_light).run_if(resource_exists::<Registry<Block>>).run_if(resource_exists::<Registry<Biome>>))
+
+            // forget the chunks that were inserted and removed, whether or not anything read them.
+            .add_systems(PostTick, clear_chunk_changes.after(update_light).after(record_chunks))
         ;
     }
 }
 
+/// The settings that make a dimension different from the others.
+/// Every dimension has one, inserted by the dimension's plugin.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Resource)]
+pub struct DimensionSettings {
+    /// The height of the dimension, in blocks. The dimension's
+    /// generator is built with this height.
+    pub height: i32,
+
+    /// True if the dimension has a sky that lights it,
+    /// see `light_queue`.
+    pub has_skylight: bool,
+}
+
+impl DimensionSettings {
+    pub const fn overworld() -> Self {
+        Self {
+            height: 256,
+            has_skylight: true,
+        }
+    }
+
+    pub const fn nether() -> Self {
+        Self {
+            height: 128,
+            has_skylight: false,
+        }
+    }
+
+    /// Build the light queue of the dimension,
+    /// without skylight if it has no sky.
+    pub fn light_queue(&self, table: LightTable) -> LightQueue {
+        let queue = LightQueue::new(table);
+        if self.has_skylight {
+            queue
+        } else {
+            queue.without_skylight()
+        }
+    }
+}
+
+impl Default for DimensionSettings {
+    fn default() -> Self {
+        Self::overworld()
+    }
+}
+
+/// The dimensions that are loaded. Only loaded dimensions are ticked,
+/// and unloading a dimension saves and drops all of its chunks. The
+/// Overworld is loaded on start, every other dimension has to be loaded.
+#[derive(Debug, Default, Resource)]
+pub struct LoadedDimensions {
+    loaded: HashSet<InternedAppLabel>,
+}
+
+impl LoadedDimensions {
+    pub fn load(&mut self, dimension: impl AppLabel) {
+        self.loaded.insert(dimension.intern());
+    }
+
+    pub fn unload(&mut self, dimension: impl AppLabel) {
+        self.loaded.remove(&dimension.intern());
+    }
+
+    pub fn is_loaded(&self, dimension: impl AppLabel) -> bool {
+        self.loaded.contains(&dimension.intern())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = InternedAppLabel> + '_ {
+        self.loaded.iter().copied()
+    }
+}
+
+/// The schedule each dimension SubApp runs every frame, which runs
+/// the tick schedules once for every tick that is due, see `run_tick`.
+#[derive(ScheduleLabel, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct RunTicks;
+
+/// Whether a dimension is loaded, and the number of ticks it has to run,
+/// extracted from the main world every frame.
+#[derive(Debug, Default, Resource)]
+pub struct DimensionState {
+    loaded: bool,
+    ticks: u32,
+}
+
+impl DimensionState {
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+}
+
+/// Create the SubApp of a dimension, with its own World and the
+/// DimensionPlugin. The SubApp is ticked at the rate of the `TickClock`
+/// while the dimension is in `LoadedDimensions`.
+pub fn new_dimension(label: impl AppLabel) -> SubApp {
+    let label = label.intern();
+    let mut dimension = SubApp::new();
+    dimension
+        .init_resource::<DimensionState>()
+        .add_systems(RunTicks, run_ticks)
+        .add_plugins(DimensionPlugin)
+        .set_extract(move |main, dimension| extract_ticks(label, main, dimension));
+    dimension.update_schedule = Some(RunTicks.intern());
+    dimension
+}
+
+fn extract_ticks(label: InternedAppLabel, main: &mut BevyEcs, dimension: &mut BevyEcs) {
+    let loaded = main
+        .get_resource::<LoadedDimensions>()
+        .is_some_and(|loaded| loaded.is_loaded(label));
+    let ticks = main.get_resource::<TickClock>().map_or(0, TickClock::due);
+
+    let was_loaded = dimension.resource::<DimensionState>().loaded;
+    if was_loaded && !loaded {
+        unload(dimension);
+    }
+
+    let mut state = dimension.resource_mut::<DimensionState>();
+    state.loaded = loaded;
+    state.ticks = if loaded { ticks } else { 0 };
+}
+
+/// Save every chunk of the dimension, and remove them from its World.
+fn unload(dimension: &mut BevyEcs) {
+    dimension.resource_scope(|dimension, mut world: Mut<World>| {
+        if let Some(mut streamer) = dimension.get_resource_mut::<ChunkStreamer>() {
+            // with no players, every chunk is unloaded and saved.
+            streamer.update(&mut world, &[]);
+            streamer.wait(&mut world);
+        }
+        if let Some(mut light) = dimension.get_resource_mut::<LightQueue>() {
+            light.clear();
+        }
+        world.clear();
+    });
+}
+
+/// Run one tick for every tick that is due.
+fn run_ticks(dimension: &mut BevyEcs) {
+    let ticks = std::mem::take(&mut dimension.resource_mut::<DimensionState>().ticks);
+    for _ in 0..ticks {
+        run_tick(dimension);
+    }
+}
+
 #[derive(AppLabel, Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Overworld;
 
@@ -23,10 +210,11 @@ pub struct Overworld;
 /// that belong to the overworld exclusively.
 impl Plugin for Overworld {
     fn build(&self, app: &mut App) {
-        app.sub_app_mut(Overworld)
-            // global plugins every dimension has.
-            .add_plugins(DimensionPlugin)
+        let mut dimension = new_dimension(Overworld);
+        dimension
+            .insert_resource(DimensionSettings::overworld())
         ;
+        app.insert_sub_app(Overworld, dimension);
     }
 }
 
@@ -37,9 +225,32 @@ pub struct Nether;
 /// that belong to the nether exclusively.
 impl Plugin for Nether {
     fn build(&self, app: &mut App) {
-        app.sub_app_mut(Nether)
-            // global plugins every dimension has.
-            .add_plugins(DimensionPlugin)
+        let mut dimension = new_dimension(Nether);
+        dimension
+            // the nether is shorter than the overworld, and has no sky.
+            .insert_resource(DimensionSettings::nether())
+        ;
+        app.insert_sub_app(Nether, dimension);
+    }
+}
+
+impl Nether {
+    /// Generate the nether around players with a `ChunkLoader`, within the
+    /// view distance, and light it. The generator and the light queue are
+    /// built from the dimension's `DimensionSettings`. Returns false, and
+    /// installs nothing, if the nether blocks aren't registered.
+    pub fn install_terrain(dimension: &mut SubApp, seed: u64, blocks: &Registry<Block>, view_distance: i32) -> bool {
+        let Some(nether_blocks) = NetherBlocks::from_registry(blocks) else {
+            return false;
+        };
+
+        let settings = *dimension.world().resource::<DimensionSettings>();
+        let generator = NetherGenerator::new(seed, nether_blocks, settings.height);
+        dimension
+            .insert_resource(ChunkStreamer::new(GeneratorSource(generator), view_distance))
+            .insert_resource(GenerationScheduler::new())
+            .insert_resource(settings.light_queue(LightTable::from_registry(blocks)))
         ;
+        true
     }
 }
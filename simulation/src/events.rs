@@ -0,0 +1,445 @@
+//! The events that go in and out of the Simulation.
+//!
+//! The Server hands every dimension the `ClientEvent`s of the clients in
+//! it through the `ClientEvents` queue, and the dimension applies all of
+//! them on its next tick: block events in `BlockUpdates`, and the rest in
+//! `Entities`. Every mutation the tick makes is packaged into a
+//! `ServerEvent` in the `ServerEvents` stream, which the Server drains and
+//! sends to the clients near each event. Block changes are taken from the
+//! World's change log, so blocks changed by any system are sent, and
+//! chunks are sent once they are lit.
+//!
+//! Blocks are named by GlobalID, since LocalIDs must not be sent over
+//! the network, so both directions need the `Registry<Block>` resource.
+//! Chunks name their biomes the same way, so sending chunks also needs
+//! the `Registry<Biome>` resource.
+
+use bevy::prelude::*;
+
+use crate::blocks::{Block, BlockState, Light};
+use crate::data::registry::{GlobalID, LocalID};
+use crate::data::Registry;
+use crate::terrain::Biome;
+use crate::world::codec::encode_chunk;
+use crate::world::lighting::LightQueue;
+use crate::world::stream::ChunkLoader;
+use crate::world::{ChunkOrigin, ChunkStatus, World, WorldPos3};
+
+/// Identifies a connected client.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClientId(pub u64);
+
+/// Something a client asked for, which the Simulation applies on its next tick.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientEvent {
+    /// The client joined the dimension, and needs a player.
+    /// Players join at the origin, until they move.
+    Join { name: String },
+
+    /// The client left the dimension, so its player is removed.
+    Leave,
+
+    /// The client's player moved to this position.
+    Move { position: Vec3 },
+
+    /// Place a block, which only replaces air.
+    PlaceBlock { pos: WorldPos3, block: GlobalID },
+
+    /// Break a block, replacing it with air.
+    BreakBlock { pos: WorldPos3 },
+
+    /// Send a chat message to the other clients.
+    Chat { message: String },
+}
+
+/// A mutation of the Simulation that clients need to know about.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerEvent {
+    /// A chunk was loaded and lit, encoded with `encode_chunk`.
+    ChunkData { origin: ChunkOrigin, data: Vec<u8> },
+
+    /// The block at this position changed.
+    BlockChange { pos: WorldPos3, block: GlobalID },
+
+    /// An entity was spawned. Players have the client that controls them.
+    EntitySpawn {
+        entity: Entity,
+        client: Option<ClientId>,
+        position: Vec3,
+    },
+
+    /// An entity moved to this position.
+    EntityMove { entity: Entity, position: Vec3 },
+
+    /// An entity was removed.
+    EntityDespawn { entity: Entity },
+
+    /// A client sent a chat message.
+    Chat { from: ClientId, message: String },
+}
+
+/// The player controlled by a client.
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct Player {
+    pub client: ClientId,
+    pub name: String,
+}
+
+/// The ClientEvents waiting for the next tick of a dimension.
+#[derive(Debug, Default, Resource)]
+pub struct ClientEvents {
+    queue: Vec<(ClientId, ClientEvent)>,
+}
+
+impl ClientEvents {
+    /// Queue an event from a client, which is applied on the next tick.
+    pub fn send(&mut self, client: ClientId, event: ClientEvent) {
+        self.queue.push((client, event));
+    }
+
+    /// The events waiting for the next tick, in the order they were sent.
+    pub fn iter(&self) -> impl Iterator<Item = &(ClientId, ClientEvent)> + '_ {
+        self.queue.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Take every event, in the order they were sent.
+    pub fn drain(&mut self) -> std::vec::Drain<'_, (ClientId, ClientEvent)> {
+        self.queue.drain(..)
+    }
+}
+
+/// The ServerEvents produced by the ticks of a dimension, which
+/// should be drained by the Server after the dimension is updated.
+#[derive(Debug, Default, Resource)]
+pub struct ServerEvents {
+    events: Vec<ServerEvent>,
+}
+
+impl ServerEvents {
+    pub fn push(&mut self, event: ServerEvent) {
+        self.events.push(event);
+    }
+
+    /// The events produced since the stream was last drained, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &ServerEvent> + '_ {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Take every event, in the order they were produced.
+    pub fn drain(&mut self) -> std::vec::Drain<'_, ServerEvent> {
+        self.events.drain(..)
+    }
+}
+
+/// Place and break the blocks asked for by clients. The changes are
+/// recorded in the World, and sent by `record_block_changes`.
+pub fn apply_block_events(events: Res<ClientEvents>, blocks: Res<Registry<Block>>, mut world: ResMut<World>) {
+    const AIR: LocalID = LocalID::new(0);
+    let mut writer = world.writer();
+
+    for (client, event) in events.iter() {
+        let (pos, block) = match *event {
+            ClientEvent::PlaceBlock { pos, block } => {
+                let Some(entry) = blocks.get_by_global(block) else {
+                    log::warn!("Client {} placed an unknown block at {pos}", client.0);
+                    continue;
+                };
+                (pos, entry.local_id())
+            }
+            ClientEvent::BreakBlock { pos } => (pos, AIR),
+            _ => continue,
+        };
+
+        // blocks are only placed into air, and air can't be broken.
+        let replaced = writer.get_block(pos).map(|state| state.block);
+        if replaced.is_some_and(|replaced| (replaced == AIR) != (block == AIR)) {
+            writer.set_block(pos, BlockState { block, light: Light::ZERO });
+        }
+    }
+}
+
+/// Apply the rest of the events asked for by clients, emptying the queue.
+///
+/// Dimensions don't propagate transforms, so players are spawned
+/// and moved with both a `Transform` and a `GlobalTransform`.
+pub fn apply_entity_events(
+    mut commands: Commands,
+    mut events: ResMut<ClientEvents>,
+    mut server: ResMut<ServerEvents>,
+    mut players: Query<(Entity, &Player, &mut Transform, &mut GlobalTransform)>,
+) {
+    for (client, event) in events.drain() {
+        let player = players.iter_mut().find(|(_, player, ..)| player.client == client);
+
+        match (event, player) {
+            (ClientEvent::Join { name }, None) => {
+                let entity = commands
+                    .spawn((Player { client, name }, ChunkLoader, Transform::default(), GlobalTransform::default()))
+                    .id();
+                server.push(ServerEvent::EntitySpawn {
+                    entity,
+                    client: Some(client),
+                    position: Vec3::ZERO,
+                });
+            }
+            (ClientEvent::Leave, Some((entity, ..))) => {
+                commands.entity(entity).despawn();
+                server.push(ServerEvent::EntityDespawn { entity });
+            }
+            (ClientEvent::Move { position }, Some((entity, _, mut transform, mut global))) => {
+                transform.translation = position;
+                *global = GlobalTransform::from(*transform);
+                server.push(ServerEvent::EntityMove { entity, position });
+            }
+            (ClientEvent::Chat { message }, _) => {
+                server.push(ServerEvent::Chat { from: client, message });
+            }
+
+            // block events were applied in `BlockUpdates`.
+            (ClientEvent::PlaceBlock { .. } | ClientEvent::BreakBlock { .. }, _) => {}
+            (event, _) => log::warn!("Ignored {event:?} from client {}", client.0),
+        }
+    }
+}
+
+/// Send every block change recorded in the World this tick, and queue
+/// the changes to be relit if the dimension has a light queue. This
+/// drains the changes, so it runs before anything else that reads them.
+///
+/// Only changes in chunks that reached `ChunkStatus::Light` are sent,
+/// because clients get the other chunks whole once they are lit.
+pub fn record_block_changes(
+    mut world: ResMut<World>,
+    blocks: Res<Registry<Block>>,
+    mut server: ResMut<ServerEvents>,
+    mut light: Option<ResMut<LightQueue>>,
+) {
+    let changes: Vec<_> = world.drain_changes().collect();
+    if let Some(light) = light.as_deref_mut() {
+        light.queue_changes(&changes);
+    }
+
+    for change in changes {
+        let lit = world
+            .get_chunk(change.pos)
+            .is_some_and(|chunk| chunk.status() >= ChunkStatus::Light);
+        if !lit {
+            continue;
+        }
+
+        let Some(entry) = blocks.try_get_by_local(change.new.block) else {
+            log::warn!("Block at {} was changed to an unregistered block", change.pos);
+            continue;
+        };
+        server.push(ServerEvent::BlockChange {
+            pos: change.pos,
+            block: entry.global_id(),
+        });
+    }
+}
+
+/// Send the chunks that were lit this tick. Dimensions without a light
+/// queue send chunks as soon as they are inserted into the World.
+pub fn record_chunks(
+    mut world: ResMut<World>,
+    blocks: Res<Registry<Block>>,
+    biomes: Res<Registry<Biome>>,
+    mut server: ResMut<ServerEvents>,
+    light: Option<Res<LightQueue>>,
+) {
+    let origins: Vec<_> = match &light {
+        Some(light) => light.lit().to_vec(),
+        None => world.drain_inserted().collect(),
+    };
+
+    for origin in origins {
+        if let Some(chunk) = world.get_chunk_with_origin(origin) {
+            server.push(ServerEvent::ChunkData {
+                origin,
+                data: encode_chunk(chunk, &blocks, &biomes),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::codec::decode_chunk;
+    use crate::world::lighting::update_light;
+    use crate::world::util::{biomes_for_testing, blocks_for_testing};
+    use crate::world::{ChunkBuilder, LightTable, CHUNK_WIDTH};
+    use crate::BevyEcs;
+
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+    const CLIENT: ClientId = ClientId(7);
+    const STONE: GlobalID = GlobalID::new("stone");
+
+    /// A dimension with one chunk at the origin, which runs
+    /// the event systems in the order they run in a tick.
+    fn test_dimension(light: bool) -> (BevyEcs, Schedule) {
+        let mut dimension = BevyEcs::new();
+        let mut world = World::new();
+        let chunk = ChunkBuilder::new(world.pool_mut(), IVec2::ZERO).with_height(1).build();
+        world.insert(chunk);
+        if light {
+            let table = LightTable::from_registry(&blocks_for_testing());
+            dimension.insert_resource(LightQueue::new(table));
+        }
+        dimension.insert_resource(world);
+        dimension.insert_resource(blocks_for_testing());
+        dimension.insert_resource(biomes_for_testing());
+        dimension.init_resource::<ClientEvents>();
+        dimension.init_resource::<ServerEvents>();
+
+        let mut tick = Schedule::default();
+        tick.add_systems(
+            (
+                apply_block_events,
+                apply_entity_events,
+                record_block_changes,
+                update_light.run_if(resource_exists::<LightQueue>),
+                record_chunks,
+            )
+                .chain(),
+        );
+        (dimension, tick)
+    }
+
+    fn send(dimension: &mut BevyEcs, event: ClientEvent) {
+        dimension.resource_mut::<ClientEvents>().send(CLIENT, event);
+    }
+
+    fn drain(dimension: &mut BevyEcs) -> Vec<ServerEvent> {
+        dimension.resource_mut::<ServerEvents>().drain().collect()
+    }
+
+    #[test]
+    fn block_events_are_sent_as_changes() {
+        let (mut dimension, mut tick) = test_dimension(true);
+        tick.run(&mut dimension);
+        let events = drain(&mut dimension);
+        assert!(matches!(events[..], [ServerEvent::ChunkData { origin: IVec2::ZERO, .. }]));
+
+        let pos = IVec3::new(3, 5, 4);
+        send(&mut dimension, ClientEvent::PlaceBlock { pos, block: STONE });
+        // air can't be broken, and the block is already placed.
+        send(&mut dimension, ClientEvent::BreakBlock { pos: pos + IVec3::X });
+        send(&mut dimension, ClientEvent::PlaceBlock { pos, block: STONE });
+        // unknown blocks and unloaded chunks are ignored.
+        send(&mut dimension, ClientEvent::PlaceBlock { pos: pos + IVec3::Y, block: GlobalID::new("bedrock") });
+        send(&mut dimension, ClientEvent::PlaceBlock { pos: pos + IVec3::X * W, block: STONE });
+        send(&mut dimension, ClientEvent::BreakBlock { pos });
+        tick.run(&mut dimension);
+
+        assert!(dimension.resource::<ClientEvents>().is_empty());
+        assert_eq!(
+            vec![
+                ServerEvent::BlockChange { pos, block: STONE },
+                ServerEvent::BlockChange { pos, block: GlobalID::new("air") },
+            ],
+            drain(&mut dimension)
+        );
+    }
+
+    #[test]
+    fn chunks_are_sent_once_lit() {
+        let (mut dimension, mut tick) = test_dimension(false);
+        tick.run(&mut dimension);
+        assert_eq!(1, drain(&mut dimension).len());
+
+        // chunks that are still generating wait for the light queue.
+        let (mut dimension, mut tick) = test_dimension(true);
+        let mut world = dimension.resource_mut::<World>();
+        world.get_chunk_with_origin_mut(IVec2::ZERO).unwrap().set_status(ChunkStatus::Carvers);
+        tick.run(&mut dimension);
+        assert!(drain(&mut dimension).is_empty());
+
+        dimension.resource_mut::<LightQueue>().queue_chunk(IVec2::ZERO);
+        tick.run(&mut dimension);
+        let events = drain(&mut dimension);
+        let [ServerEvent::ChunkData { origin, ref data }] = events[..] else {
+            panic!("expected chunk data, got {events:?}");
+        };
+        assert_eq!(IVec2::ZERO, origin);
+        let decoded = decode_chunk(data, &blocks_for_testing(), &biomes_for_testing()).unwrap();
+        assert_eq!(IVec2::ZERO, decoded.origin());
+    }
+
+    #[test]
+    fn changes_are_sent_once_lit() {
+        let (mut dimension, mut tick) = test_dimension(true);
+        let mut world = dimension.resource_mut::<World>();
+        world.get_chunk_with_origin_mut(IVec2::ZERO).unwrap().set_status(ChunkStatus::Carvers);
+        tick.run(&mut dimension);
+
+        // a decorator writing into a generating chunk isn't sent.
+        let stone = BlockState { block: LocalID::new(1), light: Light::ZERO };
+        let pos = IVec3::new(3, 5, 4);
+        dimension.resource_mut::<World>().writer().set_block(pos, stone);
+        tick.run(&mut dimension);
+        assert!(drain(&mut dimension).is_empty());
+
+        // the generation scheduler lights the chunk.
+        let mut world = dimension.resource_mut::<World>();
+        world.get_chunk_with_origin_mut(IVec2::ZERO).unwrap().set_status(ChunkStatus::Light);
+        dimension.resource_mut::<LightQueue>().queue_chunk(IVec2::ZERO);
+        tick.run(&mut dimension);
+        drain(&mut dimension);
+
+        // blocks that aren't registered aren't sent either.
+        let mut world = dimension.resource_mut::<World>();
+        world.writer().set_block(pos + IVec3::Y, stone);
+        world.writer().set_block(pos, BlockState { block: LocalID::new(100), light: Light::ZERO });
+        tick.run(&mut dimension);
+        assert_eq!(vec![ServerEvent::BlockChange { pos: pos + IVec3::Y, block: STONE }], drain(&mut dimension));
+    }
+
+    #[test]
+    fn players_join_move_chat_and_leave() {
+        let (mut dimension, mut tick) = test_dimension(false);
+        tick.run(&mut dimension);
+        drain(&mut dimension);
+
+        send(&mut dimension, ClientEvent::Join { name: "steve".into() });
+        tick.run(&mut dimension);
+        let [ServerEvent::EntitySpawn { entity, client: Some(CLIENT), position: Vec3::ZERO }] = drain(&mut dimension)[..] else {
+            panic!("expected the player to spawn");
+        };
+
+        let position = Vec3::new(4.5, 20.0, -3.0);
+        send(&mut dimension, ClientEvent::Move { position });
+        send(&mut dimension, ClientEvent::Chat { message: "hi".into() });
+        tick.run(&mut dimension);
+        assert_eq!(position, dimension.get::<GlobalTransform>(entity).unwrap().translation());
+
+        send(&mut dimension, ClientEvent::Leave);
+        tick.run(&mut dimension);
+        assert!(dimension.get_entity(entity).is_err());
+        assert_eq!(
+            vec![
+                ServerEvent::EntityMove { entity, position },
+                ServerEvent::Chat { from: CLIENT, message: "hi".into() },
+                ServerEvent::EntityDespawn { entity },
+            ],
+            drain(&mut dimension)
+        );
+    }
+}
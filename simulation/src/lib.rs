@@ -1,8 +1,9 @@
 #![feature(portable_simd)]
 
-use std::collections::HashMap;
+use bevy::prelude::*;
 
-use bevy::app::SubApp;
+use dimensions::{LoadedDimensions, Nether, Overworld};
+use tick::{advance_tick_clock, TickClock, TickCounter};
 
 pub type BevyEcs = bevy::prelude::World;
 
@@ -12,5 +13,207 @@ pub mod blocks;
 pub mod data;
 pub mod terrain;
 pub mod dimensions;
+pub mod events;
+pub mod tick;
 
+/// Loads the Simulation into the Server or the Client: the global
+/// Resources, and a SubApp for every dimension. Dimensions are ticked
+/// by the `TickClock`, and only the Overworld is loaded on start.
+pub struct SimulationPlugin {
+    /// The ticks per second.
+    pub tps: u32,
 
+    /// The most ticks run in one frame when the Simulation falls behind.
+    pub max_steps: u32,
+}
+
+impl Default for SimulationPlugin {
+    fn default() -> Self {
+        Self {
+            tps: TickClock::DEFAULT_TPS,
+            max_steps: TickClock::DEFAULT_MAX_STEPS,
+        }
+    }
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let mut loaded = LoadedDimensions::default();
+        loaded.load(Overworld);
+
+        app
+            .insert_resource(TickClock::new(self.tps).with_max_steps(self.max_steps))
+            .init_resource::<TickCounter>()
+            .insert_resource(loaded)
+
+            // count the ticks that are due before the dimensions are updated.
+            .add_systems(First, advance_tick_clock)
+
+            // every dimension, loaded or not.
+            .add_plugins((Overworld, Nether))
+        ;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::app::AppLabel;
+
+    use crate::blocks::Block;
+    use crate::data::{Id, Registry};
+    use crate::dimensions::{DimensionSettings, DimensionState};
+    use crate::world::lighting::LightQueue;
+    use crate::world::stream::ChunkLoader;
+    use crate::tick::BlockUpdates;
+
+    use super::*;
+
+    #[derive(Default, Resource)]
+    struct Ticks(u32);
+
+    /// A paused Simulation, which only ticks when it is stepped,
+    /// counting the ticks of each dimension.
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(SimulationPlugin::default());
+        app.world_mut().resource_mut::<TickClock>().set_paused(true);
+        for label in [Overworld.intern(), Nether.intern()] {
+            app.sub_app_mut(label)
+                .init_resource::<Ticks>()
+                .add_systems(BlockUpdates, |mut ticks: ResMut<Ticks>| ticks.0 += 1);
+        }
+        app
+    }
+
+    fn step(app: &mut App, ticks: u32) {
+        app.world_mut().resource_mut::<TickClock>().step(ticks);
+        app.update();
+    }
+
+    fn ticks(app: &App, label: impl AppLabel) -> u32 {
+        app.sub_app(label).world().resource::<Ticks>().0
+    }
+
+    fn counter(app: &App, label: impl AppLabel) -> u64 {
+        app.sub_app(label).world().resource::<TickCounter>().get()
+    }
+
+    #[test]
+    fn dimensions_are_distinct() {
+        let app = app();
+        let settings = |label| *app.sub_app(label).world().resource::<DimensionSettings>();
+        assert_eq!(DimensionSettings::overworld(), settings(Overworld.intern()));
+        assert_eq!(DimensionSettings::nether(), settings(Nether.intern()));
+    }
+
+    #[test]
+    fn nether_is_built_from_settings() {
+        let mut blocks = Registry::new("blocks");
+        for name in ["air", "netherrack", "bedrock", "lava"] {
+            blocks.add(Id::new(name), Block::default());
+        }
+
+        let mut app = app();
+        let nether = app.sub_app_mut(Nether);
+        assert!(Nether::install_terrain(nether, 3, &blocks, 0));
+        assert!(!nether.world().resource::<LightQueue>().has_skylight());
+        nether.world_mut().spawn((ChunkLoader, GlobalTransform::default()));
+        app.world_mut().resource_mut::<LoadedDimensions>().load(Nether);
+
+        // chunks are generated on the thread pool, and inserted on a later tick.
+        let height = |app: &App| {
+            let world = app.sub_app(Nether).world().resource::<world::World>();
+            world.get_chunk_with_origin(IVec2::ZERO).map(|chunk| chunk.height())
+        };
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(60);
+        while height(&app).is_none() && std::time::Instant::now() < deadline {
+            step(&mut app, 1);
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let expected = DimensionSettings::nether().height as usize / world::CHUNK_WIDTH;
+        assert_eq!(Some(expected), height(&app));
+    }
+
+    #[test]
+    fn loaded_dimensions_tick() {
+        let mut app = app();
+        app.update();
+        assert_eq!(0, ticks(&app, Overworld));
+
+        step(&mut app, 3);
+        assert_eq!(3, ticks(&app, Overworld));
+        assert_eq!(0, ticks(&app, Nether));
+
+        app.world_mut().resource_mut::<LoadedDimensions>().load(Nether);
+        step(&mut app, 1);
+        assert_eq!(4, ticks(&app, Overworld));
+        assert_eq!(1, ticks(&app, Nether));
+
+        // each dimension counts the ticks it ran.
+        assert_eq!(4, app.world().resource::<TickCounter>().get());
+        assert_eq!(4, counter(&app, Overworld));
+        assert_eq!(1, counter(&app, Nether));
+    }
+
+    #[test]
+    fn forgets_inserted_chunks() {
+        let mut app = app();
+        app.update();
+
+        // the overworld has no light queue and no block registry to read them.
+        let mut world = app.sub_app_mut(Overworld).world_mut().resource_mut::<world::World>();
+        let chunk = world::ChunkBuilder::new(world.pool_mut(), IVec2::ZERO).build();
+        world.insert(chunk);
+        world.remove(IVec2::ZERO);
+        step(&mut app, 1);
+
+        let mut world = app.sub_app_mut(Overworld).world_mut().resource_mut::<world::World>();
+        assert_eq!(0, world.drain_inserted().count());
+        assert_eq!(0, world.drain_removed().count());
+    }
+
+    #[test]
+    fn unloading_drops_chunks() {
+        let mut app = app();
+        app.world_mut().resource_mut::<LoadedDimensions>().load(Nether);
+        app.update();
+
+        let nether = app.sub_app_mut(Nether).world_mut();
+        assert!(nether.resource::<DimensionState>().is_loaded());
+        let mut world = nether.resource_mut::<world::World>();
+        let chunk = world::ChunkBuilder::new(world.pool_mut(), IVec2::ZERO).build();
+        world.insert(chunk);
+
+        app.world_mut().resource_mut::<LoadedDimensions>().unload(Nether);
+        step(&mut app, 1);
+
+        let nether = app.sub_app(Nether).world();
+        assert!(!nether.resource::<DimensionState>().is_loaded());
+        assert_eq!(0, nether.resource::<world::World>().origins().count());
+        assert_eq!(0, ticks(&app, Nether));
+        assert_eq!(1, ticks(&app, Overworld));
+    }
+
+    #[test]
+    fn reloading_keeps_heightmap_table() {
+        let mut blocks = Registry::new("blocks");
+        blocks.add(Id::new("air"), Block::default());
+        blocks.add(Id::new("stone"), Block::default());
+        let table = world::HeightmapTable::from_registry(&blocks);
+
+        let mut app = app();
+        app.world_mut().resource_mut::<LoadedDimensions>().load(Nether);
+        app.update();
+        let mut world = app.sub_app_mut(Nether).world_mut().resource_mut::<world::World>();
+        world.set_heightmap_table(table.clone());
+
+        app.world_mut().resource_mut::<LoadedDimensions>().unload(Nether);
+        step(&mut app, 1);
+        app.world_mut().resource_mut::<LoadedDimensions>().load(Nether);
+        step(&mut app, 1);
+
+        let nether = app.sub_app(Nether).world();
+        assert!(nether.resource::<DimensionState>().is_loaded());
+        assert_eq!(&table, nether.resource::<world::World>().heightmap_table());
+    }
+}
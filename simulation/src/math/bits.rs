@@ -17,12 +17,35 @@ impl Iterator for BitIterU8 {
     }
 }
 
+/// An iterator over the indices of 1's in a 64-bit bitfield.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct BitIterU64(pub u64);
+
+impl Iterator for BitIterU64 {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            None
+        } else {
+            let tz = self.0.trailing_zeros() as usize;
+            self.0 &= self.0 - 1;
+            Some(tz)
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::BitIterU8;
+    use super::{BitIterU64, BitIterU8};
     
     #[test]
     fn bit_iter() {
         assert_eq!(vec![1, 3, 4, 6], BitIterU8(0b0101_1010).collect::<Vec<usize>>());
     }
+
+    #[test]
+    fn bit_iter_u64() {
+        assert_eq!(vec![0, 33, 63], BitIterU64(1 | 1 << 33 | 1 << 63).collect::<Vec<usize>>());
+    }
 }
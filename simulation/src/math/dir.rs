@@ -55,9 +55,9 @@ impl Dir {
     }
 }
 
-impl Into<Vec3<i32>> for Dir {
-    fn into(self) -> Vec3<i32> {
-        self.to_vec3()
+impl From<Dir> for Vec3<i32> {
+    fn from(value: Dir) -> Self {
+        value.to_vec3()
     }
 }
 
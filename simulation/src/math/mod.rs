@@ -4,8 +4,10 @@ pub mod dir;
 pub mod collider;
 pub mod ray;
 pub mod bits;
+pub mod vec3;
 
 pub use dir::Dir;
 pub use collider::BoundingBox;
 pub use ray::Ray;
+pub use vec3::Vec3;
 
@@ -0,0 +1,53 @@
+use std::ops::{Add, Sub};
+
+/// A plain 3D vector, used by colliders and rays.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Vec3<T>(pub T, pub T, pub T);
+
+impl<T: Copy> Vec3<T> {
+    pub fn splat(value: T) -> Self {
+        Self(value, value, value)
+    }
+}
+
+impl Vec3<i32> {
+    pub fn up() -> Self {
+        Self(0, 1, 0)
+    }
+
+    pub fn down() -> Self {
+        Self(0, -1, 0)
+    }
+
+    pub fn east() -> Self {
+        Self(1, 0, 0)
+    }
+
+    pub fn west() -> Self {
+        Self(-1, 0, 0)
+    }
+
+    pub fn north() -> Self {
+        Self(0, 0, 1)
+    }
+
+    pub fn south() -> Self {
+        Self(0, 0, -1)
+    }
+}
+
+impl<T: Add<Output = T>> Add for Vec3<T> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.0 + rhs.0, self.1 + rhs.1, self.2 + rhs.2)
+    }
+}
+
+impl<T: Sub<Output = T>> Sub for Vec3<T> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 - rhs.0, self.1 - rhs.1, self.2 - rhs.2)
+    }
+}
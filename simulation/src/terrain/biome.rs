@@ -0,0 +1,263 @@
+use bevy::math::Vec2;
+
+use crate::blocks::Block;
+use crate::data::registry::{GlobalID, LocalID};
+use crate::data::{Id, Registry};
+
+/// A biome decides the shape of the terrain where it is found,
+/// and the blocks that cover it. Biomes are registered in a
+/// `Registry<Biome>`, and chunks store the LocalID of the biome
+/// of each column, see `BiomeMap`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Biome {
+    /// The temperature of the climate the biome is found in, in [-1, 1].
+    pub temperature: f32,
+
+    /// The humidity of the climate the biome is found in, in [-1, 1].
+    pub humidity: f32,
+
+    /// The height that the terrain varies around.
+    pub base_height: i32,
+
+    /// How far the terrain can rise above or sink
+    /// below the base height, in blocks.
+    pub amplitude: f64,
+
+    /// The block on top of every column.
+    pub surface: Id,
+
+    /// The block below the surface block.
+    pub subsurface: Id,
+
+    /// The number of subsurface blocks below the surface block.
+    pub subsurface_depth: i32,
+
+    /// The features placed in the biome, in order, see `Feature`.
+    pub features: Vec<Id>,
+}
+
+impl Biome {
+    pub fn plains() -> Self {
+        Self {
+            temperature: 0.0,
+            humidity: 0.0,
+            base_height: 64,
+            amplitude: 10.0,
+            surface: Id::new("grass"),
+            subsurface: Id::new("dirt"),
+            subsurface_depth: 3,
+            features: ["coal_ore", "iron_ore", "oak_tree", "flowers"].map(Id::new).to_vec(),
+        }
+    }
+
+    pub fn desert() -> Self {
+        Self {
+            temperature: 0.7,
+            humidity: -0.7,
+            base_height: 66,
+            amplitude: 6.0,
+            surface: Id::new("sand"),
+            subsurface: Id::new("sand"),
+            subsurface_depth: 4,
+            features: ["coal_ore", "iron_ore"].map(Id::new).to_vec(),
+        }
+    }
+
+    pub fn mountains() -> Self {
+        Self {
+            temperature: -0.3,
+            humidity: 0.6,
+            base_height: 96,
+            amplitude: 48.0,
+            surface: Id::new("stone"),
+            subsurface: Id::new("stone"),
+            subsurface_depth: 0,
+            features: ["coal_ore", "iron_ore", "boulder"].map(Id::new).to_vec(),
+        }
+    }
+
+    pub fn tundra() -> Self {
+        Self {
+            temperature: -0.8,
+            humidity: -0.3,
+            base_height: 68,
+            amplitude: 12.0,
+            surface: Id::new("snow"),
+            subsurface: Id::new("dirt"),
+            subsurface_depth: 3,
+            features: ["coal_ore", "iron_ore"].map(Id::new).to_vec(),
+        }
+    }
+}
+
+/// Add the default biomes to a registry, starting with plains.
+pub fn register_biomes(biomes: &mut Registry<Biome>) {
+    biomes.add(Id::new("plains"), Biome::plains());
+    biomes.add(Id::new("desert"), Biome::desert());
+    biomes.add(Id::new("mountains"), Biome::mountains());
+    biomes.add(Id::new("tundra"), Biome::tundra());
+}
+
+/// The biomes of a registry, with their blocks looked up
+/// in the block registry, ready to be used by a generator.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BiomeTable {
+    /// The biomes, indexed by LocalID.
+    biomes: Vec<BiomeEntry>,
+}
+
+/// A biome with its blocks resolved to LocalIDs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BiomeEntry {
+    pub climate: Vec2,
+    pub base_height: f64,
+    pub amplitude: f64,
+    pub surface: LocalID,
+    pub subsurface: LocalID,
+    pub subsurface_depth: i32,
+}
+
+/// The biome of a column, and the shape of its terrain
+/// blended with the biomes of similar climates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BiomeBlend {
+    /// The biome whose climate is closest to the column's.
+    pub biome: LocalID,
+
+    /// The blended base height.
+    pub base_height: f64,
+
+    /// The blended amplitude.
+    pub amplitude: f64,
+}
+
+impl BiomeTable {
+    /// Build the table from the biomes in a registry. Returns None if the
+    /// registry is empty, or a block of any biome isn't registered.
+    pub fn from_registry(biomes: &Registry<Biome>, blocks: &Registry<Block>) -> Option<Self> {
+        let block = |id: Id| blocks.get_by_global(GlobalID::new(id.name())).map(|entry| entry.local_id());
+
+        let biomes = biomes
+            .iter()
+            .map(|biome| {
+                Some(BiomeEntry {
+                    climate: Vec2::new(biome.temperature, biome.humidity),
+                    base_height: biome.base_height as f64,
+                    amplitude: biome.amplitude,
+                    surface: block(biome.surface)?,
+                    subsurface: block(biome.subsurface)?,
+                    subsurface_depth: biome.subsurface_depth,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        (!biomes.is_empty()).then_some(Self { biomes })
+    }
+
+    pub fn get(&self, biome: LocalID) -> &BiomeEntry {
+        &self.biomes[biome.index() as usize]
+    }
+
+    /// The number of biomes in the table.
+    pub fn len(&self) -> usize {
+        self.biomes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.biomes.is_empty()
+    }
+
+    /// Choose the biome with the closest climate, and blend the shape of
+    /// the terrain with every biome whose climate is less than `blend`
+    /// further away. The weights fall to 0 at `blend`, so the shape
+    /// changes smoothly as the climate crosses a border between biomes.
+    pub fn blend(&self, climate: Vec2, blend: f32) -> BiomeBlend {
+        let distances: Vec<f32> = self.biomes.iter().map(|biome| biome.climate.distance(climate)).collect();
+        let (closest, nearest) = distances
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+
+        let biome = LocalID::new(closest as u16);
+        if blend <= 0.0 {
+            let closest = &self.biomes[closest];
+            return BiomeBlend {
+                biome,
+                base_height: closest.base_height,
+                amplitude: closest.amplitude,
+            };
+        }
+
+        // the closest biome always has a weight of blend^2,
+        // so the total is never 0.
+        let mut total = 0.0;
+        let mut base_height = 0.0;
+        let mut amplitude = 0.0;
+        for (biome, distance) in self.biomes.iter().zip(distances) {
+            let weight = (blend - (distance - nearest)).max(0.0) as f64;
+            let weight = weight * weight;
+            total += weight;
+            base_height += weight * biome.base_height;
+            amplitude += weight * biome.amplitude;
+        }
+
+        BiomeBlend {
+            biome,
+            base_height: base_height / total,
+            amplitude: amplitude / total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::util::{biomes_for_testing, blocks_for_testing};
+
+    use super::*;
+
+    fn table() -> BiomeTable {
+        BiomeTable::from_registry(&biomes_for_testing(), &blocks_for_testing()).unwrap()
+    }
+
+    #[test]
+    fn resolves_blocks() {
+        let table = table();
+        assert_eq!(4, table.len());
+        assert_eq!(LocalID::new(3), table.get(LocalID::new(0)).surface);
+        assert_eq!(LocalID::new(4), table.get(LocalID::new(1)).subsurface);
+
+        let mut biomes = Registry::new("biomes");
+        biomes.add(Id::new("plains"), Biome::plains());
+        biomes.add(Id::new("gravel"), Biome { surface: Id::new("gravel"), ..Biome::plains() });
+        assert_eq!(None, BiomeTable::from_registry(&biomes, &blocks_for_testing()));
+        assert_eq!(None, BiomeTable::from_registry(&Registry::new("biomes"), &blocks_for_testing()));
+    }
+
+    #[test]
+    fn blends_across_borders() {
+        let table = table();
+        let plains = Biome::plains();
+        let desert = Biome::desert();
+
+        // far from any border, the biome isn't blended.
+        let blend = table.blend(Vec2::ZERO, 0.2);
+        assert_eq!(LocalID::new(0), blend.biome);
+        assert_eq!(plains.base_height as f64, blend.base_height);
+        assert_eq!(plains.amplitude, blend.amplitude);
+
+        // halfway between plains and desert, they are blended evenly.
+        let halfway = Vec2::new(desert.temperature, desert.humidity) / 2.0;
+        let blend = table.blend(halfway, 0.2);
+        assert!((blend.amplitude - (plains.amplitude + desert.amplitude) / 2.0).abs() < 1e-9);
+
+        // and either side of the border, the shape barely changes.
+        let step = Vec2::new(0.001, -0.001);
+        let before = table.blend(halfway - step, 0.2);
+        let after = table.blend(halfway + step, 0.2);
+        assert_eq!(LocalID::new(0), before.biome);
+        assert_eq!(LocalID::new(1), after.biome);
+        assert!((before.base_height - after.base_height).abs() < 0.05);
+    }
+}
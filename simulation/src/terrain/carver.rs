@@ -0,0 +1,385 @@
+use std::f32::consts::{PI, TAU};
+use std::fmt::Debug;
+
+use bevy::math::{IVec3, Vec3};
+use libnoise::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use crate::blocks::{BlockState, Light};
+use crate::data::registry::LocalID;
+use crate::world::{Chunk, ChunkOrigin, CHUNK_WIDTH};
+
+const W: i32 = CHUNK_WIDTH as i32;
+
+const AIR: BlockState = BlockState {
+    block: LocalID::new(0),
+    light: Light::ZERO,
+};
+
+/// Something that carves air out of a chunk after its base terrain was
+/// generated. Carvings must only depend on the seed of the carver and
+/// the world position, so a carving that crosses a chunk border continues
+/// into the neighbouring chunk, no matter which chunk is generated first.
+pub trait Carver: Debug + Send + Sync + 'static {
+    /// Carve the chunk, where `surface` is the height of the terrain
+    /// in each column, indexed by x + z * CHUNK_WIDTH.
+    fn carve(&self, chunk: &mut Chunk, surface: &[i32; CHUNK_WIDTH * CHUNK_WIDTH]);
+}
+
+/// Carve out a block of a chunk, if the chunk is tall enough.
+fn carve_block(chunk: &mut Chunk, pos: IVec3) {
+    if let Some(subchunk) = chunk.get_subchunk_mut(pos.y) {
+        subchunk.set_block(pos, AIR);
+    }
+}
+
+/// The shape of the caves carved by `NoiseCaves`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CaveSettings {
+    /// The frequency of the noise of large open caves, in cycles per block.
+    pub cheese_frequency: f64,
+
+    /// The value the cheese noise has to be above for a block to be carved.
+    /// Higher values give fewer, smaller caves.
+    pub cheese_threshold: f64,
+
+    /// The frequency of the noises of long narrow tunnels, in cycles per block.
+    pub spaghetti_frequency: f64,
+
+    /// How close both spaghetti noises have to be to 0 for a block
+    /// to be carved. Higher values give wider tunnels.
+    pub spaghetti_width: f64,
+
+    /// The number of blocks below the surface that are never carved,
+    /// so the surface isn't riddled with holes.
+    pub surface_margin: i32,
+
+    /// The lowest height that can be carved.
+    pub floor: i32,
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        Self {
+            cheese_frequency: 1.0 / 64.0,
+            cheese_threshold: 0.45,
+            spaghetti_frequency: 1.0 / 48.0,
+            spaghetti_width: 0.05,
+            surface_margin: 8,
+            floor: 4,
+        }
+    }
+}
+
+/// Carves caves from 3D noise: large "cheese" caverns where one noise
+/// is high, and "spaghetti" tunnels where two noises are both close to 0.
+/// Every block is sampled at its world position, so the caves only depend
+/// on the seed and line up across chunk borders.
+#[derive(Clone, Debug)]
+pub struct NoiseCaves {
+    seed: u64,
+    settings: CaveSettings,
+}
+
+impl NoiseCaves {
+    pub fn new(seed: u64) -> Self {
+        Self::with_settings(seed, CaveSettings::default())
+    }
+
+    pub fn with_settings(seed: u64, settings: CaveSettings) -> Self {
+        Self { seed, settings }
+    }
+
+    /// Returns true if the block at this position is carved,
+    /// ignoring the surface margin and floor.
+    pub fn is_cave(&self, pos: IVec3) -> bool {
+        let (cheese, first, second) = self.noises();
+        self.sample(&cheese, &first, &second, pos)
+    }
+
+    fn noises(&self) -> (impl Generator<3>, impl Generator<3>, impl Generator<3>) {
+        let spaghetti = |seed| Source::simplex(seed).fbm(1, self.settings.spaghetti_frequency, 2.0, 0.5);
+
+        (
+            Source::simplex(self.seed).fbm(2, self.settings.cheese_frequency, 2.0, 0.5),
+            spaghetti(self.seed.wrapping_add(1)),
+            spaghetti(self.seed.wrapping_add(2)),
+        )
+    }
+
+    fn sample(
+        &self,
+        cheese: &impl Generator<3>,
+        first: &impl Generator<3>,
+        second: &impl Generator<3>,
+        pos: IVec3,
+    ) -> bool {
+        // caves are squashed vertically, so they are wider than they are tall.
+        let point = [pos.x as f64, pos.y as f64 * 2.0, pos.z as f64];
+        if cheese.sample(point) > self.settings.cheese_threshold {
+            return true;
+        }
+
+        let width = self.settings.spaghetti_width;
+        first.sample(point).abs() < width && second.sample(point).abs() < width
+    }
+}
+
+impl Carver for NoiseCaves {
+    fn carve(&self, chunk: &mut Chunk, surface: &[i32; CHUNK_WIDTH * CHUNK_WIDTH]) {
+        let (cheese, first, second) = self.noises();
+        let origin = chunk.origin();
+        let top = (chunk.height() * CHUNK_WIDTH) as i32;
+
+        for z in 0..W {
+            for x in 0..W {
+                let ceiling = (surface[(x + z * W) as usize] - self.settings.surface_margin).min(top);
+                for y in self.settings.floor.max(0)..ceiling {
+                    let pos = IVec3::new(origin.x + x, y, origin.y + z);
+                    if self.sample(&cheese, &first, &second, pos) {
+                        carve_block(chunk, pos);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The shape and frequency of the ravines carved by `Ravines`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RavineSettings {
+    /// The chance that a ravine starts in a chunk.
+    pub chance: f32,
+
+    /// The range of the number of steps of a ravine, where each step
+    /// moves one block forward.
+    pub length: (u32, u32),
+
+    /// The range of the horizontal radius of the widest part of a ravine.
+    pub radius: (f32, f32),
+
+    /// How many times taller than wide a ravine is.
+    pub stretch: f32,
+
+    /// The range of heights that a ravine starts at.
+    pub start_height: (i32, i32),
+
+    /// The lowest height that can be carved.
+    pub floor: i32,
+}
+
+impl Default for RavineSettings {
+    fn default() -> Self {
+        Self {
+            chance: 0.02,
+            length: (64, 112),
+            radius: (1.5, 3.5),
+            stretch: 3.0,
+            start_height: (20, 56),
+            floor: 4,
+        }
+    }
+}
+
+/// Carves ravines: long, narrow and deep cuts that wind through
+/// the terrain, and can open up to the surface.
+///
+/// Whether a ravine starts in a chunk, and the path it takes, is decided
+/// by a random generator seeded with the seed and the origin of the chunk
+/// it starts in. To carve a chunk, the ravines of every chunk close
+/// enough to reach it are traced, and only the blocks within the chunk
+/// are carved, so a ravine continues correctly into the chunks next to
+/// the chunk it starts in.
+#[derive(Clone, Debug)]
+pub struct Ravines {
+    seed: u64,
+    settings: RavineSettings,
+}
+
+impl Ravines {
+    pub fn new(seed: u64) -> Self {
+        Self::with_settings(seed, RavineSettings::default())
+    }
+
+    pub fn with_settings(seed: u64, settings: RavineSettings) -> Self {
+        Self { seed, settings }
+    }
+
+    /// The number of chunks away from the chunk
+    /// it starts in that a ravine can reach.
+    pub fn range(&self) -> i32 {
+        let reach = self.settings.length.1 as f32 + self.settings.radius.1 + 1.0;
+        (reach / W as f32).ceil() as i32
+    }
+
+    /// The random generator of the ravine starting in a chunk.
+    fn rng(&self, origin: ChunkOrigin) -> Pcg32 {
+        let x = (origin.x as i64 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        let z = (origin.y as i64 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+        Pcg32::seed_from_u64(self.seed ^ x ^ z.rotate_left(32))
+    }
+
+    /// Trace the ravine starting in the chunk at `start`, if there is one,
+    /// carving the blocks that are within `chunk`.
+    fn carve_from(&self, start: ChunkOrigin, chunk: &mut Chunk) {
+        let settings = &self.settings;
+        let mut rng = self.rng(start);
+        if rng.gen::<f32>() >= settings.chance {
+            return;
+        }
+
+        let mut pos = Vec3::new(
+            (start.x + rng.gen_range(0..W)) as f32,
+            rng.gen_range(settings.start_height.0..=settings.start_height.1) as f32,
+            (start.y + rng.gen_range(0..W)) as f32,
+        );
+        let mut yaw = rng.gen_range(0.0..TAU);
+        let mut pitch = rng.gen_range(-0.15..0.15f32);
+        let length = rng.gen_range(settings.length.0..=settings.length.1);
+        let width = rng.gen_range(settings.radius.0..=settings.radius.1);
+
+        for step in 0..length {
+            // the random values of every step are drawn even if the step
+            // is outside the chunk, so the path is the same for every chunk.
+            let turn = rng.gen_range(-0.1..0.1f32);
+            let tilt = rng.gen_range(-0.05..0.05f32);
+
+            // ravines are widest in the middle, and taper at the ends.
+            let radius = 1.0 + width * (PI * step as f32 / length as f32).sin();
+            self.carve_ellipsoid(chunk, pos, radius, radius * settings.stretch);
+
+            pos += Vec3::new(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos());
+            yaw += turn;
+            pitch = (pitch * 0.7 + tilt).clamp(-0.3, 0.3);
+        }
+    }
+
+    fn carve_ellipsoid(&self, chunk: &mut Chunk, center: Vec3, radius: f32, height: f32) {
+        let origin = chunk.origin();
+        let top = (chunk.height() * CHUNK_WIDTH) as i32;
+
+        let min = (center - Vec3::new(radius, height, radius)).floor().as_ivec3();
+        let max = (center + Vec3::new(radius, height, radius)).ceil().as_ivec3();
+        let min = min.max(IVec3::new(origin.x, self.settings.floor.max(0), origin.y));
+        let max = max.min(IVec3::new(origin.x + W - 1, top - 1, origin.y + W - 1));
+
+        for z in min.z..=max.z {
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    let pos = IVec3::new(x, y, z);
+                    let offset = (pos.as_vec3() + 0.5 - center) / Vec3::new(radius, height, radius);
+                    if offset.length_squared() < 1.0 {
+                        carve_block(chunk, pos);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Carver for Ravines {
+    fn carve(&self, chunk: &mut Chunk, _surface: &[i32; CHUNK_WIDTH * CHUNK_WIDTH]) {
+        let origin = chunk.origin();
+        let range = self.range();
+        for z in -range..=range {
+            for x in -range..=range {
+                self.carve_from(origin + ChunkOrigin::new(x, z) * W, chunk);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::IVec2;
+
+    use crate::world::{ChunkBuilder, SubChunkPool};
+
+    use super::*;
+
+    const STONE: BlockState = BlockState {
+        block: LocalID::new(1),
+        light: Light::ZERO,
+    };
+
+    /// A chunk of stone, 4 subchunks high.
+    fn stone(origin: ChunkOrigin) -> Chunk {
+        let mut pool = SubChunkPool::new(0);
+        let mut chunk = ChunkBuilder::new(&mut pool, origin).with_height(4).build();
+        for y in 0..4 * W {
+            chunk.get_subchunk_mut(y).unwrap().ids_mut().fill(STONE.block);
+        }
+        chunk
+    }
+
+    fn is_air(chunk: &Chunk, pos: IVec3) -> bool {
+        chunk.get_block(pos).unwrap().block == AIR.block
+    }
+
+    #[test]
+    fn noise_caves_stay_below_surface() {
+        let caves = NoiseCaves::new(5);
+        let surface = [96; CHUNK_WIDTH * CHUNK_WIDTH];
+        let mut carved = 0;
+
+        for origin in [IVec2::ZERO, IVec2::new(W, 0), IVec2::new(-3 * W, 5 * W)] {
+            let mut chunk = stone(origin);
+            caves.carve(&mut chunk, &surface);
+
+            for z in 0..W {
+                for x in 0..W {
+                    for y in 0..4 * W {
+                        let pos = IVec3::new(origin.x + x, y, origin.y + z);
+                        let inside = (4..96 - 8).contains(&y);
+                        assert_eq!(inside && caves.is_cave(pos), is_air(&chunk, pos));
+                        carved += is_air(&chunk, pos) as u32;
+                    }
+                }
+            }
+        }
+        assert!(carved > 0);
+    }
+
+    #[test]
+    fn ravines_continue_into_neighbours() {
+        let settings = RavineSettings {
+            chance: 1.0,
+            ..RavineSettings::default()
+        };
+        let ravines = Ravines::with_settings(11, settings);
+        let surface = [128; CHUNK_WIDTH * CHUNK_WIDTH];
+        let carve = |origin| {
+            let mut chunk = stone(origin);
+            ravines.carve(&mut chunk, &surface);
+            chunk
+        };
+
+        // wherever air touches the border of two chunks that were carved
+        // separately, it is part of the same ravine, so the blocks on
+        // both sides of the border are carved together somewhere.
+        let left = carve(IVec2::ZERO);
+        let right = carve(IVec2::new(W, 0));
+        let joined = (0..W)
+            .flat_map(|z| (0..4 * W).map(move |y| (y, z)))
+            .filter(|(y, z)| is_air(&left, IVec3::new(W - 1, *y, *z)) && is_air(&right, IVec3::new(W, *y, *z)))
+            .count();
+        assert!(joined > 0);
+
+        // carving a chunk again gives the same result.
+        let again = carve(IVec2::ZERO);
+        for y in 0..4 * W {
+            assert_eq!(left.get_subchunk(y).unwrap().ids(), again.get_subchunk(y).unwrap().ids());
+        }
+    }
+
+    #[test]
+    fn ravines_are_rare() {
+        let ravines = Ravines::new(3);
+        let starts = (0..64)
+            .flat_map(|z| (0..64).map(move |x| IVec2::new(x, z) * W))
+            .filter(|origin| ravines.rng(*origin).gen::<f32>() < ravines.settings.chance)
+            .count();
+        assert!(starts > 0 && starts < 64 * 64 / 10);
+    }
+}
@@ -0,0 +1,327 @@
+use bevy::math::{IVec2, IVec3, Vec3Swizzles};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use crate::blocks::{BlockState, Light};
+use crate::data::registry::LocalID;
+use crate::world::{ChunkOrigin, ClusterMut3x3, HeightmapKind, World, CHUNK_WIDTH};
+
+use super::{Feature, FeatureEntry, FeatureKind, FeatureTable, Placement};
+
+const W: i32 = CHUNK_WIDTH as i32;
+
+const AIR: LocalID = LocalID::new(0);
+
+/// Places the features of each chunk's biome, after its terrain was
+/// generated and carved.
+///
+/// The features of a chunk start within the chunk, but can reach up to
+/// a chunk into its neighbours, so a chunk is decorated through the
+/// 3x3 cluster around it, once all 8 of its neighbours have generated.
+/// Each feature is placed with a `Pcg32` seeded with the seed, the
+/// origin of the chunk and the feature, so the features of a chunk
+/// are always the same, and adding a feature doesn't move the others.
+#[derive(Clone, Debug)]
+pub struct Decorator {
+    seed: u64,
+    features: FeatureTable,
+}
+
+impl Decorator {
+    pub fn new(seed: u64, features: FeatureTable) -> Self {
+        Self { seed, features }
+    }
+
+    pub fn features(&self) -> &FeatureTable {
+        &self.features
+    }
+
+    /// Decorate the chunk with this origin. Returns false, without placing
+    /// anything, if the chunk or any of its neighbours isn't in the world.
+    pub fn decorate(&self, world: &mut World, origin: ChunkOrigin) -> bool {
+        match world.cluster_mut_3x3(IVec3::new(origin.x, 0, origin.y)) {
+            Some(mut cluster) => {
+                self.decorate_cluster(&mut cluster);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Decorate the chunk at the center of the cluster, with
+    /// the features of the biome at the center of the chunk.
+    pub fn decorate_cluster(&self, cluster: &mut ClusterMut3x3) {
+        let origin = cluster.origin() + IVec2::splat(W);
+        let center = IVec3::new(origin.x + W / 2, 0, origin.y + W / 2);
+        let Some(biome) = cluster.get_chunk(center).map(|chunk| chunk.biome_at(center.xz())) else {
+            return;
+        };
+
+        for entry in self.features.features_of(biome) {
+            let mut rng = self.rng(origin, entry);
+            for _ in 0..entry.feature.count {
+                let column = origin + IVec2::new(rng.gen_range(0..W), rng.gen_range(0..W));
+                let placed = rng.gen::<f32>() < entry.feature.chance;
+                if let Some(pos) = position(cluster, &entry.feature, column, &mut rng).filter(|_| placed) {
+                    place_feature(cluster, &entry.feature.kind, pos, &mut rng);
+                }
+            }
+        }
+    }
+
+    /// The random generator of a feature in the chunk at this origin.
+    fn rng(&self, origin: ChunkOrigin, entry: &FeatureEntry) -> Pcg32 {
+        let x = (origin.x as i64 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        let z = (origin.y as i64 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+        let feature = (entry.id.hash() as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+        Pcg32::seed_from_u64(self.seed ^ x ^ z.rotate_left(32) ^ feature)
+    }
+}
+
+/// Choose the position of a try of a feature in a column.
+fn position(cluster: &ClusterMut3x3, feature: &Feature<LocalID>, column: IVec2, rng: &mut Pcg32) -> Option<IVec3> {
+    match feature.placement {
+        Placement::Height(min, max) => Some(IVec3::new(column.x, rng.gen_range(min..=max), column.y)),
+        Placement::Surface => {
+            let pos = IVec3::new(column.x, 0, column.y);
+            let height = cluster.get_chunk(pos)?.height_at(HeightmapKind::NonAir, column);
+            Some(IVec3::new(column.x, height, column.y))
+        }
+    }
+}
+
+fn set(cluster: &mut ClusterMut3x3, pos: IVec3, block: LocalID) {
+    cluster.set_block(pos, BlockState { block, light: Light::ZERO });
+}
+
+fn is(cluster: &ClusterMut3x3, pos: IVec3, block: LocalID) -> bool {
+    cluster.get_block(pos).is_some_and(|state| state.block == block)
+}
+
+/// Returns true if the block is air, or above the chunk.
+fn is_air(cluster: &ClusterMut3x3, pos: IVec3) -> bool {
+    cluster.contains(pos) && cluster.get_block(pos).is_none_or(|state| state.block == AIR)
+}
+
+/// Place a feature at a position.
+fn place_feature(cluster: &mut ClusterMut3x3, kind: &FeatureKind<LocalID>, pos: IVec3, rng: &mut Pcg32) {
+    match *kind {
+        FeatureKind::Ore { ore, replaces, size } => {
+            // a random walk from the position, replacing blocks as it goes.
+            let mut pos = pos;
+            for _ in 0..size {
+                if is(cluster, pos, replaces) {
+                    set(cluster, pos, ore);
+                }
+                pos += match rng.gen_range(0..6) {
+                    0 => IVec3::X,
+                    1 => IVec3::NEG_X,
+                    2 => IVec3::Y,
+                    3 => IVec3::NEG_Y,
+                    4 => IVec3::Z,
+                    _ => IVec3::NEG_Z,
+                };
+            }
+        }
+        FeatureKind::Tree { log, leaves, soil, height } => {
+            let height = rng.gen_range(height.0..=height.1);
+            if !is(cluster, pos - IVec3::Y, soil) || !(0..height + 1).all(|y| is_air(cluster, pos + IVec3::Y * y)) {
+                return;
+            }
+
+            // the canopy is two layers 2 blocks wide around the top
+            // of the trunk, then two layers 1 block wide above it.
+            let top = pos.y + height;
+            for y in top - 2..top + 2 {
+                let radius = if y < top { 2 } else { 1 };
+                for z in -radius..=radius {
+                    for x in -radius..=radius {
+                        let leaf = IVec3::new(pos.x + x, y, pos.z + z);
+                        let corner = x.abs() == radius && z.abs() == radius;
+                        if is_air(cluster, leaf) && !(corner && rng.gen_bool(0.5)) {
+                            set(cluster, leaf, leaves);
+                        }
+                    }
+                }
+            }
+            for y in 0..height {
+                set(cluster, pos + IVec3::Y * y, log);
+            }
+        }
+        FeatureKind::Boulder { block, radius } => {
+            let radius = rng.gen_range(radius.0..=radius.1);
+            let reach = radius.ceil() as i32;
+            for z in -reach..=reach {
+                for x in -reach..=reach {
+                    for y in -reach..=reach {
+                        let offset = IVec3::new(x, y, z);
+                        // roughen the surface of the ball.
+                        let rough = radius - rng.gen_range(0.0..0.75);
+                        if offset.as_vec3().length() <= rough {
+                            set(cluster, pos + offset, block);
+                        }
+                    }
+                }
+            }
+        }
+        FeatureKind::Plant { block, soil } => {
+            if is(cluster, pos - IVec3::Y, soil) && is_air(cluster, pos) {
+                set(cluster, pos, block);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::{Id, Registry};
+    use crate::terrain::register_features;
+    use crate::world::util::{biomes_for_testing, blocks_for_testing};
+    use crate::world::{Chunk, ChunkBuilder, HeightmapTable};
+
+    use super::*;
+
+    const STONE: LocalID = LocalID::new(1);
+    const DIRT: LocalID = LocalID::new(2);
+    const GRASS: LocalID = LocalID::new(3);
+    const COAL: LocalID = LocalID::new(6);
+    const LOG: LocalID = LocalID::new(8);
+    const LEAVES: LocalID = LocalID::new(9);
+    const COBBLESTONE: LocalID = LocalID::new(10);
+    const FLOWER: LocalID = LocalID::new(11);
+
+    fn decorator(seed: u64, features: Registry<Feature>) -> Decorator {
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        Decorator::new(seed, FeatureTable::from_registry(&features, &biomes, &blocks).unwrap())
+    }
+
+    fn default_decorator(seed: u64) -> Decorator {
+        let mut features = Registry::new("features");
+        register_features(&mut features);
+        decorator(seed, features)
+    }
+
+    /// A 3x3 world of plains, with stone up to 59, then dirt,
+    /// then grass at 62, and one subchunk of air above.
+    fn plains() -> World {
+        let mut world = World::new();
+        world.set_heightmap_table(HeightmapTable::from_registry(&blocks_for_testing()));
+
+        for z in -1..2 {
+            for x in -1..2 {
+                let mut chunk = ChunkBuilder::new(world.pool_mut(), IVec2::new(x, z) * W).with_height(3).build();
+                chunk.get_subchunk_mut(0).unwrap().ids_mut().fill(STONE);
+                for (i, id) in chunk.get_subchunk_mut(W).unwrap().ids_mut().iter_mut().enumerate() {
+                    *id = match i % CHUNK_WIDTH {
+                        0..27 => STONE,
+                        27..30 => DIRT,
+                        30 => GRASS,
+                        _ => AIR,
+                    };
+                }
+                world.insert(chunk);
+            }
+        }
+
+        world
+    }
+
+    /// Every block of the chunk with this origin.
+    fn blocks_of(world: &World, origin: ChunkOrigin) -> Vec<LocalID> {
+        let chunk: &Chunk = world.get_chunk_with_origin(origin).unwrap();
+        (0..chunk.height() as i32)
+            .flat_map(|i| chunk.get_subchunk(i * W).unwrap().ids().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn places_features_of_biome() {
+        let mut world = plains();
+        assert!(default_decorator(8).decorate(&mut world, IVec2::ZERO));
+
+        let blocks = blocks_of(&world, IVec2::ZERO);
+        let count = |id| blocks.iter().filter(|block| **block == id).count();
+        assert!(count(COAL) > 0);
+        assert!(count(LOG) > 0);
+        assert!(count(LEAVES) > 0);
+        assert!(count(FLOWER) > 0);
+
+        // plains don't have boulders.
+        assert_eq!(0, count(COBBLESTONE));
+
+        // flowers and trees only grow on grass.
+        let reader = world.reader();
+        for z in 0..W {
+            for x in 0..W {
+                let pos = IVec3::new(x, 63, z);
+                let block = reader.get_block(pos).unwrap().block;
+                if block == FLOWER || block == LOG {
+                    assert_eq!(GRASS, reader.get_block(pos - IVec3::Y).unwrap().block);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decoration_is_deterministic() {
+        let mut first = plains();
+        let mut second = plains();
+        default_decorator(3).decorate(&mut first, IVec2::ZERO);
+        default_decorator(3).decorate(&mut second, IVec2::ZERO);
+        for z in -1..2 {
+            for x in -1..2 {
+                let origin = IVec2::new(x, z) * W;
+                assert_eq!(blocks_of(&first, origin), blocks_of(&second, origin));
+            }
+        }
+
+        let mut other = plains();
+        default_decorator(4).decorate(&mut other, IVec2::ZERO);
+        assert_ne!(blocks_of(&first, IVec2::ZERO), blocks_of(&other, IVec2::ZERO));
+    }
+
+    #[test]
+    fn writes_into_neighbours() {
+        // plains place flowers, so make them big boulders,
+        // and every other feature of the biomes do nothing.
+        let mut features = Registry::new("features");
+        features.add(
+            Id::new("flowers"),
+            Feature {
+                count: 64,
+                chance: 1.0,
+                placement: Placement::Surface,
+                kind: FeatureKind::Boulder {
+                    block: Id::new("cobblestone"),
+                    radius: (3.0, 3.0),
+                },
+            },
+        );
+        for name in ["coal_ore", "iron_ore", "oak_tree", "boulder"] {
+            features.add(
+                Id::new(name),
+                Feature {
+                    count: 0,
+                    chance: 0.0,
+                    placement: Placement::Surface,
+                    kind: FeatureKind::Plant {
+                        block: Id::new("flower"),
+                        soil: Id::new("grass"),
+                    },
+                },
+            );
+        }
+
+        let mut world = plains();
+        let decorator = decorator(1, features);
+        assert!(decorator.decorate(&mut world, IVec2::ZERO));
+        let neighbours = [IVec2::new(-W, 0), IVec2::new(W, 0), IVec2::new(0, -W), IVec2::new(0, W)]
+            .iter()
+            .filter(|origin| blocks_of(&world, **origin).contains(&COBBLESTONE))
+            .count();
+        assert!(neighbours > 0);
+
+        // a chunk on the edge of the world can't be decorated.
+        assert!(!decorator.decorate(&mut world, IVec2::new(W, 0)));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::blocks::Block;
+use crate::data::registry::{GlobalID, LocalID};
+use crate::data::{Id, Registry};
+
+use super::Biome;
+
+/// Something placed on or in the terrain after it is generated, such as
+/// ores, trees, boulders or flowers. Features are registered in a
+/// `Registry<Feature>`, and each biome lists the features placed in it.
+///
+/// Features name their blocks by `Id`. A `FeatureTable` looks them up
+/// in the block registry, giving a `Feature<LocalID>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feature<B = Id> {
+    /// The number of times the feature is tried in each chunk.
+    pub count: u32,
+
+    /// The chance that each try is placed, in [0, 1].
+    pub chance: f32,
+
+    /// Where each try is placed.
+    pub placement: Placement,
+
+    /// What is placed.
+    pub kind: FeatureKind<B>,
+}
+
+/// Where the tries of a feature are placed, within a random column of the chunk.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// At a random height between the two heights, inclusive.
+    Height(i32, i32),
+
+    /// On the block above the highest block of the column.
+    Surface,
+}
+
+/// The shapes of features.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FeatureKind<B = Id> {
+    /// A vein of `size` ore blocks, which only replaces `replaces`.
+    Ore { ore: B, replaces: B, size: u32 },
+
+    /// A tree with a trunk of `log` and a canopy of `leaves`, which
+    /// only grows on `soil`. The trunk is between the two heights tall.
+    Tree { log: B, leaves: B, soil: B, height: (i32, i32) },
+
+    /// A rough ball of `block`, with a radius between the two radii.
+    Boulder { block: B, radius: (f32, f32) },
+
+    /// A single `block`, such as a flower, which only grows on `soil`.
+    Plant { block: B, soil: B },
+}
+
+impl<B: Copy> FeatureKind<B> {
+    /// Map the blocks of the feature, returning None if any of them can't be mapped.
+    pub fn map<T>(&self, mut f: impl FnMut(B) -> Option<T>) -> Option<FeatureKind<T>> {
+        Some(match *self {
+            Self::Ore { ore, replaces, size } => FeatureKind::Ore {
+                ore: f(ore)?,
+                replaces: f(replaces)?,
+                size,
+            },
+            Self::Tree { log, leaves, soil, height } => FeatureKind::Tree {
+                log: f(log)?,
+                leaves: f(leaves)?,
+                soil: f(soil)?,
+                height,
+            },
+            Self::Boulder { block, radius } => FeatureKind::Boulder { block: f(block)?, radius },
+            Self::Plant { block, soil } => FeatureKind::Plant {
+                block: f(block)?,
+                soil: f(soil)?,
+            },
+        })
+    }
+}
+
+/// Add the default features to a registry. Every
+/// feature listed by the default biomes is added.
+pub fn register_features(features: &mut Registry<Feature>) {
+    let ore = |ore, size, count, height| Feature {
+        count,
+        chance: 1.0,
+        placement: Placement::Height(4, height),
+        kind: FeatureKind::Ore {
+            ore: Id::new(ore),
+            replaces: Id::new("stone"),
+            size,
+        },
+    };
+
+    features.add(Id::new("coal_ore"), ore("coal_ore", 16, 20, 128));
+    features.add(Id::new("iron_ore"), ore("iron_ore", 8, 12, 64));
+    features.add(
+        Id::new("oak_tree"),
+        Feature {
+            count: 6,
+            chance: 0.5,
+            placement: Placement::Surface,
+            kind: FeatureKind::Tree {
+                log: Id::new("oak_log"),
+                leaves: Id::new("oak_leaves"),
+                soil: Id::new("grass"),
+                height: (4, 6),
+            },
+        },
+    );
+    features.add(
+        Id::new("boulder"),
+        Feature {
+            count: 1,
+            chance: 0.25,
+            placement: Placement::Surface,
+            kind: FeatureKind::Boulder {
+                block: Id::new("cobblestone"),
+                radius: (1.5, 3.0),
+            },
+        },
+    );
+    features.add(
+        Id::new("flowers"),
+        Feature {
+            count: 8,
+            chance: 1.0,
+            placement: Placement::Surface,
+            kind: FeatureKind::Plant {
+                block: Id::new("flower"),
+                soil: Id::new("grass"),
+            },
+        },
+    );
+}
+
+/// A feature with its blocks looked up in the block registry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureEntry {
+    /// The GlobalID of the feature, which seeds
+    /// the random placement of the feature.
+    pub id: GlobalID,
+
+    pub feature: Feature<LocalID>,
+}
+
+/// The features of a registry, with their blocks looked up in the
+/// block registry, and the features of each biome, ready to be used
+/// by a `Decorator`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureTable {
+    /// The features, indexed by LocalID.
+    features: Vec<FeatureEntry>,
+
+    /// The LocalIDs of the features placed in each
+    /// biome, in order, indexed by the biome's LocalID.
+    biomes: Vec<Vec<LocalID>>,
+}
+
+impl FeatureTable {
+    /// Build the table from the features and biomes in their registries.
+    /// Returns None if a block of any feature isn't registered, or any
+    /// biome lists a feature that isn't registered.
+    pub fn from_registry(
+        features: &Registry<Feature>,
+        biomes: &Registry<Biome>,
+        blocks: &Registry<Block>,
+    ) -> Option<Self> {
+        let block = |id: Id| blocks.get_by_global(GlobalID::new(id.name())).map(|entry| entry.local_id());
+
+        let entries = features
+            .iter()
+            .map(|entry| {
+                Some(FeatureEntry {
+                    id: entry.global_id(),
+                    feature: Feature {
+                        count: entry.count,
+                        chance: entry.chance,
+                        placement: entry.placement,
+                        kind: entry.kind.map(block)?,
+                    },
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let biomes = biomes
+            .iter()
+            .map(|biome| {
+                biome
+                    .features
+                    .iter()
+                    .map(|id| features.get_by_global(GlobalID::new(id.name())).map(|entry| entry.local_id()))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            features: entries,
+            biomes,
+        })
+    }
+
+    pub fn get(&self, feature: LocalID) -> &FeatureEntry {
+        &self.features[feature.index() as usize]
+    }
+
+    /// The features placed in a biome, in the order they are placed.
+    /// Biomes that aren't in the table have no features.
+    pub fn features_of(&self, biome: LocalID) -> impl Iterator<Item = &FeatureEntry> + '_ {
+        self.biomes
+            .get(biome.index() as usize)
+            .into_iter()
+            .flatten()
+            .map(|feature| self.get(*feature))
+    }
+
+    /// The number of features in the table.
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::util::{biomes_for_testing, blocks_for_testing};
+
+    use super::*;
+
+    #[test]
+    fn resolves_features_of_biomes() {
+        let blocks = blocks_for_testing();
+        let biomes = biomes_for_testing();
+        let mut features = Registry::new("features");
+        register_features(&mut features);
+
+        let table = FeatureTable::from_registry(&features, &biomes, &blocks).unwrap();
+        assert_eq!(5, table.len());
+        assert_eq!(
+            FeatureKind::Plant {
+                block: LocalID::new(11),
+                soil: LocalID::new(3),
+            },
+            table.get(LocalID::new(4)).feature.kind
+        );
+        assert_eq!(GlobalID::new("oak_tree"), table.get(LocalID::new(2)).id);
+
+        let plains: Vec<_> = table.features_of(LocalID::new(0)).map(|entry| entry.id).collect();
+        assert_eq!(
+            vec![
+                GlobalID::new("coal_ore"),
+                GlobalID::new("iron_ore"),
+                GlobalID::new("oak_tree"),
+                GlobalID::new("flowers"),
+            ],
+            plains
+        );
+        assert_eq!(0, table.features_of(LocalID::new(9)).count());
+    }
+
+    #[test]
+    fn rejects_missing_entries() {
+        let blocks = blocks_for_testing();
+        let biomes = biomes_for_testing();
+        let mut features = Registry::new("features");
+        register_features(&mut features);
+        features.add(
+            Id::new("diamond_ore"),
+            Feature {
+                count: 1,
+                chance: 1.0,
+                placement: Placement::Height(4, 16),
+                kind: FeatureKind::Ore {
+                    ore: Id::new("diamond_ore"),
+                    replaces: Id::new("stone"),
+                    size: 4,
+                },
+            },
+        );
+        assert_eq!(None, FeatureTable::from_registry(&features, &biomes, &blocks));
+
+        let mut features = Registry::new("features");
+        features.add(
+            Id::new("flowers"),
+            Feature {
+                count: 1,
+                chance: 1.0,
+                placement: Placement::Surface,
+                kind: FeatureKind::Plant {
+                    block: Id::new("flower"),
+                    soil: Id::new("grass"),
+                },
+            },
+        );
+        assert_eq!(None, FeatureTable::from_registry(&features, &biomes, &blocks));
+    }
+}
@@ -0,0 +1,99 @@
+//! Generating the terrain of chunks that have never been saved.
+//!
+//! A `ChunkGenerator` produces a whole chunk from its origin. Generators
+//! must be deterministic, so a chunk that is generated again after it was
+//! unloaded without being saved comes back the same. Generators are run on
+//! the streamer's worker threads through a `GeneratorSource`.
+
+use crate::blocks::Block;
+use crate::data::registry::{GlobalID, LocalID};
+use crate::data::Registry;
+use crate::world::stream::ChunkSource;
+use crate::world::{Chunk, ChunkOrigin, SubChunkPool};
+
+pub use biome::{register_biomes, Biome, BiomeBlend, BiomeEntry, BiomeTable};
+pub use carver::{CaveSettings, Carver, NoiseCaves, RavineSettings, Ravines};
+pub use decorator::Decorator;
+pub use feature::{register_features, Feature, FeatureEntry, FeatureKind, FeatureTable, Placement};
+pub use nether::{NetherBlocks, NetherGenerator, NetherSettings};
+pub use overworld::{OverworldGenerator, OverworldSettings};
+pub use scheduler::{advance_generation, GenerationScheduler};
+
+mod biome;
+mod carver;
+mod decorator;
+mod feature;
+mod nether;
+mod overworld;
+mod scheduler;
+
+/// Something that generates the terrain of chunks.
+pub trait ChunkGenerator: Send + Sync + 'static {
+    /// Generate the chunk with this origin, taking subchunks from the
+    /// pool, see `ChunkBuilder`. The same generator must always produce
+    /// the same chunk for the same origin. Generated chunks should have
+    /// `ChunkStatus::Carvers`, so the `GenerationScheduler` places their
+    /// features and lights them once their neighbours are ready.
+    fn generate(&self, origin: ChunkOrigin, pool: &mut SubChunkPool) -> Chunk;
+}
+
+/// A ChunkSource that generates every chunk it is asked for.
+pub struct GeneratorSource<G: ChunkGenerator>(pub G);
+
+impl<G: ChunkGenerator> ChunkSource for GeneratorSource<G> {
+    fn load(&self, origin: ChunkOrigin, pool: &mut SubChunkPool) -> Option<Chunk> {
+        Some(self.0.generate(origin, pool))
+    }
+}
+
+/// The blocks that terrain is made of.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TerrainBlocks {
+    pub stone: LocalID,
+    pub dirt: LocalID,
+    pub grass: LocalID,
+}
+
+impl TerrainBlocks {
+    pub const STONE: GlobalID = GlobalID::new("stone");
+    pub const DIRT: GlobalID = GlobalID::new("dirt");
+    pub const GRASS: GlobalID = GlobalID::new("grass");
+
+    /// Look up the terrain blocks by name.
+    /// Returns None if any of them isn't registered.
+    pub fn from_registry(blocks: &Registry<Block>) -> Option<Self> {
+        let get = |id| blocks.get_by_global(id).map(|entry| entry.local_id());
+
+        Some(Self {
+            stone: get(Self::STONE)?,
+            dirt: get(Self::DIRT)?,
+            grass: get(Self::GRASS)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Id;
+
+    use super::*;
+
+    #[test]
+    fn terrain_blocks_from_registry() {
+        let mut blocks = Registry::new("blocks");
+        for name in ["air", "grass", "stone"] {
+            blocks.add(Id::new(name), Block::default());
+        }
+        assert_eq!(None, TerrainBlocks::from_registry(&blocks));
+
+        blocks.add(Id::new("dirt"), Block::default());
+        assert_eq!(
+            Some(TerrainBlocks {
+                stone: LocalID::new(2),
+                dirt: LocalID::new(3),
+                grass: LocalID::new(1),
+            }),
+            TerrainBlocks::from_registry(&blocks)
+        );
+    }
+}
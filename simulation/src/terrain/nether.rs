@@ -0,0 +1,283 @@
+use bevy::math::{IVec2, IVec3};
+use libnoise::prelude::*;
+
+use crate::blocks::{Block, BlockState, Light};
+use crate::data::registry::{GlobalID, LocalID};
+use crate::data::Registry;
+use crate::world::{Chunk, ChunkBuilder, ChunkOrigin, ChunkStatus, SubChunkPool, CHUNK_WIDTH};
+
+use super::ChunkGenerator;
+
+const W: i32 = CHUNK_WIDTH as i32;
+
+/// The blocks that the nether is made of.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NetherBlocks {
+    pub netherrack: LocalID,
+    pub bedrock: LocalID,
+    pub lava: LocalID,
+}
+
+impl NetherBlocks {
+    pub const NETHERRACK: GlobalID = GlobalID::new("netherrack");
+    pub const BEDROCK: GlobalID = GlobalID::new("bedrock");
+    pub const LAVA: GlobalID = GlobalID::new("lava");
+
+    /// Look up the nether blocks by name.
+    /// Returns None if any of them isn't registered.
+    pub fn from_registry(blocks: &Registry<Block>) -> Option<Self> {
+        let get = |id| blocks.get_by_global(id).map(|entry| entry.local_id());
+
+        Some(Self {
+            netherrack: get(Self::NETHERRACK)?,
+            bedrock: get(Self::BEDROCK)?,
+            lava: get(Self::LAVA)?,
+        })
+    }
+}
+
+/// The shape of the nether's terrain.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NetherSettings {
+    /// Caverns are flooded with lava below this height.
+    pub lava_level: i32,
+
+    /// The number of layers of bedrock at the floor and the ceiling.
+    /// The layers past the first are more and more broken up.
+    pub bedrock_layers: i32,
+
+    /// The frequency of the cavern noise, in cycles per block.
+    pub frequency: f64,
+
+    /// The value the cavern noise has to be below for a block to be
+    /// netherrack. Higher values give smaller caverns.
+    pub threshold: f64,
+}
+
+impl Default for NetherSettings {
+    fn default() -> Self {
+        Self {
+            lava_level: 32,
+            bedrock_layers: 4,
+            frequency: 1.0 / 48.0,
+            threshold: 0.1,
+        }
+    }
+}
+
+/// Generates the nether: netherrack caverns between a bedrock floor and
+/// ceiling, with seas of lava at the bottom of the caverns.
+///
+/// Each block is netherrack if 3D noise at its world position is low
+/// enough. The noise is pushed down near the floor and the ceiling, so
+/// caverns are enclosed, and the broken layers of bedrock are decided by
+/// a hash of the position, so the nether only depends on the seed. There
+/// is nothing to carve, so chunks come out with `ChunkStatus::Carvers`.
+///
+/// The height is the height of the dimension, see `DimensionSettings`,
+/// and the top layer of blocks is the bedrock ceiling.
+#[derive(Clone, Debug)]
+pub struct NetherGenerator {
+    seed: u64,
+    blocks: NetherBlocks,
+    height: i32,
+    settings: NetherSettings,
+}
+
+impl NetherGenerator {
+    pub fn new(seed: u64, blocks: NetherBlocks, height: i32) -> Self {
+        Self::with_settings(seed, blocks, height, NetherSettings::default())
+    }
+
+    pub fn with_settings(seed: u64, blocks: NetherBlocks, height: i32, settings: NetherSettings) -> Self {
+        Self {
+            seed,
+            blocks,
+            height,
+            settings,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The height of the nether, in blocks.
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn settings(&self) -> &NetherSettings {
+        &self.settings
+    }
+
+    /// Get the block at a position, before light.
+    pub fn block_at(&self, pos: IVec3) -> LocalID {
+        self.sample(&self.noise(), pos)
+    }
+
+    fn noise(&self) -> impl Generator<3> {
+        Source::simplex(self.seed).fbm(3, self.settings.frequency, 2.0, 0.5)
+    }
+
+    fn sample(&self, noise: &impl Generator<3>, pos: IVec3) -> LocalID {
+        let settings = &self.settings;
+        let top = self.height - 1;
+        if pos.y < 0 || pos.y > top {
+            return LocalID::new(0);
+        }
+
+        // the chance of bedrock falls off with each layer
+        // away from the floor or ceiling, from 1 to 0.
+        let layer = pos.y.min(top - pos.y);
+        if layer < settings.bedrock_layers {
+            let chance = 1.0 - layer as f64 / settings.bedrock_layers as f64;
+            if self.hash(pos) < chance {
+                return self.blocks.bedrock;
+            }
+        }
+
+        // push the noise down towards the floor and ceiling, so caverns are enclosed.
+        let middle = self.height as f64 / 2.0;
+        let edge = (pos.y as f64 - middle).abs() / middle;
+        let density = noise.sample([pos.x as f64, pos.y as f64 * 1.5, pos.z as f64]) - edge.powi(6);
+
+        if density < settings.threshold {
+            self.blocks.netherrack
+        } else if pos.y < settings.lava_level {
+            self.blocks.lava
+        } else {
+            LocalID::new(0)
+        }
+    }
+
+    /// A value in [0, 1) that only depends on the seed and the position.
+    fn hash(&self, pos: IVec3) -> f64 {
+        let mut hash = self.seed ^ 0x5851_F42D_4C95_7F2D;
+        for value in [pos.x, pos.y, pos.z] {
+            hash ^= value as i64 as u64;
+            hash = hash.wrapping_mul(0x9E37_79B9_7F4A_7C15).rotate_left(29);
+        }
+        (hash >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl ChunkGenerator for NetherGenerator {
+    fn generate(&self, origin: ChunkOrigin, pool: &mut SubChunkPool) -> Chunk {
+        let noise = self.noise();
+        let height = self.height.max(0);
+        let mut builder = ChunkBuilder::new(pool, origin).with_height((height as usize).div_ceil(CHUNK_WIDTH));
+
+        for z in 0..W {
+            for x in 0..W {
+                let column = origin + IVec2::new(x, z);
+                for y in 0..height {
+                    let pos = IVec3::new(column.x, y, column.y);
+                    let block = self.sample(&noise, pos);
+                    if block != LocalID::new(0) {
+                        builder.set_block(pos, BlockState { block, light: Light::ZERO });
+                    }
+                }
+            }
+        }
+
+        let mut chunk = builder.build();
+        chunk.set_status(ChunkStatus::Carvers);
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Id;
+
+    use super::*;
+
+    fn generator(seed: u64) -> NetherGenerator {
+        generator_with_height(seed, 128)
+    }
+
+    fn generator_with_height(seed: u64, height: i32) -> NetherGenerator {
+        let mut blocks = Registry::new("blocks");
+        for name in ["air", "netherrack", "bedrock", "lava"] {
+            blocks.add(Id::new(name), Block::default());
+        }
+        NetherGenerator::new(seed, NetherBlocks::from_registry(&blocks).unwrap(), height)
+    }
+
+    fn count(chunk: &Chunk, block: LocalID, ys: std::ops::Range<i32>) -> usize {
+        let origin = chunk.origin();
+        let mut count = 0;
+        for z in 0..W {
+            for x in 0..W {
+                for y in ys.clone() {
+                    let pos = IVec3::new(origin.x + x, y, origin.y + z);
+                    count += (chunk.get_block(pos).unwrap().block == block) as usize;
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn bedrock_floor_and_ceiling() {
+        let generator = generator(9);
+        let blocks = generator.blocks;
+        let chunk = generator.generate(IVec2::new(-W, 2 * W), &mut SubChunkPool::default());
+        assert_eq!(4, chunk.height());
+        assert_eq!(ChunkStatus::Carvers, chunk.status());
+
+        assert_eq!(1024, count(&chunk, blocks.bedrock, 0..1));
+        assert_eq!(1024, count(&chunk, blocks.bedrock, 127..128));
+
+        // the layers past the first are broken up, then there's no bedrock.
+        let second = count(&chunk, blocks.bedrock, 1..2);
+        assert!(second > 0 && second < 1024);
+        assert_eq!(0, count(&chunk, blocks.bedrock, 4..124));
+    }
+
+    #[test]
+    fn ceiling_at_height() {
+        let generator = generator_with_height(9, 80);
+        let blocks = generator.blocks;
+        let chunk = generator.generate(IVec2::ZERO, &mut SubChunkPool::default());
+        assert_eq!(3, chunk.height());
+        assert_eq!(1024, count(&chunk, blocks.bedrock, 79..80));
+        assert_eq!(0, count(&chunk, blocks.netherrack, 80..96));
+    }
+
+    #[test]
+    fn caverns_and_lava_seas() {
+        let generator = generator(2);
+        let blocks = generator.blocks;
+        let mut pool = SubChunkPool::default();
+        let mut caverns = 0;
+        let mut lava = 0;
+
+        for origin in [IVec2::ZERO, IVec2::new(W, 0), IVec2::new(0, -3 * W)] {
+            let chunk = generator.generate(origin, &mut pool);
+            caverns += count(&chunk, LocalID::new(0), 0..128);
+            lava += count(&chunk, blocks.lava, 0..32);
+            assert!(count(&chunk, blocks.netherrack, 0..128) > 0);
+
+            // lava only fills caverns below the lava level.
+            assert_eq!(0, count(&chunk, blocks.lava, 32..128));
+            assert_eq!(0, count(&chunk, LocalID::new(0), 0..32));
+        }
+        assert!(caverns > 0);
+        assert!(lava > 0);
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let mut pool = SubChunkPool::default();
+        let origin = IVec2::new(5 * W, -W);
+        let first = generator(77).generate(origin, &mut pool);
+        let second = generator(77).generate(origin, &mut pool);
+        let other = generator(78).generate(origin, &mut pool);
+
+        let ids = |chunk: &Chunk| (0..4).flat_map(|i| chunk.get_subchunk(i * W).unwrap().ids().to_vec()).collect::<Vec<_>>();
+        assert_eq!(ids(&first), ids(&second));
+        assert_ne!(ids(&first), ids(&other));
+    }
+}
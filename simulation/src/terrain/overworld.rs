@@ -0,0 +1,335 @@
+use std::sync::Arc;
+
+use bevy::math::{IVec2, IVec3, Vec2};
+use libnoise::prelude::*;
+
+use crate::blocks::{BlockState, Light};
+use crate::data::registry::LocalID;
+use crate::world::{Chunk, ChunkBuilder, ChunkOrigin, ChunkStatus, SubChunkPool, WorldPos2, CHUNK_WIDTH};
+
+use super::{BiomeTable, Carver, ChunkGenerator, NoiseCaves, Ravines, TerrainBlocks};
+
+const W: i32 = CHUNK_WIDTH as i32;
+
+/// Added to the seed for the climate noises, so
+/// they don't line up with the height noise.
+const TEMPERATURE_SALT: u64 = 0x7E3A_11C5;
+const HUMIDITY_SALT: u64 = 0x4F1B_92D7;
+const CAVE_SALT: u64 = 0x2C65_D0A3;
+const RAVINE_SALT: u64 = 0x61E8_3B5F;
+
+/// The shape of the overworld's terrain.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OverworldSettings {
+    /// The frequency of the first octave of height
+    /// noise, in cycles per block.
+    pub frequency: f64,
+
+    /// The number of octaves of height noise.
+    pub octaves: u32,
+
+    /// The frequency of the temperature and
+    /// humidity noise, in cycles per block.
+    pub climate_frequency: f64,
+
+    /// How far apart two climates can be for their biomes to be
+    /// blended, see `BiomeTable::blend`. Larger values give wider,
+    /// smoother borders between biomes.
+    pub biome_blend: f32,
+}
+
+impl Default for OverworldSettings {
+    fn default() -> Self {
+        Self {
+            frequency: 1.0 / 192.0,
+            octaves: 5,
+            climate_frequency: 1.0 / 768.0,
+            biome_blend: 0.2,
+        }
+    }
+}
+
+/// Generates stone terrain, covered by the surface blocks of each biome.
+///
+/// The temperature and humidity of every column are sampled from noise,
+/// and the column gets the biome with the closest climate. The height of
+/// the column is sampled from fractal simplex noise, scaled by the shape of
+/// its biome blended with the biomes of nearby climates, so terrain meets
+/// smoothly at biome borders. Every noise is sampled at the column's world
+/// position, so the terrain only depends on the seed and lines up across
+/// chunk borders. Once the columns are filled, caves and ravines are
+/// carved out by the generator's carvers, in order.
+#[derive(Clone, Debug)]
+pub struct OverworldGenerator {
+    seed: u64,
+    blocks: TerrainBlocks,
+    biomes: BiomeTable,
+    settings: OverworldSettings,
+    carvers: Vec<Arc<dyn Carver>>,
+}
+
+/// The noises sampled for every column.
+struct Noises<H, T, M> {
+    height: H,
+    temperature: T,
+    humidity: M,
+}
+
+/// The biome and height of a column.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Column {
+    biome: LocalID,
+    height: i32,
+}
+
+impl OverworldGenerator {
+    pub fn new(seed: u64, blocks: TerrainBlocks, biomes: BiomeTable) -> Self {
+        Self::with_settings(seed, blocks, biomes, OverworldSettings::default())
+    }
+
+    /// Construct a generator with noise caves and ravines, see `with_carvers`.
+    pub fn with_settings(seed: u64, blocks: TerrainBlocks, biomes: BiomeTable, settings: OverworldSettings) -> Self {
+        Self {
+            seed,
+            blocks,
+            biomes,
+            settings,
+            carvers: vec![
+                Arc::new(NoiseCaves::new(seed.wrapping_add(CAVE_SALT))),
+                Arc::new(Ravines::new(seed.wrapping_add(RAVINE_SALT))),
+            ],
+        }
+    }
+
+    /// Replace the carvers that run after the columns are filled.
+    pub fn with_carvers(mut self, carvers: Vec<Arc<dyn Carver>>) -> Self {
+        self.carvers = carvers;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn settings(&self) -> &OverworldSettings {
+        &self.settings
+    }
+
+    pub fn biomes(&self) -> &BiomeTable {
+        &self.biomes
+    }
+
+    /// Get the height of the terrain in a column, which is
+    /// one above the surface block, before it is carved.
+    pub fn height_at(&self, pos: WorldPos2) -> i32 {
+        self.column(&self.noises(), pos).height
+    }
+
+    /// Get the biome of a column.
+    pub fn biome_at(&self, pos: WorldPos2) -> LocalID {
+        self.column(&self.noises(), pos).biome
+    }
+
+    fn noises(&self) -> Noises<impl Generator<2>, impl Generator<2>, impl Generator<2>> {
+        let climate = |seed| Source::simplex(seed).fbm(3, self.settings.climate_frequency, 2.0, 0.5);
+
+        Noises {
+            height: Source::simplex(self.seed).fbm(self.settings.octaves, self.settings.frequency, 2.0, 0.5),
+            temperature: climate(self.seed.wrapping_add(TEMPERATURE_SALT)),
+            humidity: climate(self.seed.wrapping_add(HUMIDITY_SALT)),
+        }
+    }
+
+    fn column(
+        &self,
+        noises: &Noises<impl Generator<2>, impl Generator<2>, impl Generator<2>>,
+        pos: WorldPos2,
+    ) -> Column {
+        let point = [pos.x as f64, pos.y as f64];
+        let climate = Vec2::new(
+            noises.temperature.sample(point).clamp(-1.0, 1.0) as f32,
+            noises.humidity.sample(point).clamp(-1.0, 1.0) as f32,
+        );
+        let blend = self.biomes.blend(climate, self.settings.biome_blend);
+
+        let value = noises.height.sample(point).clamp(-1.0, 1.0);
+        let height = (blend.base_height + value * blend.amplitude).round() as i32;
+        Column {
+            biome: blend.biome,
+            height: height.max(1),
+        }
+    }
+
+    /// Get the block at this height in a column.
+    fn block_at(&self, y: i32, column: Column) -> BlockState {
+        let biome = self.biomes.get(column.biome);
+        let block = if y == column.height - 1 {
+            biome.surface
+        } else if y >= column.height - 1 - biome.subsurface_depth {
+            biome.subsurface
+        } else {
+            self.blocks.stone
+        };
+
+        BlockState {
+            block,
+            light: Light::ZERO,
+        }
+    }
+}
+
+impl ChunkGenerator for OverworldGenerator {
+    fn generate(&self, origin: ChunkOrigin, pool: &mut SubChunkPool) -> Chunk {
+        let noises = self.noises();
+        let mut columns = [Column { biome: LocalID::new(0), height: 0 }; CHUNK_WIDTH * CHUNK_WIDTH];
+        for z in 0..W {
+            for x in 0..W {
+                columns[(x + z * W) as usize] = self.column(&noises, origin + IVec2::new(x, z));
+            }
+        }
+
+        let surface = columns.map(|column| column.height);
+        let top = surface.iter().copied().max().unwrap_or(0);
+        let mut builder = ChunkBuilder::new(pool, origin).with_height((top as usize).div_ceil(CHUNK_WIDTH));
+
+        for z in 0..W {
+            for x in 0..W {
+                let column = columns[(x + z * W) as usize];
+                builder.set_biome(origin + IVec2::new(x, z), column.biome);
+                for y in 0..column.height {
+                    let pos = IVec3::new(origin.x + x, y, origin.y + z);
+                    builder.set_block(pos, self.block_at(y, column));
+                }
+            }
+        }
+
+        let mut chunk = builder.build();
+        chunk.set_status(ChunkStatus::Noise);
+        for carver in &self.carvers {
+            carver.carve(&mut chunk, &surface);
+        }
+        chunk.set_status(ChunkStatus::Carvers);
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::util::{biomes_for_testing, blocks_for_testing};
+    use crate::world::{HeightmapKind, HeightmapTable, World};
+
+    use super::*;
+
+    fn generator(seed: u64) -> OverworldGenerator {
+        let blocks = blocks_for_testing();
+        let biomes = biomes_for_testing();
+
+        OverworldGenerator::new(
+            seed,
+            TerrainBlocks::from_registry(&blocks).unwrap(),
+            BiomeTable::from_registry(&biomes, &blocks).unwrap(),
+        )
+    }
+
+    /// A generator that doesn't carve caves, so
+    /// every column is filled up to its height.
+    fn uncarved(seed: u64) -> OverworldGenerator {
+        generator(seed).with_carvers(Vec::new())
+    }
+
+    fn ids(chunk: &Chunk) -> Vec<LocalID> {
+        (0..chunk.height() as i32)
+            .flat_map(|i| chunk.get_subchunk(i * W).unwrap().ids().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let origins = [IVec2::ZERO, IVec2::new(-W, 3 * W), IVec2::new(7 * W, -2 * W)];
+        let mut pool = SubChunkPool::default();
+
+        let first: Vec<_> = origins.iter().map(|origin| generator(42).generate(*origin, &mut pool)).collect();
+        let second: Vec<_> = origins.iter().rev().map(|origin| generator(42).generate(*origin, &mut pool)).collect();
+        for (a, b) in first.iter().zip(second.iter().rev()) {
+            assert_eq!(a.origin(), b.origin());
+            assert_eq!(ids(a), ids(b));
+            assert_eq!(a.biomes(), b.biomes());
+        }
+
+        let other = generator(43).generate(origins[0], &mut pool);
+        assert_ne!(ids(&first[0]), ids(&other));
+    }
+
+    #[test]
+    fn columns_are_layered() {
+        let generator = uncarved(7);
+        let stone = TerrainBlocks::from_registry(&blocks_for_testing()).unwrap().stone;
+        let origin = IVec2::new(-W, W);
+        let chunk = generator.generate(origin, &mut SubChunkPool::default());
+
+        for z in 0..W {
+            for x in 0..W {
+                let column = origin + IVec2::new(x, z);
+                let height = generator.height_at(column);
+                let biome = generator.biomes().get(generator.biome_at(column));
+                let block = |y| chunk.get_block(IVec3::new(column.x, y, column.y)).map(|state| state.block);
+
+                assert_eq!(generator.biome_at(column), chunk.biome_at(column));
+                assert_eq!(Some(biome.surface), block(height - 1));
+                if biome.subsurface_depth > 0 {
+                    assert_eq!(Some(biome.subsurface), block(height - 2));
+                    assert_eq!(Some(biome.subsurface), block(height - 1 - biome.subsurface_depth));
+                }
+                assert_eq!(Some(stone), block(height - 2 - biome.subsurface_depth));
+                assert_eq!(Some(stone), block(0));
+                assert!(block(height).is_none_or(|id| id == LocalID::new(0)));
+            }
+        }
+    }
+
+    #[test]
+    fn carves_caves_below_surface() {
+        let carved = generator(21).generate(IVec2::new(W, -W), &mut SubChunkPool::default());
+        let filled = uncarved(21).generate(IVec2::new(W, -W), &mut SubChunkPool::default());
+
+        let (carved, filled) = (ids(&carved), ids(&filled));
+        let air = LocalID::new(0);
+        assert!(carved.iter().zip(&filled).all(|(carved, filled)| carved == filled || *carved == air));
+        assert!(carved.iter().filter(|id| **id == air).count() > filled.iter().filter(|id| **id == air).count());
+    }
+
+    #[test]
+    fn climate_selects_biomes() {
+        let generator = generator(99);
+        let mut found = vec![false; generator.biomes().len()];
+        for z in -32..32 {
+            for x in -32..32 {
+                let biome = generator.biome_at(IVec2::new(x, z) * 256);
+                found[biome.index() as usize] = true;
+            }
+        }
+        assert!(found.iter().filter(|found| **found).count() > 1);
+    }
+
+    #[test]
+    fn heightmaps_match_generated_heights() {
+        let blocks = blocks_for_testing();
+        let generator = uncarved(1234);
+        let mut world = World::new();
+        world.set_heightmap_table(HeightmapTable::from_registry(&blocks));
+
+        for origin in [IVec2::ZERO, IVec2::new(W, 0)] {
+            let chunk = generator.generate(origin, world.pool_mut());
+            world.insert(chunk);
+        }
+
+        let reader = world.reader();
+        for x in 0..2 * W {
+            for z in [0, W / 2, W - 1] {
+                let column = IVec2::new(x, z);
+                assert_eq!(Some(generator.height_at(column)), reader.height(HeightmapKind::NonAir, column));
+                assert_eq!(Some(generator.biome_at(column)), reader.biome(column));
+            }
+        }
+    }
+}
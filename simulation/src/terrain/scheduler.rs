@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+
+use crate::world::lighting::LightQueue;
+use crate::world::{ChunkOrigin, ChunkStatus, World, CHUNK_WIDTH};
+
+use super::Decorator;
+
+const W: i32 = CHUNK_WIDTH as i32;
+
+/// Advances generated chunks through the stages of world generation
+/// that write into, or read from, their neighbours.
+///
+/// Chunks arrive from a generator with `ChunkStatus::Carvers`, since
+/// filling and carving a chunk never touches its neighbours. Every later
+/// stage waits until all 8 neighbours of the chunk have reached the stage
+/// before it, see `prerequisite`, so a decorator never writes into a
+/// chunk that is being lit, and light never spreads into a chunk that
+/// a neighbour may still decorate.
+#[derive(Debug, Default, Resource)]
+pub struct GenerationScheduler {
+    decorator: Option<Decorator>,
+}
+
+impl GenerationScheduler {
+    /// Construct a scheduler that doesn't place features.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Place features with this decorator when chunks reach `ChunkStatus::Features`.
+    pub fn with_decorator(mut self, decorator: Decorator) -> Self {
+        self.decorator = Some(decorator);
+        self
+    }
+
+    pub fn decorator(&self) -> Option<&Decorator> {
+        self.decorator.as_ref()
+    }
+
+    /// The status every neighbour of a chunk must have reached before the
+    /// chunk can advance to `status`. Returns None for the stages that are
+    /// done by the generator, which don't depend on neighbours.
+    pub const fn prerequisite(status: ChunkStatus) -> Option<ChunkStatus> {
+        match status {
+            ChunkStatus::Empty | ChunkStatus::Noise | ChunkStatus::Carvers => None,
+
+            // features are placed through the 3x3 cluster around the chunk.
+            ChunkStatus::Features => Some(ChunkStatus::Carvers),
+
+            // the features of the neighbours may write into the chunk.
+            ChunkStatus::Light => Some(ChunkStatus::Features),
+
+            // light may still spread in from a neighbour until it is lit.
+            ChunkStatus::Full => Some(ChunkStatus::Light),
+        }
+    }
+
+    /// Advance every chunk in the world whose neighbours are ready by one
+    /// status, returning the number of chunks that advanced. Chunks that
+    /// reach `ChunkStatus::Light` are queued in the light queue, if there
+    /// is one, which should be updated after this.
+    pub fn update(&mut self, world: &mut World, mut light: Option<&mut LightQueue>) -> usize {
+        // chunks before Carvers are still being generated, and full chunks are done.
+        let scheduled = |status| (ChunkStatus::Carvers..ChunkStatus::Full).contains(&status);
+        let pending: Vec<_> = world
+            .origins()
+            .filter(|origin| world.get_chunk_with_origin(*origin).is_some_and(|chunk| scheduled(chunk.status())))
+            .collect();
+
+        let mut advanced = 0;
+        for origin in pending {
+            let Some(status) = world.get_chunk_with_origin(origin).and_then(|chunk| chunk.status().next()) else {
+                continue;
+            };
+            if !Self::neighbours_reached(world, origin, status) {
+                continue;
+            }
+
+            match status {
+                ChunkStatus::Features => {
+                    if let Some(decorator) = &self.decorator {
+                        decorator.decorate(world, origin);
+                    }
+                }
+                ChunkStatus::Light => {
+                    if let Some(light) = light.as_deref_mut() {
+                        light.queue_chunk(origin);
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some(chunk) = world.get_chunk_with_origin_mut(origin) {
+                chunk.set_status(status);
+                advanced += 1;
+            }
+        }
+
+        advanced
+    }
+
+    /// Returns true if every neighbour of the chunk is loaded and
+    /// has reached the prerequisite of the status.
+    fn neighbours_reached(world: &World, origin: ChunkOrigin, status: ChunkStatus) -> bool {
+        let Some(prerequisite) = Self::prerequisite(status) else {
+            return true;
+        };
+
+        (-1..=1).all(|z| {
+            (-1..=1).all(|x| {
+                world
+                    .get_chunk_with_origin(origin + IVec2::new(x, z) * W)
+                    .is_some_and(|chunk| chunk.status() >= prerequisite)
+            })
+        })
+    }
+}
+
+/// Advance chunks through world generation, see `GenerationScheduler`.
+pub fn advance_generation(
+    mut world: ResMut<World>,
+    mut scheduler: ResMut<GenerationScheduler>,
+    mut light: Option<ResMut<LightQueue>>,
+) {
+    scheduler.update(&mut world, light.as_deref_mut());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::{BlockState, Light};
+    use crate::data::registry::LocalID;
+    use crate::data::{Id, Registry};
+    use crate::terrain::{Feature, FeatureKind, FeatureTable, Placement};
+    use crate::world::util::{biomes_for_testing, blocks_for_testing};
+    use crate::world::{ChunkBuilder, LightTable, MAX_LIGHT};
+
+    use super::*;
+
+    const STONE: LocalID = LocalID::new(1);
+    const GRASS: LocalID = LocalID::new(3);
+    const FLOWER: LocalID = LocalID::new(11);
+
+    /// A decorator that places flowers on grass in plains,
+    /// where none of the biome's other features do anything.
+    fn decorator() -> Decorator {
+        let blocks = blocks_for_testing();
+        let biomes = biomes_for_testing();
+
+        let mut features = Registry::new("features");
+        for name in ["coal_ore", "iron_ore", "oak_tree", "flowers", "boulder"] {
+            features.add(
+                Id::new(name),
+                Feature {
+                    count: if name == "flowers" { 16 } else { 0 },
+                    chance: 1.0,
+                    placement: Placement::Surface,
+                    kind: FeatureKind::Plant {
+                        block: Id::new("flower"),
+                        soil: Id::new("grass"),
+                    },
+                },
+            );
+        }
+
+        Decorator::new(5, FeatureTable::from_registry(&features, &biomes, &blocks).unwrap())
+    }
+
+    /// Insert a freshly generated chunk, with stone below
+    /// y = 8, grass at 8, and air above it.
+    fn generate(world: &mut World, origin: ChunkOrigin) {
+        let mut chunk = ChunkBuilder::new(world.pool_mut(), origin).with_height(1).build();
+        for (i, id) in chunk.get_subchunk_mut(0).unwrap().ids_mut().iter_mut().enumerate() {
+            *id = match i % CHUNK_WIDTH {
+                0..8 => STONE,
+                8 => GRASS,
+                _ => LocalID::new(0),
+            };
+        }
+        chunk.set_status(ChunkStatus::Carvers);
+        world.insert(chunk);
+    }
+
+    fn status(world: &World, x: i32, z: i32) -> ChunkStatus {
+        world.get_chunk_with_origin(IVec2::new(x, z) * W).unwrap().status()
+    }
+
+    fn has_flowers(world: &World, x: i32, z: i32) -> bool {
+        let chunk = world.get_chunk_with_origin(IVec2::new(x, z) * W).unwrap();
+        chunk.get_subchunk(0).unwrap().ids().contains(&FLOWER)
+    }
+
+    #[test]
+    fn advances_when_neighbours_are_ready() {
+        let mut world = World::new();
+        let mut scheduler = GenerationScheduler::new();
+        for z in -3..=3 {
+            for x in -3..=3 {
+                generate(&mut world, IVec2::new(x, z) * W);
+            }
+        }
+
+        while scheduler.update(&mut world, None) > 0 {}
+
+        // each ring of chunks is one stage behind the ring inside it,
+        // since the outermost ring never has all of its neighbours.
+        for z in -3..=3i32 {
+            for x in -3..=3i32 {
+                let expected = match x.abs().max(z.abs()) {
+                    0 => ChunkStatus::Full,
+                    1 => ChunkStatus::Light,
+                    2 => ChunkStatus::Features,
+                    _ => ChunkStatus::Carvers,
+                };
+                assert_eq!(expected, status(&world, x, z), "at: {x}, {z}");
+            }
+        }
+    }
+
+    #[test]
+    fn decorates_once_neighbours_are_generated() {
+        let mut world = World::new();
+        let mut scheduler = GenerationScheduler::new().with_decorator(decorator());
+        for z in -1..=1 {
+            for x in -1..=1 {
+                if (x, z) != (1, 1) {
+                    generate(&mut world, IVec2::new(x, z) * W);
+                }
+            }
+        }
+
+        assert_eq!(0, scheduler.update(&mut world, None));
+        assert_eq!(ChunkStatus::Carvers, status(&world, 0, 0));
+        assert!(!has_flowers(&world, 0, 0));
+
+        generate(&mut world, IVec2::new(W, W));
+        assert_eq!(1, scheduler.update(&mut world, None));
+        assert_eq!(ChunkStatus::Features, status(&world, 0, 0));
+        assert!(has_flowers(&world, 0, 0));
+    }
+
+    #[test]
+    fn lights_once_neighbours_are_decorated() {
+        let mut world = World::new();
+        let mut scheduler = GenerationScheduler::new();
+        let mut light = LightQueue::new(LightTable::from_registry(&blocks_for_testing()));
+        for z in -2..=2 {
+            for x in -2..=2 {
+                generate(&mut world, IVec2::new(x, z) * W);
+            }
+        }
+
+        let sky = |world: &World| world.reader().get_block(IVec3::new(4, 20, 4)).map(|state| state.light.ambient());
+
+        // generating chunks aren't lit when they are inserted.
+        light.update(&mut world);
+        assert_eq!(Some(0), sky(&world));
+
+        scheduler.update(&mut world, Some(&mut light));
+        light.update(&mut world);
+        assert_eq!(ChunkStatus::Features, status(&world, 0, 0));
+        assert_eq!(Some(0), sky(&world));
+
+        scheduler.update(&mut world, Some(&mut light));
+        light.update(&mut world);
+        assert_eq!(ChunkStatus::Light, status(&world, 0, 0));
+        assert_eq!(Some(MAX_LIGHT), sky(&world));
+
+        // light doesn't spread into the chunks that aren't lit yet.
+        let edge = BlockState {
+            block: LocalID::new(0),
+            light: Light::ZERO,
+        };
+        assert_eq!(Some(edge), world.reader().get_block(IVec3::new(-2 * W, 20, 0)));
+    }
+}
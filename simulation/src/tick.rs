@@ -0,0 +1,272 @@
+//! Ticking the Simulation at a fixed rate.
+//!
+//! The Simulation advances in fixed steps called ticks, 20 times a second by
+//! default. The `TickClock` in the main app counts the ticks that are due
+//! every frame from real time, so the Simulation ticks at the same rate in a
+//! headless server driven by a `ScheduleRunnerPlugin`, and in the Client at
+//! any frame rate. When frames fall behind, the clock catches up by running
+//! several ticks in one frame, up to `TickClock::max_steps`. Time past that
+//! budget is dropped, so a slow server falls behind instead of spiralling.
+//!
+//! Each tick, every loaded dimension runs `PreTick`, `BlockUpdates`,
+//! `Entities` and `PostTick`, in that order, see `run_tick`.
+
+use std::time::{Duration, Instant};
+
+use bevy::ecs::schedule::{InternedScheduleLabel, ScheduleLabel};
+use bevy::prelude::*;
+
+use crate::BevyEcs;
+
+/// Runs first every tick, before anything changes, such as
+/// streaming chunks and advancing world generation.
+#[derive(ScheduleLabel, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct PreTick;
+
+/// Runs after `PreTick`, for changing blocks.
+#[derive(ScheduleLabel, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct BlockUpdates;
+
+/// Runs after `BlockUpdates`, for moving entities.
+#[derive(ScheduleLabel, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Entities;
+
+/// Runs last every tick, after everything changed, such as lighting.
+#[derive(ScheduleLabel, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct PostTick;
+
+/// The schedules run every tick, in order.
+pub fn tick_schedules() -> [InternedScheduleLabel; 4] {
+    [PreTick.intern(), BlockUpdates.intern(), Entities.intern(), PostTick.intern()]
+}
+
+/// The number of ticks that have run. In the main app, this is the number of
+/// ticks the clock has counted, and in a dimension, the number of ticks the
+/// dimension has run, which doesn't count ticks while it wasn't loaded.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Resource)]
+pub struct TickCounter(pub u64);
+
+impl TickCounter {
+    pub fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Counts the ticks that are due every frame, see the module documentation.
+#[derive(Debug, Resource)]
+pub struct TickClock {
+    tps: u32,
+    max_steps: u32,
+    paused: bool,
+
+    /// The time that hasn't been ticked yet.
+    elapsed: Duration,
+    last: Option<Instant>,
+
+    /// The ticks requested with `step`.
+    steps: u32,
+    due: u32,
+    skipped: u64,
+}
+
+impl Default for TickClock {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_TPS)
+    }
+}
+
+impl TickClock {
+    pub const DEFAULT_TPS: u32 = 20;
+    pub const DEFAULT_MAX_STEPS: u32 = 10;
+
+    /// Construct a clock that ticks `tps` times a second.
+    pub fn new(tps: u32) -> Self {
+        assert!(tps > 0, "the tick rate must not be zero");
+        Self {
+            tps,
+            max_steps: Self::DEFAULT_MAX_STEPS,
+            paused: false,
+            elapsed: Duration::ZERO,
+            last: None,
+            steps: 0,
+            due: 0,
+            skipped: 0,
+        }
+    }
+
+    /// Run at most `max_steps` ticks in one frame when catching up.
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// The ticks per second.
+    pub fn tps(&self) -> u32 {
+        self.tps
+    }
+
+    pub fn set_tps(&mut self, tps: u32) {
+        assert!(tps > 0, "the tick rate must not be zero");
+        self.tps = tps;
+    }
+
+    /// The time between ticks.
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.tps
+    }
+
+    pub fn max_steps(&self) -> u32 {
+        self.max_steps
+    }
+
+    /// The number of ticks the dimensions run this frame.
+    pub fn due(&self) -> u32 {
+        self.due
+    }
+
+    /// The number of ticks that were dropped because they were over the
+    /// `max_steps` budget, which is how far the Simulation fell behind.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stop counting ticks from real time. Ticks requested with `step`
+    /// still run, so a paused Simulation can be stepped by hand.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Run `ticks` more ticks in the next frame, paused or not,
+    /// regardless of the `max_steps` budget.
+    pub fn step(&mut self, ticks: u32) {
+        self.steps += ticks;
+    }
+
+    /// Add time that has passed, which is counted by the next `update`.
+    pub fn elapse(&mut self, elapsed: Duration) {
+        self.elapsed += elapsed;
+    }
+
+    /// Count the ticks that are due for the time that has passed, and
+    /// the steps that were requested, returning the number of ticks.
+    pub fn update(&mut self) -> u32 {
+        let mut due = 0;
+        if self.paused {
+            self.elapsed = Duration::ZERO;
+        } else {
+            let duration = self.tick_duration();
+            due = (self.elapsed.as_nanos() / duration.as_nanos()) as u32;
+            self.elapsed -= duration * due;
+
+            if due > self.max_steps {
+                let skipped = due - self.max_steps;
+                log::warn!("Can't keep up, skipping {skipped} ticks");
+                self.skipped += skipped as u64;
+                due = self.max_steps;
+            }
+        }
+
+        self.due = due + std::mem::take(&mut self.steps);
+        self.due
+    }
+}
+
+/// Count the ticks that are due for the real time since the last frame.
+pub fn advance_tick_clock(mut clock: ResMut<TickClock>, mut counter: ResMut<TickCounter>) {
+    let now = Instant::now();
+    if let Some(last) = clock.last {
+        clock.elapse(now - last);
+    }
+    clock.last = Some(now);
+    counter.0 += clock.update() as u64;
+}
+
+/// Run one tick of a dimension: every tick schedule in order,
+/// then advance the dimension's `TickCounter`.
+pub fn run_tick(dimension: &mut BevyEcs) {
+    for label in tick_schedules() {
+        dimension.run_schedule(label);
+    }
+    dimension.resource_mut::<TickCounter>().0 += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_up_within_budget() {
+        let mut clock = TickClock::new(20).with_max_steps(4);
+        assert_eq!(Duration::from_millis(50), clock.tick_duration());
+
+        clock.elapse(Duration::from_millis(120));
+        assert_eq!(2, clock.update());
+        assert_eq!(0, clock.update());
+
+        // the left over 20ms are carried over.
+        clock.elapse(Duration::from_millis(30));
+        assert_eq!(1, clock.update());
+
+        // falling 10 ticks behind runs the budget, and drops the rest.
+        clock.elapse(Duration::from_millis(500));
+        assert_eq!(4, clock.update());
+        assert_eq!(6, clock.skipped());
+        assert_eq!(0, clock.update());
+    }
+
+    #[test]
+    fn steps_while_paused() {
+        let mut clock = TickClock::new(10);
+        clock.set_paused(true);
+        clock.elapse(Duration::from_secs(1));
+        assert_eq!(0, clock.update());
+
+        clock.step(3);
+        assert_eq!(3, clock.update());
+        assert_eq!(0, clock.update());
+
+        // time that passed while paused isn't ticked.
+        clock.set_paused(false);
+        clock.elapse(Duration::from_millis(100));
+        clock.step(1);
+        assert_eq!(2, clock.update());
+    }
+
+    #[test]
+    fn ticks_schedules_in_order() {
+        #[derive(Default, Resource)]
+        struct Order(Vec<(u64, &'static str)>);
+
+        let mut dimension = BevyEcs::new();
+        dimension.init_resource::<Order>();
+        dimension.init_resource::<TickCounter>();
+        for (label, name) in tick_schedules().into_iter().zip(["pre", "blocks", "entities", "post"]) {
+            let mut schedule = Schedule::new(label);
+            schedule.add_systems(move |mut order: ResMut<Order>, tick: Res<TickCounter>| {
+                order.0.push((tick.get(), name));
+            });
+            dimension.add_schedule(schedule);
+        }
+
+        run_tick(&mut dimension);
+        run_tick(&mut dimension);
+        assert_eq!(TickCounter(2), *dimension.resource::<TickCounter>());
+        assert_eq!(
+            vec![
+                (0, "pre"),
+                (0, "blocks"),
+                (0, "entities"),
+                (0, "post"),
+                (1, "pre"),
+                (1, "blocks"),
+                (1, "entities"),
+                (1, "post"),
+            ],
+            dimension.resource::<Order>().0
+        );
+    }
+}
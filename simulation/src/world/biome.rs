@@ -0,0 +1,92 @@
+use crate::data::registry::LocalID;
+
+use super::heightmap::to_column_index;
+use super::*;
+
+/// The biome of every column of a chunk, by the LocalID
+/// of the biome in the biome registry.
+///
+/// Biomes are set when the chunk is generated, and
+/// every column starts as the biome with LocalID 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BiomeMap {
+    /// The biome of each column, indexed by x + z * CHUNK_WIDTH.
+    biomes: [LocalID; CHUNK_WIDTH * CHUNK_WIDTH],
+}
+
+impl BiomeMap {
+    pub const fn new() -> Self {
+        Self {
+            biomes: [LocalID::new(0); CHUNK_WIDTH * CHUNK_WIDTH],
+        }
+    }
+
+    /// Get the biome of a column, where the xz of
+    /// the position is within the chunk.
+    pub fn get(&self, pos: WorldPos2) -> LocalID {
+        self.biomes[to_column_index(pos)]
+    }
+
+    /// Set the biome of a column, where the xz of
+    /// the position is within the chunk.
+    pub fn set(&mut self, pos: WorldPos2, biome: LocalID) {
+        self.biomes[to_column_index(pos)] = biome;
+    }
+
+    /// Get the biome of every column, indexed by x + z * CHUNK_WIDTH.
+    pub fn biomes(&self) -> &[LocalID; CHUNK_WIDTH * CHUNK_WIDTH] {
+        &self.biomes
+    }
+}
+
+impl Default for BiomeMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunk {
+    /// Get the biome of the column containing the position.
+    pub fn biome_at(&self, pos: WorldPos2) -> LocalID {
+        self.biomes.get(pos)
+    }
+
+    /// Set the biome of the column containing the position.
+    pub fn set_biome(&mut self, pos: WorldPos2, biome: LocalID) {
+        self.biomes.set(pos, biome);
+    }
+
+    pub fn biomes(&self) -> &BiomeMap {
+        &self.biomes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::util::*;
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    #[test]
+    fn biomes_are_per_column() {
+        let origin = IVec2::new(-W, 2 * W);
+        let mut chunk = Chunk::new(origin);
+        assert!(chunk.biomes().biomes().iter().all(|biome| *biome == LocalID::new(0)));
+
+        chunk.set_biome(origin + IVec2::new(3, 5), LocalID::new(2));
+        assert_eq!(LocalID::new(2), chunk.biome_at(origin + IVec2::new(3, 5)));
+        assert_eq!(LocalID::new(0), chunk.biome_at(origin + IVec2::new(5, 3)));
+        assert_eq!(LocalID::new(2), chunk.biomes().biomes()[3 + 5 * CHUNK_WIDTH]);
+
+        let mut world = world_for_testing();
+        let mut chunk = world.take(IVec2::ZERO).unwrap();
+        chunk.set_biome(IVec2::new(W - 1, 0), LocalID::new(1));
+        world.insert(chunk);
+
+        let reader = world.reader();
+        assert_eq!(Some(LocalID::new(1)), reader.biome(IVec2::new(W - 1, 0)));
+        assert_eq!(Some(LocalID::new(0)), reader.biome(IVec2::new(W, 0)));
+        assert_eq!(None, reader.biome(IVec2::new(4 * W, 0)));
+    }
+}
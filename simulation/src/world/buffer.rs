@@ -1,9 +1,223 @@
+use std::ops::{Index, IndexMut};
 
 use bevy::math::IVec3;
 
+use super::*;
+
+/// A dense copy of a box-shaped region of the world.
+///
+/// A WorldBuffer owns its data, so it can be sent to another
+/// thread, operated on, and written back to the world with
+/// `WorldWriter::write_buffer`. Like a SubChunk, the data is
+/// linear on the y axis, then x, then z.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct WorldBuffer<T> {
     data: Vec<T>,
     origin: IVec3,
     extent: IVec3,
 }
 
+impl<T: Clone> WorldBuffer<T> {
+    /// Create a buffer starting at origin and extending by extent
+    /// (exclusive), with every element set to the value.
+    pub fn new(origin: WorldPos3, extent: IVec3, value: T) -> Self {
+        let extent = extent.max(IVec3::ZERO);
+        Self {
+            data: vec![value; (extent.x * extent.y * extent.z) as usize],
+            origin,
+            extent,
+        }
+    }
+}
+
+impl<T> WorldBuffer<T> {
+    /// The minimum position in the buffer.
+    pub fn origin(&self) -> WorldPos3 {
+        self.origin
+    }
+
+    /// The size of the buffer on each axis.
+    pub fn extent(&self) -> IVec3 {
+        self.extent
+    }
+
+    /// Returns true if the position is in the buffer.
+    pub fn contains(&self, pos: WorldPos3) -> bool {
+        pos.cmpge(self.origin).all() && pos.cmplt(self.origin + self.extent).all()
+    }
+
+    /// The index of the position in the data,
+    /// or None if the position is not in the buffer.
+    pub fn index_of(&self, pos: WorldPos3) -> Option<usize> {
+        if !self.contains(pos) {
+            return None;
+        }
+
+        let local = pos - self.origin;
+        Some((local.y + local.x * self.extent.y + local.z * self.extent.y * self.extent.x) as usize)
+    }
+
+    /// The position of an index in the data.
+    pub fn position_of(&self, index: usize) -> WorldPos3 {
+        let index = index as i32;
+        let column = index / self.extent.y;
+        IVec3::new(column % self.extent.x, index % self.extent.y, column / self.extent.x) + self.origin
+    }
+
+    /// Get the value at the position, or None if
+    /// the position is not in the buffer.
+    pub fn get(&self, pos: WorldPos3) -> Option<&T> {
+        self.data.get(self.index_of(pos)?)
+    }
+
+    /// Get the value at the position mutably, or
+    /// None if the position is not in the buffer.
+    pub fn get_mut(&mut self, pos: WorldPos3) -> Option<&mut T> {
+        let index = self.index_of(pos)?;
+        self.data.get_mut(index)
+    }
+
+    /// Iterate over the positions and values
+    /// in the buffer, in the order of the data.
+    pub fn iter(&self) -> impl Iterator<Item = (WorldPos3, &T)> {
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (self.position_of(index), value))
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn as_slice_mut(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+}
+
+impl<T> Index<WorldPos3> for WorldBuffer<T> {
+    type Output = T;
+
+    fn index(&self, pos: WorldPos3) -> &Self::Output {
+        match self.index_of(pos) {
+            Some(index) => &self.data[index],
+            None => panic!("{pos} is not in the buffer at {} with extent {}", self.origin, self.extent),
+        }
+    }
+}
+
+impl<T> IndexMut<WorldPos3> for WorldBuffer<T> {
+    fn index_mut(&mut self, pos: WorldPos3) -> &mut Self::Output {
+        match self.index_of(pos) {
+            Some(index) => &mut self.data[index],
+            None => panic!("{pos} is not in the buffer at {} with extent {}", self.origin, self.extent),
+        }
+    }
+}
+
+impl World {
+    /// Copy the blocks starting at origin and extending by extent (exclusive)
+    /// into a buffer. Blocks that are not in the world are `BlockState::default()`.
+    pub fn read_buffer(&self, origin: WorldPos3, extent: IVec3) -> WorldBuffer<BlockState> {
+        let mut buffer = WorldBuffer::new(origin, extent, BlockState::default());
+        for fragment in self.reader().volume(origin, extent).fragments() {
+            for (pos, state) in fragment {
+                buffer[pos] = state;
+            }
+        }
+        buffer
+    }
+
+    /// Copy the subchunk with this origin into a buffer, along with
+    /// `padding` blocks of its neighbours on every side. A padding of 1
+    /// is enough to find the neighbours of every block in the subchunk.
+    pub fn read_subchunk_padded(&self, origin: SubChunkOrigin, padding: i32) -> WorldBuffer<BlockState> {
+        self.read_buffer(
+            origin - IVec3::splat(padding),
+            IVec3::splat(CHUNK_WIDTH as i32 + padding * 2),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::Light;
+    use crate::data::registry::LocalID;
+
+    use super::util::*;
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    const STONE: BlockState = BlockState {
+        block: LocalID::new(1),
+        light: Light::from_raw(15, 0, 0, 0),
+    };
+
+    #[test]
+    fn buffer_index() {
+        let mut buffer = WorldBuffer::new(IVec3::new(-3, 4, 7), IVec3::new(2, 3, 4), 0);
+        for (i, value) in buffer.as_slice_mut().iter_mut().enumerate() {
+            *value = i;
+        }
+
+        for i in 0..buffer.as_slice().len() {
+            let pos = buffer.position_of(i);
+            assert!(buffer.contains(pos));
+            assert_eq!(Some(i), buffer.index_of(pos));
+            assert_eq!(i, buffer[pos]);
+        }
+        assert_eq!(None, buffer.get(IVec3::new(-3, 7, 7)));
+        assert_eq!(None, buffer.get(IVec3::new(-4, 4, 7)));
+    }
+
+    #[test]
+    fn read_subchunk_with_padding() {
+        let world = world_for_testing();
+        let reader = world.reader();
+        let buffer = world.read_subchunk_padded(IVec3::ZERO, 1);
+        assert_eq!(IVec3::splat(-1), buffer.origin());
+        assert_eq!(IVec3::splat(W + 2), buffer.extent());
+
+        for (pos, state) in buffer.iter() {
+            // blocks below the world are the default.
+            let expected = reader.get_block(pos).unwrap_or_default();
+            assert_eq!(expected, *state, "at: {pos}");
+        }
+    }
+
+    #[test]
+    fn write_buffer_back() {
+        let mut world = world_for_testing();
+        let mut buffer = world.read_buffer(IVec3::new(-4, W - 2, -4), IVec3::new(8, 4, 8));
+
+        // operate on the buffer on another thread.
+        let buffer = std::thread::spawn(move || {
+            for state in buffer.as_slice_mut() {
+                *state = STONE;
+            }
+            buffer
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(8 * 4 * 8, world.writer().write_buffer(&buffer));
+        let reader = world.reader();
+        for (pos, state) in buffer.iter() {
+            assert_eq!(Some(*state), reader.get_block(pos), "at: {pos}");
+        }
+        assert_eq!(8 * 4 * 8, world.drain_changes().count());
+    }
+
+    #[test]
+    fn write_buffer_skips_default_outside_world() {
+        let mut world = world_for_testing();
+        let mut buffer = world.read_buffer(IVec3::new(0, W * 3 - 1, 0), IVec3::new(1, 3, 1));
+        buffer[IVec3::new(0, W * 3 - 1, 0)] = STONE;
+
+        // only the block that was changed is written, and no
+        // subchunks are created for the empty blocks above the chunk.
+        assert_eq!(1, world.writer().write_buffer(&buffer));
+        assert_eq!(3, world.get_chunk(IVec3::ZERO).unwrap().subchunks.len());
+    }
+}
@@ -5,7 +5,7 @@ use super::chunk::{CHUNK_LEN, CHUNK_WIDTH};
 /// what edges of the subchunk it is on, if any.
 ///
 /// Order is -x, +x, -y, +y, -z, +z
-pub const CACHED_NEIGHBOUR_CHUNK_BOUNDARIES: [u8; CHUNK_LEN] = {
+pub static CACHED_NEIGHBOUR_CHUNK_BOUNDARIES: [u8; CHUNK_LEN] = {
     let mut result = [0; CHUNK_LEN];
     const W: usize = CHUNK_WIDTH - 1;
 
@@ -0,0 +1,204 @@
+use bevy::ecs::system::ResMut;
+use chunk::DIRTY_SECTION_WIDTH;
+
+use crate::math::bits::BitIterU64;
+
+use super::*;
+
+/// A block that was changed by a WorldWriter.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BlockChange {
+    pub pos: WorldPos3,
+    pub old: BlockState,
+    pub new: BlockState,
+}
+
+/// Record of the mutations made to the World since
+/// it was last drained. Downstream systems (meshing,
+/// lighting, networking) read this to react only to
+/// the regions that were modified.
+#[derive(Clone, Debug, Default)]
+pub struct ChangeLog {
+    /// Every block change, in the order they were made.
+    pub(super) blocks: Vec<BlockChange>,
+
+    /// Origins of the subchunks with a non-zero dirty
+    /// mask, in the order they first became dirty.
+    pub(super) dirty: Vec<SubChunkOrigin>,
+
+    /// Origins of the chunks that were inserted
+    /// into the World, in the order they were inserted.
+    pub(super) inserted: Vec<ChunkOrigin>,
+
+    /// Origins of the chunks that were removed
+    /// from the World, in the order they were removed.
+    pub(super) removed: Vec<ChunkOrigin>,
+}
+
+impl ChangeLog {
+    /// Record a change to the block at this position in the subchunk.
+    /// Changes where the old and new states are the same are ignored.
+    pub(super) fn record(&mut self, subchunk: &mut SubChunk, pos: WorldPos3, old: BlockState, new: BlockState) {
+        if old == new {
+            return;
+        }
+
+        self.blocks.push(BlockChange { pos, old, new });
+        self.mark_dirty(subchunk, pos);
+    }
+
+    /// Mark the section of the subchunk containing this position as modified.
+    pub(super) fn mark_dirty(&mut self, subchunk: &mut SubChunk, pos: WorldPos3) {
+        if subchunk.mark_dirty(pos) {
+            self.dirty.push(subchunk.origin);
+        }
+    }
+}
+
+/// Iterator over the subchunks that were modified since the dirty set
+/// was last drained and their dirty masks. The masks of the subchunks are
+/// cleared as they are visited, and any that are not visited are
+/// cleared when the iterator is dropped.
+///
+/// Subchunks that were removed from the World since they were modified
+/// are skipped.
+pub struct DrainDirty<'w> {
+    pub(super) chunks: &'w mut BTreeMap<u64, Chunk>,
+    pub(super) origins: std::vec::Drain<'w, SubChunkOrigin>,
+}
+
+impl<'w> Iterator for DrainDirty<'w> {
+    type Item = (SubChunkOrigin, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for origin in self.origins.by_ref() {
+            let subchunk = self
+                .chunks
+                .get_mut(&combine_into_u64(origin.xz()))
+                .and_then(|chunk| chunk.get_subchunk_mut(origin.y));
+
+            if let Some(subchunk) = subchunk {
+                let mask = std::mem::take(&mut subchunk.dirty);
+                if mask != 0 {
+                    return Some((origin, mask));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<'w> Drop for DrainDirty<'w> {
+    fn drop(&mut self) {
+        // masks must be cleared so the subchunks
+        // are listed again when next modified.
+        for _ in self.by_ref() {}
+    }
+}
+
+/// Get the world-space origin of each 8x8x8 section
+/// of the subchunk that is set in the dirty mask.
+pub fn dirty_sections(origin: SubChunkOrigin, mask: u64) -> impl Iterator<Item = WorldPos3> {
+    const S: i32 = DIRTY_SECTION_WIDTH as i32;
+    const N: usize = CHUNK_WIDTH / DIRTY_SECTION_WIDTH;
+    BitIterU64(mask).map(move |bit| {
+        let (y, x, z) = (bit % N, (bit / N) % N, bit / (N * N));
+        origin + IVec3::new(x as i32, y as i32, z as i32) * S
+    })
+}
+
+/// Forget the chunks that were inserted into and removed from the World.
+/// Dimensions run this at the end of every tick, after the systems that
+/// read them, so the lists don't grow in dimensions where nothing does.
+pub fn clear_chunk_changes(mut world: ResMut<World>) {
+    world.changes.inserted.clear();
+    world.changes.removed.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use chunk::to_dirty_bit;
+    use crate::{blocks::Light, data::registry::LocalID};
+
+    use super::util::*;
+    use super::*;
+
+    // the testing world has every LocalID with no light,
+    // so STONE is lit to make sure that every write is a change.
+    const STONE: BlockState = BlockState {
+        block: LocalID::new(1),
+        light: Light::from_raw(15, 0, 0, 0),
+    };
+
+    #[test]
+    fn records_block_changes() {
+        let mut world = world_for_testing();
+        let pos = IVec3::new(4, 9, -3);
+        let old = world.reader().get_block(pos).unwrap();
+
+        let mut writer = world.writer();
+        writer.set_block(pos, STONE);
+        // writing the same state again is not a change.
+        writer.set_block(pos, STONE);
+
+        let changes = world.drain_changes().collect::<Vec<_>>();
+        assert_eq!(vec![BlockChange { pos, old, new: STONE }], changes);
+        assert_eq!(0, world.drain_changes().count());
+    }
+
+    #[test]
+    fn dirty_masks() {
+        let mut world = world_for_testing();
+        let mut writer = world.writer();
+        writer.set_block(IVec3::new(1, 1, 1), STONE);
+        writer.set_block(IVec3::new(2, 3, 4), STONE);
+        writer.set_block(IVec3::new(1, 9, 17), STONE);
+        writer.set_block(IVec3::new(-1, 40, 0), STONE);
+
+        let dirty = world.drain_dirty().collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (IVec3::new(0, 0, 0), to_dirty_bit(IVec3::ZERO) | to_dirty_bit(IVec3::new(1, 9, 17))),
+                (IVec3::new(-32, 32, 0), to_dirty_bit(IVec3::new(31, 8, 0))),
+            ],
+            dirty
+        );
+
+        assert_eq!(
+            vec![IVec3::new(0, 0, 0), IVec3::new(0, 8, 16)],
+            dirty_sections(dirty[0].0, dirty[0].1).collect::<Vec<_>>()
+        );
+
+        // draining clears the masks.
+        assert_eq!(0, world.get_subchunk(IVec3::ZERO).unwrap().dirty());
+        assert_eq!(0, world.drain_dirty().count());
+    }
+
+    #[test]
+    fn dropped_drain_clears_masks() {
+        let mut world = world_for_testing();
+        let mut writer = world.writer();
+        writer.set_block(IVec3::new(1, 1, 1), STONE);
+        writer.set_block(IVec3::new(1, 40, 1), STONE);
+
+        assert_eq!(1, world.drain_dirty().take(1).count());
+        assert_eq!(0, world.get_subchunk(IVec3::new(1, 40, 1)).unwrap().dirty());
+
+        // the subchunk is listed again when it is next modified.
+        world.writer().set_block(IVec3::new(1, 40, 1), BlockState::default());
+        assert_eq!(1, world.drain_dirty().count());
+    }
+
+    #[test]
+    fn fill_records_changes() {
+        let mut world = world_for_testing();
+        world.writer().fill(BlockBox::from_extent(IVec3::new(-2, 0, 0), IVec3::new(4, 2, 1)), STONE);
+        world.writer().fill(BlockBox::from_extent(IVec3::new(-2, 0, 0), IVec3::new(4, 2, 1)), STONE);
+
+        let changes = world.drain_changes().collect::<Vec<_>>();
+        assert_eq!(8, changes.len());
+        assert!(changes.iter().all(|change| change.new == STONE));
+        assert_eq!(2, world.drain_dirty().count());
+    }
+}
@@ -1,11 +1,8 @@
-use std::ops::Shl;
-
 use bevy::math::IVec2;
 
 use crate::{blocks::Light, data::registry::LocalID};
 
 use super::*;
-use super::util::*;
 
 /// Subchunks are square, so the dims of a subchunk
 /// is CHUNK_WIDTH * CHUNK_WIDTH * CHUNK_WIDTH.
@@ -16,6 +13,13 @@ pub const CHUNK_WIDTH: usize = 32;
 /// The length of the buffer containnig blocks within a subchunk.
 pub const CHUNK_LEN: usize = CHUNK_WIDTH * CHUNK_WIDTH * CHUNK_WIDTH;
 
+/// The most subchunks a chunk can have, which
+/// makes the world MAX_SUBCHUNKS * CHUNK_WIDTH tall.
+pub const MAX_SUBCHUNKS: usize = 16;
+
+/// The height of the world. Blocks can't be written at or above it.
+pub const MAX_HEIGHT: i32 = (MAX_SUBCHUNKS * CHUNK_WIDTH) as i32;
+
 #[derive(Clone, Debug)]
 pub struct Chunk {
     /// The SubChunks that make up this chunk.
@@ -28,9 +32,50 @@ pub struct Chunk {
     /// This refers specifically to the xz coordinates.
     /// The 'Y' is always assumed to be 0.
     pub(super) origin: ChunkOrigin,
+
+    /// The height of the highest block of each
+    /// kind in every column, see `Heightmaps`.
+    pub(super) heightmaps: Heightmaps,
+
+    /// The biome of every column, see `BiomeMap`.
+    pub(super) biomes: BiomeMap,
+
+    /// How far the chunk has come through world generation.
+    pub(super) status: ChunkStatus,
 }
 
 impl Chunk {
+    /// Create a chunk with no subchunks.
+    pub const fn new(origin: ChunkOrigin) -> Self {
+        Self {
+            subchunks: Vec::new(),
+            origin,
+            heightmaps: Heightmaps::new(),
+            biomes: BiomeMap::new(),
+            status: ChunkStatus::Full,
+        }
+    }
+
+    /// Create a chunk with `height` empty subchunks, taken from the pool.
+    pub fn with_subchunks(origin: ChunkOrigin, height: usize, pool: &mut SubChunkPool) -> Self {
+        let subchunks = (0..height)
+            .map(|i| pool.alloc(IVec3::new(origin.x, (i * CHUNK_WIDTH) as i32, origin.y)))
+            .collect();
+
+        Self {
+            subchunks,
+            origin,
+            heightmaps: Heightmaps::new(),
+            biomes: BiomeMap::new(),
+            status: ChunkStatus::Full,
+        }
+    }
+
+    /// The number of subchunks in the chunk.
+    pub fn height(&self) -> usize {
+        self.subchunks.len()
+    }
+
     /// Get a block, assuming that the position is within the chunks' bounds.
     /// if the position's xz is not within the chunks' xz, the result of this
     /// operation is not guaranteed to be correct.
@@ -59,11 +104,50 @@ impl Chunk {
             .get((y as usize) / CHUNK_WIDTH)
             .map(|sub| &**sub)
     }
+
+    /// Get the subchunk within the chunk that contains the given
+    /// y value mutably, if it exists. Returns none if the value is
+    /// above or below the chunk.
+    pub fn get_subchunk_mut(&mut self, y: i32) -> Option<&mut SubChunk> {
+        if y < 0 {
+            return None;
+        }
+
+        self.subchunks
+            .get_mut((y as usize) / CHUNK_WIDTH)
+            .map(|sub| &mut **sub)
+    }
+
+    /// Get the subchunk that contains the given y value, creating
+    /// it and every subchunk below it from the pool if they do not
+    /// exist yet. Returns None if the value is below or above the world.
+    pub(super) fn get_or_insert_subchunk(&mut self, y: i32, pool: &mut SubChunkPool) -> Option<&mut SubChunk> {
+        if !(0..MAX_HEIGHT).contains(&y) {
+            return None;
+        }
+
+        let index = (y as usize) / CHUNK_WIDTH;
+        while self.subchunks.len() <= index {
+            let height = (self.subchunks.len() * CHUNK_WIDTH) as i32;
+            self.subchunks
+                .push(pool.alloc(IVec3::new(self.origin.x, height, self.origin.y)));
+        }
+
+        Some(&mut self.subchunks[index])
+    }
+
+    /// The origin of the chunk in world-space.
+    pub const fn origin(&self) -> ChunkOrigin {
+        self.origin
+    }
 }
 
 pub static EMPTY_CHUNK: Chunk = Chunk {
     subchunks: Vec::new(),
     origin: IVec2 { x: i32::MAX, y: i32::MAX },
+    heightmaps: Heightmaps::new(),
+    biomes: BiomeMap::new(),
+    status: ChunkStatus::Full,
 };
 
 #[derive(Clone, Debug)]
@@ -72,23 +156,34 @@ pub struct SubChunk {
     /// subchunk. the memory layout of this Subchunk
     /// is Y, then X, then Z. This means data is
     /// linear along the Y axis.
-    pub(super) blocks: [BlockState; CHUNK_LEN],
+    pub(super) blocks: [LocalID; CHUNK_LEN],
+
+    /// Buffer that stores the light of each block, in
+    /// the same layout as `blocks`. Light is stored
+    /// separately so light updates don't touch the
+    /// block ids and compression can dedupe blocks
+    /// that only differ in light.
+    pub(super) light: [Light; CHUNK_LEN],
 
     /// World-space origin of the subchunk, a.k.a.
     /// the coordinate with the lowest value. The
     /// first block in blocks is located at the origin,
     /// and the last block is located at origin + IVec3::splat(CHUNK_WIDTH)
     pub(super) origin: SubChunkOrigin,
+
+    /// Bitmask of the sections of the subchunk that were
+    /// modified since the mask was last drained. Each bit
+    /// is an 8x8x8 section, see `to_dirty_bit`.
+    pub(super) dirty: u64,
 }
 
 impl SubChunk {
     pub const fn new(origin: SubChunkOrigin) -> Self {
         Self {
-            blocks: [BlockState {
-                block: LocalID::new(0),
-                light: Light::ZERO,
-            }; CHUNK_LEN],
-            origin: IVec3::splat(0),
+            blocks: [LocalID::new(0); CHUNK_LEN],
+            light: [Light::ZERO; CHUNK_LEN],
+            origin,
+            dirty: 0,
         }
     }
 
@@ -96,16 +191,31 @@ impl SubChunk {
         self.origin
     }
 
-    pub const fn as_slice(&self) -> &[BlockState] {
-        &self.blocks.as_slice()
+    /// Reset the subchunk to the state of `SubChunk::new`,
+    /// so a box that held another subchunk can be reused.
+    pub fn reset(&mut self, origin: SubChunkOrigin) {
+        self.blocks.fill(LocalID::new(0));
+        self.light.fill(Light::ZERO);
+        self.origin = origin;
+        self.dirty = 0;
     }
 
-    pub fn as_slice_mut(&mut self) -> &mut [BlockState] {
-        self.blocks.as_mut_slice()
+    /// The block ids in the subchunk.
+    pub const fn ids(&self) -> &[LocalID; CHUNK_LEN] {
+        &self.blocks
     }
 
-    pub const fn as_array(&self) -> &[BlockState; CHUNK_LEN] {
-        &self.blocks
+    pub fn ids_mut(&mut self) -> &mut [LocalID; CHUNK_LEN] {
+        &mut self.blocks
+    }
+
+    /// The light of each block in the subchunk.
+    pub const fn lights(&self) -> &[Light; CHUNK_LEN] {
+        &self.light
+    }
+
+    pub fn lights_mut(&mut self) -> &mut [Light; CHUNK_LEN] {
+        &mut self.light
     }
 
     /// Get the block at this position, assuming
@@ -113,15 +223,68 @@ impl SubChunk {
     /// If the position is not iside the subchunk,
     /// this function may panic or return a non-useful result.
     pub fn get_block(&self, pos: WorldPos3) -> BlockState {
-        self.blocks[to_subchunk_index(pos)]
+        self.get_by_index(to_subchunk_index(pos))
     }
 
-    pub fn get_block_mut(&mut self, pos: WorldPos3) -> &mut BlockState {
-        &mut self.blocks[to_subchunk_index(pos)]
+    /// Get the block at an index in the range [0, CHUNK_LEN).
+    #[inline]
+    pub fn get_by_index(&self, index: usize) -> BlockState {
+        BlockState {
+            block: self.blocks[index],
+            light: self.light[index],
+        }
+    }
+
+    pub fn get_block_mut(&mut self, pos: WorldPos3) -> BlockStateMut<'_> {
+        let index = to_subchunk_index(pos);
+        BlockStateMut {
+            block: &mut self.blocks[index],
+            light: &mut self.light[index],
+        }
     }
 
     pub fn set_block(&mut self, pos: WorldPos3, state: BlockState) {
-        self.blocks[to_subchunk_index(pos)] = state;
+        self.replace_by_index(to_subchunk_index(pos), state);
+    }
+
+    /// Set the block at an index in the range [0, CHUNK_LEN),
+    /// returning the block that was replaced.
+    #[inline]
+    pub fn replace_by_index(&mut self, index: usize, state: BlockState) -> BlockState {
+        BlockState {
+            block: std::mem::replace(&mut self.blocks[index], state.block),
+            light: std::mem::replace(&mut self.light[index], state.light),
+        }
+    }
+
+    /// Get the id of the block at this position.
+    pub fn get_id(&self, pos: WorldPos3) -> LocalID {
+        self.blocks[to_subchunk_index(pos)]
+    }
+
+    /// Get the light of the block at this position.
+    pub fn get_light(&self, pos: WorldPos3) -> Light {
+        self.light[to_subchunk_index(pos)]
+    }
+
+    /// Set the light of the block at this position,
+    /// returning the light that was replaced.
+    pub fn set_light(&mut self, pos: WorldPos3, light: Light) -> Light {
+        std::mem::replace(&mut self.light[to_subchunk_index(pos)], light)
+    }
+
+    /// Bitmask of the 8x8x8 sections of the subchunk that
+    /// were modified since the world's dirty set was drained.
+    pub const fn dirty(&self) -> u64 {
+        self.dirty
+    }
+
+    /// Mark the section containing this position as modified,
+    /// returning true if the subchunk was not dirty before.
+    pub(super) fn mark_dirty(&mut self, pos: WorldPos3) -> bool {
+        let was_clean = self.dirty == 0;
+        self.dirty |= to_dirty_bit(to_local_pos(pos));
+        was_clean
     }
 
     /// Returns true if the subchunk contains the position.
@@ -135,6 +298,32 @@ impl SubChunk {
     }
 }
 
+/// Mutable access to a block in a subchunk, which
+/// stores the id and light of a block separately.
+#[derive(Debug)]
+pub struct BlockStateMut<'a> {
+    pub block: &'a mut LocalID,
+    pub light: &'a mut Light,
+}
+
+impl<'a> BlockStateMut<'a> {
+    /// Read the block as a BlockState.
+    pub fn get(&self) -> BlockState {
+        BlockState {
+            block: *self.block,
+            light: *self.light,
+        }
+    }
+
+    /// Overwrite the block, returning the block that was replaced.
+    pub fn set(&mut self, state: BlockState) -> BlockState {
+        BlockState {
+            block: std::mem::replace(self.block, state.block),
+            light: std::mem::replace(self.light, state.light),
+        }
+    }
+}
+
 /// Compute the index of the block at the world-space coordinates
 /// within this subchunk. If the provided position is not within
 /// the subchunk, the result of this operation is not guaranteed
@@ -155,12 +344,23 @@ pub const fn to_subchunk_index_prewrapped(pos: LocalPos3) -> usize {
     (pos.y + pos.x * CHUNK_WIDTH as i32 + pos.z * CHUNK_WIDTH as i32 * CHUNK_WIDTH as i32) as usize
 }
 
+/// The width of a section in a subchunk's dirty mask.
+pub const DIRTY_SECTION_WIDTH: usize = 8;
+
+/// Compute the bit in a subchunk's dirty mask that contains the local position.
+/// Like the blocks of a subchunk, the bits are ordered Y, then X, then Z.
+#[inline]
+pub const fn to_dirty_bit(pos: LocalPos3) -> u64 {
+    const S: i32 = DIRTY_SECTION_WIDTH as i32;
+    const N: i32 = (CHUNK_WIDTH / DIRTY_SECTION_WIDTH) as i32;
+    1 << (pos.y / S + (pos.x / S) * N + (pos.z / S) * N * N)
+}
+
 pub static EMPTY_SUBCHUNK: SubChunk = SubChunk {
-    blocks: [BlockState {
-        block: LocalID::new(0),
-        light: Light::default(),
-    }; CHUNK_LEN],
+    blocks: [LocalID::new(0); CHUNK_LEN],
+    light: [Light::default(); CHUNK_LEN],
     origin: IVec3::splat(i32::MAX),
+    dirty: 0,
 };
 
 /// Convert a world position to a position within a subchunk.
@@ -193,6 +393,7 @@ pub fn to_origin_local(pos: WorldPos3) -> (SubChunkOrigin, LocalPos3) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::util::*;
 
     #[test]
     fn test_to_subchunk_origin() {
@@ -212,11 +413,11 @@ mod tests {
         assert_eq!(1, subchunk.get_block(IVec3::new(0, 1, 0)).block.index());
         assert_eq!(2, subchunk.get_block(IVec3::new(0, 2, 0)).block.index());
         assert_eq!(
-            CHUNK_LEN as u32 - 1,
+            CHUNK_LEN as u16 - 1,
             subchunk.get_block(IVec3::splat((CHUNK_WIDTH - 1) as i32)).block.index()
         );
         assert_eq!(
-            CHUNK_WIDTH as u32 - 1,
+            CHUNK_WIDTH as u16 - 1,
             subchunk
                 .get_block(IVec3::new(0, CHUNK_WIDTH as i32 - 1, 0))
                 .block
@@ -1,11 +1,13 @@
-
 use std::marker::PhantomData;
 
-use bevy::math::IVec2;
+use bevy::math::{IVec2, Vec3Swizzles};
 
-use crate::blocks::BlockState;
+use crate::blocks::{BlockState, Light};
 
-use super::{Chunk, WorldPos3};
+use super::cached::CACHED_NEIGHBOUR_CHUNK_BOUNDARIES;
+use super::chunk::{to_subchunk_index, BlockStateMut, CHUNK_WIDTH};
+use super::reader::Neighbours;
+use super::{ChangeLog, Chunk, ChunkOrigin, HeightmapTable, SubChunk, SubChunkPool, WorldPos3};
 
 #[derive(Clone)]
 pub struct Cluster2x2<'w> {
@@ -19,20 +21,371 @@ pub struct Cluster3x3<'w> {
     pub(in super) origin: IVec2,
 }
 
-impl<'w> Cluster3x3<'w> {
-    pub fn get_block(&self, pos: WorldPos3) -> Option<BlockState> {
-        todo!()
-    }
-}
-
+/// A 2x2 Cluster that can be mutated.
+///
+/// Blocks written with `set_block` are recorded in the World's
+/// ChangeLog, like a WorldWriter. Writes through `get_block_mut`
+/// and `get_subchunk_mut` are not recorded, and do not update
+/// the heightmaps of the chunk.
 pub struct ClusterMut2x2<'w> {
     pub(in super) chunks: [[*mut Chunk; 2]; 2],
+    pub(in super) changes: &'w mut ChangeLog,
+    pub(in super) pool: &'w mut SubChunkPool,
+    pub(in super) heightmaps: &'w HeightmapTable,
     pub(in super) marker: PhantomData<&'w i32>,
     pub(in super) origin: IVec2
 }
 
+/// A 3x3 Cluster that can be mutated.
+///
+/// Blocks written with `set_block` are recorded in the World's
+/// ChangeLog, like a WorldWriter. Writes through `get_block_mut`
+/// and `get_subchunk_mut` are not recorded, and do not update
+/// the heightmaps of the chunk.
 pub struct ClusterMut3x3<'w> {
     pub(in super) chunks: [[*mut Chunk; 3]; 3],
+    pub(in super) changes: &'w mut ChangeLog,
+    pub(in super) pool: &'w mut SubChunkPool,
+    pub(in super) heightmaps: &'w HeightmapTable,
     pub(in super) marker: PhantomData<&'w i32>,
     pub(in super) origin: IVec2,
 }
+
+/// Read access shared by all clusters. The cluster must implement
+/// `chunk_at(x, z)`, which gets the chunk at an index in the cluster.
+macro_rules! impl_cluster_read {
+    ($cluster:ident, $n:literal) => {
+        impl<'w> $cluster<'w> {
+            /// The width of the cluster, in chunks.
+            pub const WIDTH: usize = $n;
+
+            /// The origin of the chunk in the cluster with the lowest coordinates.
+            pub fn origin(&self) -> ChunkOrigin {
+                self.origin
+            }
+
+            /// Returns true if the xz of the position is within the cluster.
+            pub fn contains(&self, pos: WorldPos3) -> bool {
+                self.chunk_index(pos).is_some()
+            }
+
+            /// Get the chunk containing the position, or None if
+            /// the position is outside of the cluster.
+            pub fn get_chunk(&self, pos: WorldPos3) -> Option<&Chunk> {
+                let (x, z) = self.chunk_index(pos)?;
+                Some(self.chunk_at(x, z))
+            }
+
+            /// Get the subchunk containing the position, or None if the position
+            /// is outside of the cluster or above or below the chunk.
+            pub fn get_subchunk(&self, pos: WorldPos3) -> Option<&SubChunk> {
+                self.get_chunk(pos)?.get_subchunk(pos.y)
+            }
+
+            /// Get the block at the position, or None if the position is
+            /// outside of the cluster or above or below the chunk.
+            pub fn get_block(&self, pos: WorldPos3) -> Option<BlockState> {
+                Some(self.get_subchunk(pos)?.get_block(pos))
+            }
+
+            /// Get the 6 blocks that are neighbours of this block. Neighbours
+            /// that are outside of the cluster are `BlockState::default()`.
+            pub fn neighbours(&self, pos: WorldPos3) -> Neighbours {
+                const W: usize = CHUNK_WIDTH;
+                let index = to_subchunk_index(pos);
+
+                // if the block is not on the edge of a subchunk,
+                // all of its neighbours are in the same subchunk.
+                if CACHED_NEIGHBOUR_CHUNK_BOUNDARIES[index] == 0 {
+                    if let Some(center) = self.get_subchunk(pos) {
+                        return Neighbours::new([
+                            center.get_by_index(index + 1),
+                            center.get_by_index(index - 1),
+                            center.get_by_index(index + W),
+                            center.get_by_index(index - W),
+                            center.get_by_index(index + W * W),
+                            center.get_by_index(index - W * W),
+                        ]);
+                    }
+                }
+
+                Neighbours::new(
+                    Neighbours::DIRS.map(|dir| self.get_block(dir + pos).unwrap_or_default()),
+                )
+            }
+
+            /// The index of the chunk containing the position in the cluster.
+            fn chunk_index(&self, pos: WorldPos3) -> Option<(usize, usize)> {
+                const W: i32 = CHUNK_WIDTH as i32;
+                let local = pos.xz() - self.origin;
+                if local.x < 0 || local.y < 0 || local.x >= W * $n || local.y >= W * $n {
+                    None
+                } else {
+                    Some(((local.x / W) as usize, (local.y / W) as usize))
+                }
+            }
+        }
+    };
+}
+
+/// Write access shared by the mutable clusters.
+macro_rules! impl_cluster_write {
+    ($cluster:ident) => {
+        impl<'w> $cluster<'w> {
+            /// Get the chunk at an index in the cluster.
+            fn chunk_at(&self, x: usize, z: usize) -> &Chunk {
+                // the pointer is valid for 'w, and the cluster
+                // is borrowed immutably for the returned lifetime.
+                unsafe { &*self.chunks[z][x] }
+            }
+
+            /// Get the chunk containing the position mutably, or
+            /// None if the position is outside of the cluster.
+            pub fn get_chunk_mut(&mut self, pos: WorldPos3) -> Option<&mut Chunk> {
+                let (x, z) = self.chunk_index(pos)?;
+                // the pointer is valid for 'w, and the cluster
+                // is borrowed mutably for the returned lifetime.
+                Some(unsafe { &mut *self.chunks[z][x] })
+            }
+
+            /// Get the subchunk containing the position mutably, or None if the
+            /// position is outside of the cluster or above or below the chunk.
+            pub fn get_subchunk_mut(&mut self, pos: WorldPos3) -> Option<&mut SubChunk> {
+                self.get_chunk_mut(pos)?.get_subchunk_mut(pos.y)
+            }
+
+            /// Get the block at the position mutably, or None if the position
+            /// is outside of the cluster or above or below the chunk. Writes
+            /// through the returned reference are not recorded as changes.
+            pub fn get_block_mut(&mut self, pos: WorldPos3) -> Option<BlockStateMut<'_>> {
+                Some(self.get_subchunk_mut(pos)?.get_block_mut(pos))
+            }
+
+            /// Set the block at this position, returning the block that was
+            /// replaced. Missing subchunks are created up to the position from
+            /// the World's pool, like a WorldWriter, and the chunk's heightmaps are updated. Returns
+            /// None if the position is outside of the cluster or below or above the world.
+            pub fn set_block(&mut self, pos: WorldPos3, state: BlockState) -> Option<BlockState> {
+                let (x, z) = self.chunk_index(pos)?;
+                // the pointer is valid for 'w and does not alias the ChangeLog or pool.
+                let chunk = unsafe { &mut *self.chunks[z][x] };
+                let subchunk = chunk.get_or_insert_subchunk(pos.y, self.pool)?;
+                let old = subchunk.get_block_mut(pos).set(state);
+                self.changes.record(subchunk, pos, old, state);
+                chunk.update_heightmaps(pos.xz(), pos.y, pos.y + 1, state, self.heightmaps);
+                Some(old)
+            }
+
+            /// Set the light of the block at this position, returning
+            /// the light that was replaced. Returns None if the block
+            /// does not exist in the cluster.
+            pub fn set_light(&mut self, pos: WorldPos3, light: Light) -> Option<Light> {
+                let (x, z) = self.chunk_index(pos)?;
+                // the pointer is valid for 'w and does not alias the ChangeLog.
+                let subchunk = unsafe { &mut *self.chunks[z][x] }.get_subchunk_mut(pos.y)?;
+                let old = subchunk.set_light(pos, light);
+                if old != light {
+                    self.changes.mark_dirty(subchunk, pos);
+                }
+                Some(old)
+            }
+        }
+    };
+}
+
+impl<'w> Cluster2x2<'w> {
+    fn chunk_at(&self, x: usize, z: usize) -> &'w Chunk {
+        self.chunks[z][x]
+    }
+}
+
+impl<'w> Cluster3x3<'w> {
+    fn chunk_at(&self, x: usize, z: usize) -> &'w Chunk {
+        self.chunks[z][x]
+    }
+}
+
+impl_cluster_read!(Cluster2x2, 2);
+impl_cluster_read!(Cluster3x3, 3);
+impl_cluster_read!(ClusterMut2x2, 2);
+impl_cluster_read!(ClusterMut3x3, 3);
+impl_cluster_write!(ClusterMut2x2);
+impl_cluster_write!(ClusterMut3x3);
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::IVec3;
+
+    use crate::data::registry::LocalID;
+
+    use super::super::util::*;
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    const STONE: BlockState = BlockState {
+        block: LocalID::new(1),
+        light: Light::from_raw(15, 0, 0, 0),
+    };
+
+    /// Every position on the boundary of a chunk
+    /// in a cluster with this origin and width.
+    fn boundaries(origin: IVec2, n: i32) -> Vec<WorldPos3> {
+        let mut result = Vec::new();
+        for z in origin.y..origin.y + W * n {
+            for x in origin.x..origin.x + W * n {
+                let local = IVec2::new(x, z) - origin;
+                let on_edge = |n: i32| n % W == 0 || n % W == W - 1;
+                if !on_edge(local.x) && !on_edge(local.y) {
+                    continue;
+                }
+                for y in [0, 1, W - 1, W, W * 2, W * 3 - 1] {
+                    result.push(IVec3::new(x, y, z));
+                }
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn cluster_3x3_blocks() {
+        let world = world_for_testing();
+        let reader = world.reader();
+        let cluster = world.cluster_3x3(IVec3::new(4, 4, 4)).unwrap();
+        assert_eq!(IVec2::splat(-W), cluster.origin());
+
+        for pos in boundaries(cluster.origin(), 3) {
+            assert_eq!(reader.get_block(pos), cluster.get_block(pos), "at: {pos}");
+            assert_eq!(
+                reader.get_subchunk(pos).map(|sub| sub.origin()),
+                cluster.get_subchunk(pos).map(|sub| sub.origin()),
+                "at: {pos}"
+            );
+        }
+    }
+
+    #[test]
+    fn cluster_3x3_bounds() {
+        let world = world_for_testing();
+        let cluster = world.cluster_3x3(IVec3::new(0, 0, 0)).unwrap();
+        assert!(cluster.get_block(IVec3::new(-W - 1, 0, 0)).is_none());
+        assert!(cluster.get_block(IVec3::new(0, 0, W * 2)).is_none());
+        assert!(cluster.get_block(IVec3::new(0, -1, 0)).is_none());
+        assert!(cluster.get_block(IVec3::new(0, W * 3, 0)).is_none());
+        assert!(cluster.get_block(IVec3::new(-W, 0, W * 2 - 1)).is_some());
+
+        // clusters need every chunk to be loaded.
+        assert!(world.cluster_3x3(IVec3::new(W, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn cluster_2x2_blocks() {
+        let world = world_for_testing();
+        let reader = world.reader();
+
+        for (pos, origin) in [
+            (IVec3::new(4, 0, 4), IVec2::new(-W, -W)),
+            (IVec3::new(20, 0, 4), IVec2::new(0, -W)),
+            (IVec3::new(4, 0, 20), IVec2::new(-W, 0)),
+            (IVec3::new(20, 0, 20), IVec2::new(0, 0)),
+        ] {
+            let cluster = world.cluster_2x2(pos).unwrap();
+            assert_eq!(origin, cluster.origin());
+
+            for offset in [IVec3::new(16, 0, 16), IVec3::new(-16, 0, -16), IVec3::new(-16, 0, 16)] {
+                assert!(cluster.contains(pos + offset));
+            }
+
+            for pos in boundaries(cluster.origin(), 2) {
+                assert_eq!(reader.get_block(pos), cluster.get_block(pos), "at: {pos}");
+            }
+        }
+    }
+
+    #[test]
+    fn cluster_neighbours() {
+        let world = world_for_testing();
+        let reader = world.reader();
+        let cluster = world.cluster_3x3(IVec3::ZERO).unwrap();
+        let pairs = |pos| {
+            cluster
+                .neighbours(pos)
+                .map(|(dir, state)| (dir, state.block))
+                .collect::<Vec<_>>()
+        };
+        let expected = |pos| {
+            reader
+                .neighbours(pos)
+                .map(|(dir, state)| (dir, state.block))
+                .collect::<Vec<_>>()
+        };
+
+        for pos in boundaries(cluster.origin(), 3) {
+            // the reader can see outside of the cluster.
+            let outside = Neighbours::DIRS.iter().any(|dir| !cluster.contains(*dir + pos));
+            if !outside {
+                assert_eq!(expected(pos), pairs(pos), "at: {pos}");
+            }
+        }
+
+        let interior = IVec3::new(5, 6, 7);
+        assert_eq!(expected(interior), pairs(interior));
+    }
+
+    #[test]
+    fn cluster_mut_3x3_set_blocks() {
+        let mut world = world_for_testing();
+        let mut cluster = world.cluster_mut_3x3(IVec3::new(4, 4, 4)).unwrap();
+        let positions = boundaries(cluster.origin(), 3);
+
+        for pos in &positions {
+            let old = cluster.get_block(*pos).unwrap();
+            assert_eq!(Some(old), cluster.set_block(*pos, STONE));
+        }
+        assert!(cluster.set_block(IVec3::new(W * 2, 0, 0), STONE).is_none());
+
+        let reader = world.reader();
+        for pos in &positions {
+            assert_eq!(Some(STONE), reader.get_block(*pos), "at: {pos}");
+        }
+        assert_eq!(positions.len(), world.drain_changes().count());
+    }
+
+    #[test]
+    fn cluster_mut_2x2_set_blocks() {
+        let mut world = world_for_testing();
+        let mut cluster = world.cluster_mut_2x2(IVec3::new(4, 0, 4)).unwrap();
+        let positions = boundaries(cluster.origin(), 2);
+
+        for pos in &positions {
+            cluster.set_block(*pos, STONE).unwrap();
+            assert_eq!(Some(STONE), cluster.get_block(*pos));
+        }
+
+        // writing above the chunk creates subchunks.
+        let high = IVec3::new(-1, W * 5, -1);
+        assert_eq!(Some(BlockState { block: LocalID::new(0), light: Light::ZERO }), cluster.set_block(high, STONE));
+
+        let reader = world.reader();
+        for pos in positions.iter().chain([&high]) {
+            assert_eq!(Some(STONE), reader.get_block(*pos), "at: {pos}");
+        }
+    }
+
+    #[test]
+    fn cluster_mut_get_block_mut() {
+        let mut world = world_for_testing();
+        let pos = IVec3::new(-1, W - 1, W);
+        {
+            let mut cluster = world.cluster_mut_3x3(IVec3::ZERO).unwrap();
+            let block = cluster.get_block_mut(pos).unwrap();
+            *block.light = Light::from_raw(3, 0, 0, 0);
+            assert_eq!(Light::from_raw(3, 0, 0, 0), block.get().light);
+            assert!(cluster.get_block_mut(IVec3::new(0, W * 3, 0)).is_none());
+        }
+
+        assert_eq!(Light::from_raw(3, 0, 0, 0), world.reader().get_block(pos).unwrap().light);
+        // writes through get_block_mut are not recorded.
+        assert_eq!(0, world.drain_changes().count());
+    }
+}
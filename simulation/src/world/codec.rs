@@ -0,0 +1,723 @@
+//! The binary encoding of chunks and subchunks, used for saving the world.
+//!
+//! Blocks and biomes are stored by GlobalID, because LocalIDs are not
+//! consistent across versions. Every encoding is laid out as follows, where all
+//! integers are little-endian:
+//!
+//! - magic: `b"MCRE"`
+//! - version: u16, see `FORMAT_VERSION`
+//! - kind: u8, 0 for a chunk and 1 for a subchunk
+//! - origin: 2 i32s for a chunk, 3 i32s for a subchunk
+//! - status: u8 for a chunk, the index of its `ChunkStatus` in `ChunkStatus::ALL`
+//! - biomes: for a chunk, u16 length of the biome palette, then the GlobalID
+//!   (u32) of each biome, then u16 count of runs, then each run of columns
+//!   as the u16 index of its biome in the palette and the u16 number of
+//!   columns in the run
+//! - palette: u16 length, then the GlobalID (u32) of each block
+//! - subchunks: u16 count for a chunk, at most `MAX_SUBCHUNKS`, then each subchunk
+//! - checksum: u32, the xxh32 of every preceding byte
+//!
+//! Each subchunk is a pair of paletted containers, see `PalettedContainer`.
+//! The block container's palette holds indices into the palette above, and
+//! the light container's palette holds packed lights:
+//!
+//! - bits: u8
+//! - palette: u16 length, then each u16 entry
+//! - data: u32 length, then each u64 word
+//!
+//! The runs of biomes cover the columns in the order of `BiomeMap::biomes`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use xxhash_rust::xxh32::xxh32;
+
+use crate::blocks::{Block, Light};
+use crate::data::registry::{GlobalID, LocalID};
+use crate::data::Registry;
+use crate::terrain::Biome;
+
+use super::*;
+
+/// The version of the format written by `encode_chunk` and `encode_subchunk`.
+pub const FORMAT_VERSION: u16 = 1;
+
+const MAGIC: [u8; 4] = *b"MCRE";
+const CHECKSUM_SEED: u32 = 0x4D435245;
+const KIND_CHUNK: u8 = 0;
+const KIND_SUBCHUNK: u8 = 1;
+
+/// The smallest encoded subchunk: two containers of a single
+/// value, which have the bits, a palette of one entry and no data.
+const MIN_SUBCHUNK_LEN: usize = 2 * (1 + 2 + 2 + 4);
+
+/// The reasons an encoded chunk can fail to decode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChunkDecodeError {
+    /// The data does not start with the magic bytes,
+    /// so it is not an encoded chunk.
+    BadMagic,
+
+    /// The data was written by a version of the format
+    /// that this version does not know how to read.
+    UnsupportedVersion(u16),
+
+    /// The data ended before the chunk was fully read.
+    Truncated,
+
+    /// The checksum of the data does not match the checksum
+    /// that was written, so the data was corrupted.
+    ChecksumMismatch { expected: u32, actual: u32 },
+
+    /// A block in the palette is not in the block registry.
+    UnknownBlock(GlobalID),
+
+    /// A biome in the biome palette is not in the biome registry.
+    UnknownBiome(GlobalID),
+
+    /// The data is well-formed, but describes an invalid chunk.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for ChunkDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "data is not an encoded chunk"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "chunk format version {version} is not supported, expected version {FORMAT_VERSION}"
+            ),
+            Self::Truncated => write!(f, "encoded chunk is truncated"),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "encoded chunk checksum is {actual:#010x}, expected {expected:#010x}"
+            ),
+            Self::UnknownBlock(global) => {
+                write!(f, "block with GlobalID {:#010x} is not registered", global.hash())
+            }
+            Self::UnknownBiome(global) => {
+                write!(f, "biome with GlobalID {:#010x} is not registered", global.hash())
+            }
+            Self::Corrupt(reason) => write!(f, "encoded chunk is corrupt: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ChunkDecodeError {}
+
+/// Encode a chunk, storing blocks and biomes by their GlobalID in the registries.
+pub fn encode_chunk(chunk: &Chunk, blocks: &Registry<Block>, biomes: &Registry<Biome>) -> Vec<u8> {
+    let compressed: Vec<_> = chunk.subchunks.iter().map(|sub| sub.compress()).collect();
+    let mut out = header(KIND_CHUNK);
+    out.extend_from_slice(&chunk.origin.x.to_le_bytes());
+    out.extend_from_slice(&chunk.origin.y.to_le_bytes());
+    out.push(chunk.status as u8);
+    write_biomes(&mut out, &chunk.biomes, biomes);
+
+    let palette = Palette::new(&compressed);
+    palette.write(&mut out, blocks);
+
+    out.extend_from_slice(&(compressed.len() as u16).to_le_bytes());
+    for subchunk in &compressed {
+        write_subchunk(&mut out, subchunk, &palette);
+    }
+
+    footer(out)
+}
+
+/// Decode a chunk that was encoded with `encode_chunk`, mapping the
+/// GlobalIDs of blocks and biomes to LocalIDs in the registries.
+pub fn decode_chunk(
+    bytes: &[u8],
+    blocks: &Registry<Block>,
+    biomes: &Registry<Biome>,
+) -> Result<Chunk, ChunkDecodeError> {
+    decode_chunk_with(bytes, blocks, biomes, &mut SubChunkPool::new(0))
+}
+
+/// Decode a chunk like `decode_chunk`, taking its subchunks from the pool.
+pub fn decode_chunk_with(
+    bytes: &[u8],
+    blocks: &Registry<Block>,
+    biomes: &Registry<Biome>,
+    pool: &mut SubChunkPool,
+) -> Result<Chunk, ChunkDecodeError> {
+    let mut reader = Reader::new(bytes, KIND_CHUNK)?;
+    let origin = IVec2::new(reader.i32()?, reader.i32()?);
+    if to_chunk_origin(origin) != origin {
+        return Err(ChunkDecodeError::Corrupt("chunk origin is not a multiple of the chunk width"));
+    }
+
+    let status = *ChunkStatus::ALL
+        .get(reader.u8()? as usize)
+        .ok_or(ChunkDecodeError::Corrupt("chunk status is unknown"))?;
+    let biomes = reader.biomes(biomes)?;
+
+    let palette = reader.palette(blocks)?;
+    // every subchunk is taken from the pool before any of them are read,
+    // and even the smallest encoding decodes into a whole subchunk, so
+    // the count is checked against the height of the world first.
+    let count = reader.u16()? as usize;
+    if count > MAX_SUBCHUNKS {
+        return Err(ChunkDecodeError::Corrupt("chunk is taller than the world"));
+    }
+    if count > reader.bytes.len() / MIN_SUBCHUNK_LEN {
+        return Err(ChunkDecodeError::Truncated);
+    }
+    let mut chunk = Chunk::with_subchunks(origin, count, pool);
+    chunk.status = status;
+    chunk.biomes = biomes;
+    for i in 0..count {
+        let origin = chunk.subchunks[i].origin;
+        match reader.subchunk(&palette, origin) {
+            Ok(compressed) => compressed.decompress_into(&mut chunk.subchunks[i]),
+            Err(err) => {
+                pool.free_chunk(chunk);
+                return Err(err);
+            }
+        }
+    }
+
+    reader.finish()?;
+    Ok(chunk)
+}
+
+/// Encode a single subchunk, storing blocks by their GlobalID in the registry.
+pub fn encode_subchunk(subchunk: &SubChunk, blocks: &Registry<Block>) -> Vec<u8> {
+    let compressed = subchunk.compress();
+    let mut out = header(KIND_SUBCHUNK);
+    for axis in subchunk.origin.to_array() {
+        out.extend_from_slice(&axis.to_le_bytes());
+    }
+
+    let palette = Palette::new(std::slice::from_ref(&compressed));
+    palette.write(&mut out, blocks);
+    write_subchunk(&mut out, &compressed, &palette);
+    footer(out)
+}
+
+/// Decode a subchunk that was encoded with `encode_subchunk`, mapping
+/// the GlobalIDs of blocks to LocalIDs in the registry.
+pub fn decode_subchunk(bytes: &[u8], blocks: &Registry<Block>) -> Result<Box<SubChunk>, ChunkDecodeError> {
+    let mut reader = Reader::new(bytes, KIND_SUBCHUNK)?;
+    let origin = IVec3::new(reader.i32()?, reader.i32()?, reader.i32()?);
+    if origin.y < 0 || to_subchunk_origin(origin) != origin {
+        return Err(ChunkDecodeError::Corrupt("subchunk origin is not a valid subchunk origin"));
+    }
+
+    let palette = reader.palette(blocks)?;
+    let mut subchunk = Box::new(SubChunk::new(origin));
+    reader.subchunk(&palette, origin)?.decompress_into(&mut subchunk);
+    reader.finish()?;
+    Ok(subchunk)
+}
+
+fn header(kind: u8) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4096);
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.push(kind);
+    out
+}
+
+fn footer(mut out: Vec<u8>) -> Vec<u8> {
+    let checksum = xxh32(&out, CHECKSUM_SEED);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+/// The blocks used by the subchunks being encoded,
+/// in the order they first appear.
+struct Palette {
+    ids: Vec<LocalID>,
+    indices: HashMap<LocalID, u16>,
+}
+
+impl Palette {
+    fn new(subchunks: &[CompressedSubChunk]) -> Self {
+        let mut palette = Self {
+            ids: Vec::new(),
+            indices: HashMap::new(),
+        };
+
+        for id in subchunks.iter().flat_map(|sub| sub.blocks.palette()) {
+            if !palette.indices.contains_key(id) {
+                palette.indices.insert(*id, palette.ids.len() as u16);
+                palette.ids.push(*id);
+            }
+        }
+
+        palette
+    }
+
+    fn write(&self, out: &mut Vec<u8>, blocks: &Registry<Block>) {
+        out.extend_from_slice(&(self.ids.len() as u16).to_le_bytes());
+        for id in &self.ids {
+            out.extend_from_slice(&blocks.get_by_local(*id).global_id().hash().to_le_bytes());
+        }
+    }
+}
+
+/// Write the biome of every column as runs of the same biome,
+/// since neighbouring columns mostly share a biome.
+fn write_biomes(out: &mut Vec<u8>, map: &BiomeMap, biomes: &Registry<Biome>) {
+    // a chunk only has a few biomes, so the palette is searched linearly.
+    let mut palette: Vec<LocalID> = Vec::new();
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for biome in map.biomes() {
+        let index = match palette.iter().position(|id| id == biome) {
+            Some(index) => index,
+            None => {
+                palette.push(*biome);
+                palette.len() - 1
+            }
+        } as u16;
+
+        match runs.last_mut() {
+            Some((last, len)) if *last == index => *len += 1,
+            _ => runs.push((index, 1)),
+        }
+    }
+
+    out.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for id in palette {
+        out.extend_from_slice(&biomes.get_by_local(id).global_id().hash().to_le_bytes());
+    }
+
+    out.extend_from_slice(&(runs.len() as u16).to_le_bytes());
+    for (index, len) in runs {
+        out.extend_from_slice(&index.to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes());
+    }
+}
+
+fn write_subchunk(out: &mut Vec<u8>, subchunk: &CompressedSubChunk, palette: &Palette) {
+    write_container(out, &subchunk.blocks, |id| palette.indices[&id]);
+    write_container(out, &subchunk.light, |light| light.to_bits());
+}
+
+fn write_container<T: Copy + Eq + std::hash::Hash>(
+    out: &mut Vec<u8>,
+    container: &PalettedContainer<T>,
+    entry: impl Fn(T) -> u16,
+) {
+    out.push(container.bits() as u8);
+    out.extend_from_slice(&(container.palette().len() as u16).to_le_bytes());
+    for value in container.palette() {
+        out.extend_from_slice(&entry(*value).to_le_bytes());
+    }
+    out.extend_from_slice(&(container.data().len() as u32).to_le_bytes());
+    for word in container.data() {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+}
+
+/// Reads the body of an encoding, after the header
+/// has been checked and before the checksum.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Check the header and checksum of the data,
+    /// returning a reader over the body.
+    fn new(bytes: &'a [u8], kind: u8) -> Result<Self, ChunkDecodeError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(ChunkDecodeError::BadMagic);
+        }
+
+        let mut reader = Self { bytes: &bytes[MAGIC.len()..] };
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(ChunkDecodeError::UnsupportedVersion(version));
+        }
+
+        let (data, checksum) = bytes
+            .len()
+            .checked_sub(4)
+            .filter(|len| *len >= MAGIC.len() + 3)
+            .map(|len| bytes.split_at(len))
+            .ok_or(ChunkDecodeError::Truncated)?;
+        let expected = u32::from_le_bytes(checksum.try_into().unwrap());
+        let actual = xxh32(data, CHECKSUM_SEED);
+        if expected != actual {
+            return Err(ChunkDecodeError::ChecksumMismatch { expected, actual });
+        }
+
+        reader.bytes = &data[MAGIC.len() + 2..];
+        if reader.u8()? != kind {
+            return Err(ChunkDecodeError::Corrupt("data is not the expected kind of encoding"));
+        }
+
+        Ok(reader)
+    }
+
+    /// Make sure every byte of the body was read.
+    fn finish(self) -> Result<(), ChunkDecodeError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(ChunkDecodeError::Corrupt("unexpected data after the end of the chunk"))
+        }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ChunkDecodeError> {
+        if self.bytes.len() < N {
+            return Err(ChunkDecodeError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(taken.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, ChunkDecodeError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ChunkDecodeError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, ChunkDecodeError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ChunkDecodeError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, ChunkDecodeError> {
+        Ok(i32::from_le_bytes(self.take()?))
+    }
+
+    /// Read the palette, mapping each GlobalID to its LocalID.
+    fn palette(&mut self, blocks: &Registry<Block>) -> Result<Vec<LocalID>, ChunkDecodeError> {
+        let len = self.u16()?;
+        (0..len)
+            .map(|_| {
+                let global = GlobalID::from_hash(self.u32()?);
+                blocks
+                    .get_by_global(global)
+                    .map(|entry| entry.local_id())
+                    .ok_or(ChunkDecodeError::UnknownBlock(global))
+            })
+            .collect()
+    }
+
+    /// Read the biome palette, mapping each GlobalID to its LocalID,
+    /// and the runs of biomes, which must cover every column exactly.
+    fn biomes(&mut self, registry: &Registry<Biome>) -> Result<BiomeMap, ChunkDecodeError> {
+        let len = self.u16()?;
+        let palette = (0..len)
+            .map(|_| {
+                let global = GlobalID::from_hash(self.u32()?);
+                registry
+                    .get_by_global(global)
+                    .map(|entry| entry.local_id())
+                    .ok_or(ChunkDecodeError::UnknownBiome(global))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut biomes = BiomeMap::new();
+        let mut column = 0;
+        for _ in 0..self.u16()? {
+            let biome = *palette
+                .get(self.u16()? as usize)
+                .ok_or(ChunkDecodeError::Corrupt("biome is not in the palette"))?;
+            let len = self.u16()? as usize;
+            if len > CHUNK_WIDTH * CHUNK_WIDTH - column {
+                return Err(ChunkDecodeError::Corrupt("biomes cover more than every column"));
+            }
+
+            for i in column..column + len {
+                let pos = IVec2::new((i % CHUNK_WIDTH) as i32, (i / CHUNK_WIDTH) as i32);
+                biomes.set(pos, biome);
+            }
+            column += len;
+        }
+
+        if column != CHUNK_WIDTH * CHUNK_WIDTH {
+            return Err(ChunkDecodeError::Corrupt("biomes don't cover every column"));
+        }
+        Ok(biomes)
+    }
+
+    fn subchunk(
+        &mut self,
+        palette: &[LocalID],
+        origin: SubChunkOrigin,
+    ) -> Result<CompressedSubChunk, ChunkDecodeError> {
+        let blocks = self.container(|index| {
+            palette
+                .get(index as usize)
+                .copied()
+                .ok_or(ChunkDecodeError::Corrupt("block is not in the palette"))
+        })?;
+        let light = self.container(|bits| Ok(Light::from_bits(bits)))?;
+
+        Ok(CompressedSubChunk {
+            blocks,
+            light,
+            origin,
+        })
+    }
+
+    fn container<T: Copy + Eq + std::hash::Hash>(
+        &mut self,
+        entry: impl Fn(u16) -> Result<T, ChunkDecodeError>,
+    ) -> Result<PalettedContainer<T>, ChunkDecodeError> {
+        let bits = self.u8()? as u32;
+        let palette = (0..self.u16()?)
+            .map(|_| entry(self.u16()?))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // the length is checked against the remaining bytes first,
+        // so corrupt data can't make us allocate a huge buffer.
+        let len = self.u32()? as usize;
+        if len > self.bytes.len() / 8 {
+            return Err(ChunkDecodeError::Truncated);
+        }
+        let data = (0..len).map(|_| self.u64()).collect::<Result<Vec<_>, _>>()?;
+
+        PalettedContainer::from_raw_parts(palette, bits, data)
+            .ok_or(ChunkDecodeError::Corrupt("paletted container is invalid"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::Id;
+    use crate::world::util::{biomes_for_testing, blocks_for_testing};
+
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    /// Terrain with a few blocks of each kind, with some light.
+    fn chunk(origin: IVec2) -> Chunk {
+        let mut pool = SubChunkPool::default();
+        let mut chunk = Chunk::new(origin);
+
+        for y in 0..W * 2 + 5 {
+            for z in 0..W {
+                for x in 0..W {
+                    let id = match y {
+                        _ if (x * 7 + y * 13 + z * 5) % 61 == 0 => 4,
+                        0..40 => 1,
+                        40..43 => 2,
+                        43 => 3,
+                        _ => 0,
+                    };
+                    let pos = IVec3::new(origin.x + x, y, origin.y + z);
+                    let subchunk = chunk.get_or_insert_subchunk(y, &mut pool).unwrap();
+                    subchunk.set_block(pos, BlockState {
+                        block: LocalID::new(id),
+                        light: Light::from_raw((y % 16) as u8, 0, 0, 0),
+                    });
+                }
+            }
+        }
+
+        chunk
+    }
+
+    /// A chunk with runs of every biome, and plains in the -x-z corner.
+    fn biome_chunk() -> Chunk {
+        let mut chunk = chunk(IVec2::new(W, -W));
+        for z in 0..W {
+            for x in 0..W {
+                let biome = if x + z < 20 { 0 } else { 1 + (x / 8 + z) as u16 % 3 };
+                chunk.set_biome(IVec2::new(W + x, -W + z), LocalID::new(biome));
+            }
+        }
+        chunk
+    }
+
+    fn assert_same(expected: &Chunk, actual: &Chunk) {
+        assert_eq!(expected.origin, actual.origin);
+        assert_eq!(expected.subchunks.len(), actual.subchunks.len());
+        for (expected, actual) in expected.subchunks.iter().zip(&actual.subchunks) {
+            assert_eq!(expected.origin, actual.origin);
+            assert_eq!(expected.ids(), actual.ids());
+            assert_eq!(expected.lights(), actual.lights());
+        }
+    }
+
+    #[test]
+    fn chunk_round_trip() {
+        let blocks = blocks_for_testing();
+        let chunk = chunk(IVec2::new(-W * 3, W * 7));
+        let bytes = encode_chunk(&chunk, &blocks, &biomes_for_testing());
+        let decoded = decode_chunk(&bytes, &blocks, &biomes_for_testing()).unwrap();
+        assert_same(&chunk, &decoded);
+
+        let mut world = World::new();
+        world.insert(decoded);
+        let reader = world.reader();
+        for subchunk in &chunk.subchunks {
+            for (i, id) in subchunk.ids().iter().enumerate().step_by(97) {
+                let pos = subchunk.origin + IVec3::new((i / 32 % 32) as i32, (i % 32) as i32, (i / 1024) as i32);
+                assert_eq!(Some(*id), reader.get_block(pos).map(|state| state.block), "at: {pos}");
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_status() {
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        for status in ChunkStatus::ALL {
+            let mut chunk = chunk(IVec2::ZERO);
+            chunk.set_status(status);
+            let decoded = decode_chunk(&encode_chunk(&chunk, &blocks, &biomes), &blocks, &biomes).unwrap();
+            assert_eq!(status, decoded.status());
+        }
+    }
+
+    #[test]
+    fn keeps_biomes() {
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        let chunk = biome_chunk();
+        let decoded = decode_chunk(&encode_chunk(&chunk, &blocks, &biomes), &blocks, &biomes).unwrap();
+        assert_eq!(chunk.biomes(), decoded.biomes());
+        assert_eq!(LocalID::new(0), decoded.biome_at(IVec2::new(W, -W)));
+        assert_ne!(LocalID::new(0), decoded.biome_at(IVec2::new(W + 31, -W + 31)));
+    }
+
+    #[test]
+    fn stores_biome_global_ids() {
+        let chunk = biome_chunk();
+        let bytes = encode_chunk(&chunk, &blocks_for_testing(), &biomes_for_testing());
+
+        // the same biomes, registered in a different order.
+        let mut biomes = Registry::new("biomes");
+        biomes.add(Id::new("tundra"), Biome::tundra());
+        biomes.add(Id::new("plains"), Biome::plains());
+        biomes.add(Id::new("mountains"), Biome::mountains());
+        biomes.add(Id::new("desert"), Biome::desert());
+
+        let decoded = decode_chunk(&bytes, &blocks_for_testing(), &biomes).unwrap();
+        let remap = [1, 3, 2, 0];
+        for (expected, actual) in chunk.biomes().biomes().iter().zip(decoded.biomes().biomes()) {
+            assert_eq!(remap[expected.index() as usize], actual.index());
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_biomes() {
+        let bytes = encode_chunk(&biome_chunk(), &blocks_for_testing(), &biomes_for_testing());
+
+        let mut biomes = Registry::new("biomes");
+        biomes.add(Id::new("plains"), Biome::plains());
+        biomes.add(Id::new("desert"), Biome::desert());
+        biomes.add(Id::new("tundra"), Biome::tundra());
+        assert_eq!(
+            Err(ChunkDecodeError::UnknownBiome(GlobalID::new("mountains"))),
+            decode_chunk(&bytes, &blocks_for_testing(), &biomes).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn subchunk_round_trip() {
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        let chunk = chunk(IVec2::ZERO);
+        let bytes = encode_subchunk(&chunk.subchunks[1], &blocks);
+        let decoded = decode_subchunk(&bytes, &blocks).unwrap();
+        assert_eq!(chunk.subchunks[1].origin, decoded.origin);
+        assert_eq!(chunk.subchunks[1].ids(), decoded.ids());
+        assert_eq!(chunk.subchunks[1].lights(), decoded.lights());
+
+        // a subchunk is not a chunk.
+        assert!(matches!(decode_chunk(&bytes, &blocks, &biomes), Err(ChunkDecodeError::Corrupt(_))));
+    }
+
+    #[test]
+    fn stores_global_ids() {
+        let chunk = chunk(IVec2::ZERO);
+        let bytes = encode_chunk(&chunk, &blocks_for_testing(), &biomes_for_testing());
+
+        // the same blocks, registered in a different order.
+        let mut blocks = Registry::new("blocks");
+        for name in ["sand", "grass", "air", "dirt", "stone"] {
+            blocks.add(Id::new(name), Block::default());
+        }
+
+        let decoded = decode_chunk(&bytes, &blocks, &biomes_for_testing()).unwrap();
+        let remap = [2, 4, 3, 1, 0];
+        for (expected, actual) in chunk.subchunks.iter().zip(&decoded.subchunks) {
+            for (expected, actual) in expected.ids().iter().zip(actual.ids()) {
+                assert_eq!(remap[expected.index() as usize], actual.index());
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_blocks() {
+        let chunk = chunk(IVec2::ZERO);
+        let bytes = encode_chunk(&chunk, &blocks_for_testing(), &biomes_for_testing());
+
+        let mut blocks = Registry::new("blocks");
+        for name in ["air", "stone", "grass", "sand"] {
+            blocks.add(Id::new(name), Block::default());
+        }
+        assert_eq!(
+            Err(ChunkDecodeError::UnknownBlock(GlobalID::new("dirt"))),
+            decode_chunk(&bytes, &blocks, &biomes_for_testing()).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        let bytes = encode_chunk(&chunk(IVec2::ZERO), &blocks, &biomes);
+        let decode = |bytes: &[u8]| decode_chunk(bytes, &blocks, &biomes).map(|_| ());
+
+        let mut flipped = bytes.clone();
+        flipped[bytes.len() / 2] ^= 0x10;
+        assert!(matches!(decode(&flipped), Err(ChunkDecodeError::ChecksumMismatch { .. })));
+
+        assert_eq!(Err(ChunkDecodeError::BadMagic), decode(b"not a chunk"));
+        assert_eq!(Err(ChunkDecodeError::BadMagic), decode(&[]));
+        assert_eq!(Err(ChunkDecodeError::Truncated), decode(&bytes[..5]));
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_huge_subchunk_count() {
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        let mut chunk = Chunk::new(IVec2::ZERO);
+        chunk.get_or_insert_subchunk(0, &mut SubChunkPool::new(0));
+        let bytes = encode_chunk(&chunk, &blocks, &biomes);
+
+        // the count comes right before the only subchunk, which is all air.
+        let count = bytes.len() - 4 - MIN_SUBCHUNK_LEN - 2;
+        assert_eq!(1, u16::from_le_bytes([bytes[count], bytes[count + 1]]));
+
+        let with_count = |value: u16| {
+            let mut bytes = bytes[..bytes.len() - 4].to_vec();
+            bytes[count..count + 2].copy_from_slice(&value.to_le_bytes());
+            footer(bytes)
+        };
+        let mut pool = SubChunkPool::new(0);
+        assert_eq!(
+            Err(ChunkDecodeError::Corrupt("chunk is taller than the world")),
+            decode_chunk_with(&with_count(u16::MAX), &blocks, &biomes, &mut pool).map(|_| ())
+        );
+        assert_eq!(
+            Err(ChunkDecodeError::Truncated),
+            decode_chunk_with(&with_count(MAX_SUBCHUNKS as u16), &blocks, &biomes, &mut pool).map(|_| ())
+        );
+        assert_eq!(0, pool.stats().allocated);
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        let mut bytes = encode_chunk(&chunk(IVec2::ZERO), &blocks, &biomes);
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Err(ChunkDecodeError::UnsupportedVersion(FORMAT_VERSION + 1)),
+            decode_chunk(&bytes, &blocks, &biomes).map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,357 @@
+use crate::blocks::{Block, FaceCoverage, Transparency};
+use crate::data::registry::LocalID;
+use crate::data::Registry;
+
+use super::*;
+
+bitflags::bitflags! {
+    /// The heightmaps a block counts towards.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct HeightmapFlags: u8 {
+        /// The block is not air.
+        const NON_AIR        = 0b0000_0001;
+
+        /// The block stops skylight from passing down through it.
+        const LIGHT_BLOCKING = 0b0000_0010;
+
+        /// The block has a solid collider.
+        const SOLID          = 0b0000_0100;
+    }
+}
+
+/// The kinds of heightmaps kept by every chunk.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HeightmapKind {
+    /// The highest block that isn't air.
+    NonAir = 0,
+
+    /// The highest block that blocks skylight, used for lighting.
+    LightBlocking = 1,
+
+    /// The highest block with a solid collider, used for spawning.
+    Solid = 2,
+}
+
+impl HeightmapKind {
+    pub const ALL: [Self; 3] = [Self::NonAir, Self::LightBlocking, Self::Solid];
+
+    /// The flag a block must have to count towards this heightmap.
+    pub const fn flag(self) -> HeightmapFlags {
+        match self {
+            Self::NonAir => HeightmapFlags::NON_AIR,
+            Self::LightBlocking => HeightmapFlags::LIGHT_BLOCKING,
+            Self::Solid => HeightmapFlags::SOLID,
+        }
+    }
+}
+
+/// The HeightmapFlags of every block, indexed by LocalID.
+///
+/// LocalID 0 is always air. Blocks that aren't in the table are
+/// treated like `Block::default()`, which is a full, solid cube.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeightmapTable {
+    flags: Vec<HeightmapFlags>,
+}
+
+impl HeightmapTable {
+    /// Build the table from the blocks in a registry.
+    pub fn from_registry(blocks: &Registry<Block>) -> Self {
+        let mut flags: Vec<_> = blocks.iter().map(|entry| Self::flags_of(entry)).collect();
+        if let Some(air) = flags.first_mut() {
+            *air = HeightmapFlags::empty();
+        }
+        Self { flags }
+    }
+
+    /// Compute the flags of a block. A block is air if it has no
+    /// colliders and none of its faces exist, and it blocks skylight
+    /// if light can't pass through its top and bottom faces.
+    pub fn flags_of(block: &Block) -> HeightmapFlags {
+        let mut flags = HeightmapFlags::empty();
+
+        let has_faces = block
+            .faces
+            .faces
+            .iter()
+            .any(|face| face.coverage != FaceCoverage::None);
+        if has_faces || !block.colliders.is_empty() {
+            flags |= HeightmapFlags::NON_AIR;
+        }
+
+        if !block.faces.bitmask.contains(Transparency::UP | Transparency::DOWN) {
+            flags |= HeightmapFlags::LIGHT_BLOCKING;
+        }
+
+        if block.colliders.iter().any(|collider| collider.is_solid) {
+            flags |= HeightmapFlags::SOLID;
+        }
+
+        flags
+    }
+
+    /// Get the flags of a block.
+    #[inline]
+    pub fn get(&self, id: LocalID) -> HeightmapFlags {
+        match id.index() {
+            0 => HeightmapFlags::empty(),
+            index => self
+                .flags
+                .get(index as usize)
+                .copied()
+                .unwrap_or(HeightmapFlags::all()),
+        }
+    }
+}
+
+/// The height of the highest block of each kind in every column of a chunk.
+///
+/// Heights are one above the highest block, so a column with no
+/// blocks of a kind has a height of 0. Heightmaps are updated by
+/// `WorldWriter` and `ClusterMut`, and rebuilt when a chunk is inserted
+/// into the World. Blocks written through `get_block_mut` or directly
+/// into a SubChunk are not tracked until `Chunk::rebuild_heightmaps`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heightmaps {
+    /// The heights of each kind, indexed by x + z * CHUNK_WIDTH.
+    heights: [[u16; CHUNK_WIDTH * CHUNK_WIDTH]; 3],
+}
+
+impl Heightmaps {
+    pub const fn new() -> Self {
+        Self {
+            heights: [[0; CHUNK_WIDTH * CHUNK_WIDTH]; 3],
+        }
+    }
+
+    /// Get the height of a column, where the xz of the
+    /// position is within the chunk. See `Heightmaps`.
+    pub fn get(&self, kind: HeightmapKind, pos: WorldPos2) -> i32 {
+        self.heights[kind as usize][to_column_index(pos)] as i32
+    }
+
+    /// Get every height of one kind, indexed by x + z * CHUNK_WIDTH.
+    pub fn heights(&self, kind: HeightmapKind) -> &[u16; CHUNK_WIDTH * CHUNK_WIDTH] {
+        &self.heights[kind as usize]
+    }
+}
+
+impl Default for Heightmaps {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunk {
+    /// Get the height of the column containing the position, see `Heightmaps`.
+    pub fn height_at(&self, kind: HeightmapKind, pos: WorldPos2) -> i32 {
+        self.heightmaps.get(kind, pos)
+    }
+
+    pub fn heightmaps(&self) -> &Heightmaps {
+        &self.heightmaps
+    }
+
+    /// Recompute every heightmap from the blocks in the subchunks.
+    pub fn rebuild_heightmaps(&mut self, table: &HeightmapTable) {
+        let top = (self.subchunks.len() * CHUNK_WIDTH) as i32;
+        for column in 0..CHUNK_WIDTH * CHUNK_WIDTH {
+            for kind in HeightmapKind::ALL {
+                self.heightmaps.heights[kind as usize][column] =
+                    self.scan_column(column, top, kind, table);
+            }
+        }
+    }
+
+    /// Update the heightmaps of the column containing the position after
+    /// the blocks from `bottom` up to `top` (exclusive) were set to `state`.
+    pub(super) fn update_heightmaps(
+        &mut self,
+        pos: WorldPos2,
+        bottom: i32,
+        top: i32,
+        state: BlockState,
+        table: &HeightmapTable,
+    ) {
+        let column = to_column_index(pos);
+        let flags = table.get(state.block);
+        for kind in HeightmapKind::ALL {
+            let height = self.heightmaps.heights[kind as usize][column] as i32;
+            let new = if flags.contains(kind.flag()) {
+                height.max(top)
+            } else if height > bottom && height <= top {
+                // the highest block was replaced, so
+                // search for the next one below the range.
+                self.scan_column(column, bottom, kind, table) as i32
+            } else {
+                height
+            };
+            self.heightmaps.heights[kind as usize][column] = new as u16;
+        }
+    }
+
+    /// Find the height of the highest block of this kind below `top`.
+    fn scan_column(&self, column: usize, top: i32, kind: HeightmapKind, table: &HeightmapTable) -> u16 {
+        // the index of the bottom of the column within a subchunk,
+        // since data is linear on the y axis.
+        let base = column / CHUNK_WIDTH * CHUNK_WIDTH * CHUNK_WIDTH + column % CHUNK_WIDTH * CHUNK_WIDTH;
+
+        let mut y = top.min((self.subchunks.len() * CHUNK_WIDTH) as i32);
+        while y > 0 {
+            y -= 1;
+            let subchunk = &self.subchunks[y as usize / CHUNK_WIDTH];
+            let id = subchunk.blocks[base + y as usize % CHUNK_WIDTH];
+            if table.get(id).contains(kind.flag()) {
+                return (y + 1) as u16;
+            }
+        }
+
+        0
+    }
+}
+
+/// Compute the index of a column within a heightmap, where
+/// the position is in world-space.
+#[inline]
+pub(super) fn to_column_index(pos: WorldPos2) -> usize {
+    const M: i32 = CHUNK_WIDTH as i32 - 1;
+    ((pos.x & M) + (pos.y & M) * CHUNK_WIDTH as i32) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::{Light, Faces, Face};
+    use crate::data::Id;
+
+    use super::util::*;
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    const STONE: BlockState = BlockState {
+        block: LocalID::new(1),
+        light: Light::ZERO,
+    };
+
+    const GLASS: BlockState = BlockState {
+        block: LocalID::new(2),
+        light: Light::ZERO,
+    };
+
+    const AIR: BlockState = BlockState {
+        block: LocalID::new(0),
+        light: Light::ZERO,
+    };
+
+    fn table() -> HeightmapTable {
+        let mut blocks = Registry::new("blocks");
+        blocks.add(
+            Id::new("air"),
+            Block {
+                faces: Faces::all(Face { transparent: true, coverage: FaceCoverage::None }),
+                colliders: Vec::new(),
+                ..Block::default()
+            },
+        );
+        blocks.add(Id::new("stone"), Block::default());
+        blocks.add(
+            Id::new("glass"),
+            Block {
+                faces: Faces::all(Face { transparent: true, coverage: FaceCoverage::Full }),
+                ..Block::default()
+            },
+        );
+        HeightmapTable::from_registry(&blocks)
+    }
+
+    /// A world with one empty chunk at the origin, 2 subchunks high.
+    fn empty_world() -> World {
+        let mut world = World::new();
+        world.set_heightmap_table(table());
+        let chunk = ChunkBuilder::new(world.pool_mut(), IVec2::ZERO)
+            .with_height(2)
+            .build();
+        world.insert(chunk);
+        world
+    }
+
+    #[test]
+    fn table_flags() {
+        let table = table();
+        assert_eq!(HeightmapFlags::empty(), table.get(AIR.block));
+        assert_eq!(HeightmapFlags::all(), table.get(STONE.block));
+        assert_eq!(HeightmapFlags::NON_AIR | HeightmapFlags::SOLID, table.get(GLASS.block));
+        assert_eq!(HeightmapFlags::all(), table.get(LocalID::new(100)));
+    }
+
+    #[test]
+    fn writes_raise_and_lower() {
+        let mut world = empty_world();
+        let pos = IVec3::new(3, 10, 5);
+
+        world.writer().set_block(pos, GLASS);
+        world.writer().set_block(pos.with_y(4), STONE);
+        let reader = world.reader();
+        assert_eq!(Some(11), reader.height(HeightmapKind::NonAir, pos.xz()));
+        assert_eq!(Some(11), reader.height(HeightmapKind::Solid, pos.xz()));
+        assert_eq!(Some(5), reader.height(HeightmapKind::LightBlocking, pos.xz()));
+        assert_eq!(Some(0), reader.height(HeightmapKind::NonAir, IVec2::new(4, 5)));
+
+        // removing the top block lowers the heightmap to the next block.
+        world.writer().set_block(pos, AIR);
+        assert_eq!(Some(5), world.reader().height(HeightmapKind::NonAir, pos.xz()));
+
+        world.writer().set_block(pos.with_y(4), AIR);
+        assert_eq!(None, world.reader().highest_block(HeightmapKind::Solid, pos.xz()));
+    }
+
+    #[test]
+    fn writes_above_the_chunk() {
+        let mut world = empty_world();
+        let pos = IVec3::new(-1 + W, W * 5 + 2, 0);
+        world.writer().set_block(pos, STONE);
+        assert_eq!(Some(pos), world.reader().highest_block(HeightmapKind::Solid, pos.xz()));
+    }
+
+    #[test]
+    fn fill_updates_columns() {
+        let mut world = empty_world();
+        world.writer().fill(BlockBox::from_extent(IVec3::new(0, 0, 0), IVec3::new(4, 20, 4)), STONE);
+        world.writer().fill(BlockBox::from_extent(IVec3::new(1, 10, 1), IVec3::new(2, 20, 2)), AIR);
+
+        let reader = world.reader();
+        assert_eq!(Some(20), reader.height(HeightmapKind::NonAir, IVec2::new(0, 0)));
+        assert_eq!(Some(10), reader.height(HeightmapKind::NonAir, IVec2::new(1, 1)));
+        assert_eq!(Some(10), reader.height(HeightmapKind::NonAir, IVec2::new(2, 2)));
+        assert_eq!(Some(20), reader.height(HeightmapKind::NonAir, IVec2::new(3, 3)));
+        assert_eq!(None, reader.height(HeightmapKind::NonAir, IVec2::new(W * 2, 0)));
+    }
+
+    #[test]
+    fn cluster_updates_heightmaps() {
+        let mut world = world_for_testing();
+        let pos = IVec3::new(5, W * 3 - 1, -5);
+        assert_eq!(Some(W * 3), world.reader().height(HeightmapKind::NonAir, pos.xz()));
+
+        world.cluster_mut_3x3(IVec3::ZERO).unwrap().set_block(pos, AIR);
+        assert_eq!(Some(W * 3 - 1), world.reader().height(HeightmapKind::NonAir, pos.xz()));
+    }
+
+    #[test]
+    fn rebuild_matches_incremental() {
+        let mut world = world_for_testing();
+        let mut writer = world.writer();
+        for (i, x) in (-W..W * 2).step_by(7).enumerate() {
+            let z = (i as i32 * 13) % (W * 3) - W;
+            writer.fill(BlockBox::from_extent(IVec3::new(x, 0, z), IVec3::new(3, W * 3, 3)), AIR);
+            writer.set_block(IVec3::new(x, i as i32 * 5, z), STONE);
+        }
+
+        for origin in world.origins().collect::<Vec<_>>() {
+            let chunk = world.get_chunk(IVec3::new(origin.x, 0, origin.y)).unwrap();
+            let mut rebuilt = chunk.clone();
+            rebuilt.rebuild_heightmaps(&HeightmapTable::default());
+            assert_eq!(rebuilt.heightmaps(), chunk.heightmaps(), "at: {origin}");
+        }
+    }
+}
@@ -0,0 +1,980 @@
+//! Light propagation.
+//!
+//! Skylight is stored in the ambient nibble of each block's Light. Every
+//! block that can see the sky straight up has an ambient of 15, and light
+//! spreads from there to each neighbour, losing 1 level per block. Light
+//! only passes between two blocks if the faces it crosses are transparent,
+//! see `Transparency`. Skylight that travels straight down at 15 does not
+//! lose a level, so columns open to the sky are fully lit.
+//!
+//! Positions above the highest subchunk of a loaded chunk are open sky.
+//!
+//! Block light is stored in the intensity, hue and lightness nibbles. It
+//! spreads from every block that emits light in the same way, but keeps
+//! the color of the emitter. A block's color is the mix of the colors of
+//! its brightest neighbours, so where the light of two emitters meets at
+//! the same level, the colors are mixed, see `mix_colors`.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use arrayvec::ArrayVec;
+
+use crate::blocks::{Block, Light, Transparency};
+use crate::data::registry::LocalID;
+use crate::data::Registry;
+use crate::math::Dir;
+
+use super::reader::Neighbours;
+use super::*;
+
+/// The maximum light level.
+pub const MAX_LIGHT: u8 = 15;
+
+/// The Transparency and emitted light of every block, indexed by LocalID.
+///
+/// LocalID 0 is always air, which lets light through every face.
+/// Blocks that aren't in the table are treated like `Block::default()`,
+/// which lets no light through and doesn't emit light.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LightTable {
+    transparency: Vec<Transparency>,
+    emission: Vec<Option<Light>>,
+}
+
+impl LightTable {
+    /// Build the table from the blocks in a registry. Blocks
+    /// that emit light emit it at the maximum intensity.
+    pub fn from_registry(blocks: &Registry<Block>) -> Self {
+        Self {
+            transparency: blocks.iter().map(|entry| entry.faces.bitmask).collect(),
+            emission: blocks
+                .iter()
+                .map(|entry| entry.emits_light.map(|color| Light::from_color(color, MAX_LIGHT)))
+                .collect(),
+        }
+    }
+
+    /// Get the light emitted by a block, if it emits light.
+    #[inline]
+    pub fn emission(&self, id: LocalID) -> Option<Light> {
+        self.emission.get(id.index() as usize).copied().flatten()
+    }
+
+    /// Get the faces of a block that light can pass through.
+    #[inline]
+    pub fn transparency(&self, id: LocalID) -> Transparency {
+        match id.index() {
+            0 => Transparency::all(),
+            index => self
+                .transparency
+                .get(index as usize)
+                .copied()
+                .unwrap_or(Transparency::empty()),
+        }
+    }
+
+    /// Returns true if light can travel from a block
+    /// into its neighbour in the direction.
+    #[inline]
+    pub fn passes(&self, from: LocalID, to: LocalID, dir: Dir) -> bool {
+        self.transparency(from).contains(dir.into())
+            && self.transparency(to).contains(dir.invert().into())
+    }
+}
+
+/// Computes skylight, see the module documentation.
+///
+/// The engine keeps its queues between updates, so
+/// it only allocates while it is warming up.
+#[derive(Debug, Default)]
+pub struct SkyLight {
+    table: LightTable,
+    add: VecDeque<WorldPos3>,
+    remove: VecDeque<(WorldPos3, u8)>,
+    deferred: Deferred,
+}
+
+impl SkyLight {
+    pub fn new(table: LightTable) -> Self {
+        Self {
+            table,
+            add: VecDeque::new(),
+            remove: VecDeque::new(),
+            deferred: Deferred::default(),
+        }
+    }
+
+    pub fn table(&self) -> &LightTable {
+        &self.table
+    }
+
+    /// The number of chunks that aren't loaded, which light is
+    /// waiting to spread into once they are lit.
+    pub fn deferred(&self) -> usize {
+        self.deferred.len()
+    }
+
+    /// Mark a chunk that was inserted into the world as not lit yet, so
+    /// light spreading into it is deferred until it is lit. This should be
+    /// called for every chunk in a batch before any of them are lit.
+    pub fn mark_unlit(&mut self, origin: ChunkOrigin) {
+        self.deferred.unlit.insert(origin);
+    }
+
+    /// Returns true if the chunk was marked as unlit and hasn't been lit since.
+    pub fn is_unlit(&self, origin: ChunkOrigin) -> bool {
+        self.deferred.unlit.contains(&origin)
+    }
+
+    /// Forget the deferred light into and out of a chunk that was
+    /// removed from the world, see `Deferred::forget`.
+    pub fn forget_chunk(&mut self, origin: ChunkOrigin) {
+        self.deferred.forget(origin);
+    }
+
+    /// Compute the skylight of a chunk that was just inserted into the
+    /// world. Light flows in from the neighbouring chunks that are lit,
+    /// and out of the chunk into them, and light that reached the chunk
+    /// before it was loaded flows in, see `Deferred`. Does nothing if the
+    /// chunk isn't loaded.
+    pub fn light_chunk(&mut self, world: &mut World, origin: ChunkOrigin) {
+        const W: i32 = CHUNK_WIDTH as i32;
+
+        self.deferred.unlit.remove(&origin);
+        let Some(chunk) = world.chunks.get_mut(&combine_into_u64(origin)) else {
+            return;
+        };
+
+        // seed every block that can see the sky.
+        for subchunk in chunk.subchunks.iter_mut() {
+            for light in subchunk.light.iter_mut() {
+                light.set_ambient(0);
+            }
+        }
+
+        let top = chunk.height() as i32 * W;
+        for z in origin.y..origin.y + W {
+            for x in origin.x..origin.x + W {
+                let mut above = LocalID::new(0);
+                for y in (0..top).rev() {
+                    let pos = IVec3::new(x, y, z);
+                    let id = chunk.get_block(pos).expect("y is below the top").block;
+                    if !self.table.passes(above, id, Dir::Down) {
+                        break;
+                    }
+
+                    let subchunk = chunk.get_subchunk_mut(y).expect("y is below the top");
+                    let mut light = subchunk.get_light(pos);
+                    light.set_ambient(MAX_LIGHT);
+                    subchunk.set_light(pos, light);
+                    self.add.push_back(pos);
+                    above = id;
+                }
+            }
+        }
+
+        // the borders of the neighbouring chunks, and
+        // light that reached the chunk before it was loaded.
+        self.deferred.borders(world, origin, |pos, _| self.add.push_back(pos));
+        self.add.extend(self.deferred.take(origin));
+
+        self.propagate(&mut world.writer());
+    }
+
+    /// Update the skylight around a block that was just set, where `old`
+    /// is the block that was replaced, as returned by `WorldWriter::set_block`.
+    /// Light that reached other blocks through the old block is removed,
+    /// and the light of the neighbours flows back into the hole.
+    pub fn update_block(&mut self, world: &mut World, pos: WorldPos3, old: BlockState) {
+        let mut writer = world.writer();
+        if set_ambient(&mut writer, pos, 0).is_none() {
+            return;
+        }
+
+        self.remove.push_back((pos, old.light.ambient()));
+        while let Some((pos, level)) = self.remove.pop_front() {
+            for dir in Neighbours::DIRS {
+                let next = dir + pos;
+                let Some(state) = writer.get_block(next) else {
+                    continue;
+                };
+
+                let ambient = state.light.ambient();
+                if ambient == 0 {
+                    continue;
+                }
+
+                // light that is dimmer, or that came straight down from
+                // the sky through this block, was lit by this block.
+                let straight = dir == Dir::Down && level == MAX_LIGHT && ambient == MAX_LIGHT;
+                if ambient < level || straight {
+                    set_ambient(&mut writer, next, 0);
+                    self.remove.push_back((next, ambient));
+                } else {
+                    self.add.push_back(next);
+                }
+            }
+        }
+
+        // the neighbours, and the sky, flow back into the block.
+        for dir in Neighbours::DIRS {
+            self.add.push_back(dir + pos);
+        }
+
+        self.propagate(&mut writer);
+    }
+
+    /// Spread the light of every block in the add queue.
+    fn propagate(&mut self, writer: &mut WorldWriter) {
+        while let Some(pos) = self.add.pop_front() {
+            let Some((id, level)) = sky_or_block(writer, pos) else {
+                continue;
+            };
+            if level <= 1 {
+                continue;
+            }
+
+            for dir in Neighbours::DIRS {
+                let next = dir + pos;
+                if self.deferred.defer(writer, pos, next) {
+                    continue;
+                }
+                let Some(state) = writer.get_block(next) else {
+                    continue;
+                };
+
+                if !self.table.passes(id, state.block, dir) {
+                    continue;
+                }
+
+                let new = if dir == Dir::Down && level == MAX_LIGHT {
+                    MAX_LIGHT
+                } else {
+                    level - 1
+                };
+                if new > state.light.ambient() {
+                    set_ambient(writer, next, new);
+                    self.add.push_back(next);
+                }
+            }
+        }
+    }
+}
+
+/// Computes colored block light, see the module documentation.
+///
+/// Light is spread one level at a time, from the brightest blocks to the
+/// dimmest, so every block is colored once all of its brighter neighbours
+/// are final. The engine keeps its queues between updates.
+#[derive(Debug, Default)]
+pub struct BlockLight {
+    table: LightTable,
+
+    /// The blocks to spread light from, indexed by their intensity. The
+    /// flag is true if the block was lit, rather than only recolored.
+    add: [VecDeque<(WorldPos3, bool)>; MAX_LIGHT as usize + 1],
+    remove: VecDeque<(WorldPos3, u8)>,
+
+    /// The blocks that were colored during the current update.
+    done: HashSet<WorldPos3>,
+    deferred: Deferred,
+}
+
+impl BlockLight {
+    pub fn new(table: LightTable) -> Self {
+        Self {
+            table,
+            ..Default::default()
+        }
+    }
+
+    pub fn table(&self) -> &LightTable {
+        &self.table
+    }
+
+    /// The number of chunks that aren't loaded, which light is
+    /// waiting to spread into once they are lit.
+    pub fn deferred(&self) -> usize {
+        self.deferred.len()
+    }
+
+    /// Mark a chunk that was inserted into the world as not lit yet, so
+    /// light spreading into it is deferred until it is lit. This should be
+    /// called for every chunk in a batch before any of them are lit.
+    pub fn mark_unlit(&mut self, origin: ChunkOrigin) {
+        self.deferred.unlit.insert(origin);
+    }
+
+    /// Returns true if the chunk was marked as unlit and hasn't been lit since.
+    pub fn is_unlit(&self, origin: ChunkOrigin) -> bool {
+        self.deferred.unlit.contains(&origin)
+    }
+
+    /// Forget the deferred light into and out of a chunk that was
+    /// removed from the world, see `Deferred::forget`.
+    pub fn forget_chunk(&mut self, origin: ChunkOrigin) {
+        self.deferred.forget(origin);
+    }
+
+    /// Compute the block light of a chunk that was just inserted into the
+    /// world. Light flows in from the neighbouring chunks that are lit,
+    /// and out of the chunk into them, and light that reached the chunk
+    /// before it was loaded flows in, see `Deferred`. Does nothing if the
+    /// chunk isn't loaded.
+    pub fn light_chunk(&mut self, world: &mut World, origin: ChunkOrigin) {
+        self.deferred.unlit.remove(&origin);
+        let Some(chunk) = world.chunks.get_mut(&combine_into_u64(origin)) else {
+            return;
+        };
+
+        // clear the chunk and light every emitter.
+        for subchunk in chunk.subchunks.iter_mut() {
+            let sub_origin = subchunk.origin;
+            for (index, light) in subchunk.light.iter_mut().enumerate() {
+                *light = Light::from_raw(light.ambient(), 0, 0, 0);
+                if let Some(emission) = self.table.emission(subchunk.blocks[index]) {
+                    *light = with_block_light(*light, emission);
+                    // data is linear on the y axis, then x, then z.
+                    let local = IVec3::new(
+                        (index / CHUNK_WIDTH % CHUNK_WIDTH) as i32,
+                        (index % CHUNK_WIDTH) as i32,
+                        (index / (CHUNK_WIDTH * CHUNK_WIDTH)) as i32,
+                    );
+                    self.add[emission.intensity() as usize].push_back((sub_origin + local, true));
+                }
+            }
+        }
+
+        // the lit blocks on the borders of the neighbouring chunks.
+        let add = &mut self.add;
+        self.deferred.borders(world, origin, |pos, neighbour| {
+            let intensity = neighbour.get_block(pos).expect("y is below the top").light.intensity();
+            if intensity > 0 {
+                add[intensity as usize].push_back((pos, true));
+            }
+        });
+
+        // light that reached the chunk before it was loaded.
+        let mut writer = world.writer();
+        for pos in self.deferred.take(origin) {
+            let intensity = writer.get_block(pos).map_or(0, |state| state.light.intensity());
+            if intensity > 0 {
+                self.add[intensity as usize].push_back((pos, true));
+            }
+        }
+
+        self.propagate(&mut writer);
+    }
+
+    /// Update the block light around a block that was just set, where `old`
+    /// is the block that was replaced, as returned by `WorldWriter::set_block`.
+    /// Light that came from, or through, the old block is removed, and the
+    /// light of the new block and the neighbours flows back in.
+    pub fn update_block(&mut self, world: &mut World, pos: WorldPos3, old: BlockState) {
+        let mut writer = world.writer();
+        let Some(state) = writer.get_block(pos) else {
+            return;
+        };
+        set_block_light(&mut writer, pos, Light::ZERO);
+
+        self.remove.push_back((pos, old.light.intensity()));
+        while let Some((pos, level)) = self.remove.pop_front() {
+            for dir in Neighbours::DIRS {
+                let next = dir + pos;
+                let Some(state) = writer.get_block(next) else {
+                    continue;
+                };
+
+                let intensity = state.light.intensity();
+                if intensity == 0 {
+                    continue;
+                }
+
+                // dimmer light may have come from this block, brighter
+                // light has another source and flows back in.
+                if intensity < level {
+                    set_block_light(&mut writer, next, Light::ZERO);
+                    self.remove.push_back((next, intensity));
+                    if let Some(emission) = self.table.emission(state.block) {
+                        self.emit(&mut writer, next, emission);
+                    }
+                } else {
+                    self.add[intensity as usize].push_back((next, true));
+                }
+            }
+        }
+
+        if let Some(emission) = self.table.emission(state.block) {
+            self.emit(&mut writer, pos, emission);
+        }
+
+        self.propagate(&mut writer);
+    }
+
+    /// Light an emitter with the light it emits.
+    fn emit(&mut self, writer: &mut WorldWriter, pos: WorldPos3, emission: Light) {
+        set_block_light(writer, pos, emission);
+        self.add[emission.intensity() as usize].push_back((pos, true));
+    }
+
+    /// Color and spread the light of every block in the
+    /// add queues, from the brightest to the dimmest.
+    fn propagate(&mut self, writer: &mut WorldWriter) {
+        for level in (1..=MAX_LIGHT).rev() {
+            while let Some((pos, lit)) = self.add[level as usize].pop_front() {
+                let Some(state) = writer.get_block(pos) else {
+                    continue;
+                };
+
+                // the block was lit to a brighter level since it was queued.
+                if state.light.intensity() != level || !self.done.insert(pos) {
+                    continue;
+                }
+
+                // the brighter neighbours that light this block.
+                let mut colors = ArrayVec::<Light, 7>::new();
+                for dir in Neighbours::DIRS {
+                    let Some(next) = writer.get_block(dir + pos) else {
+                        continue;
+                    };
+                    if next.light.intensity() == level + 1 && self.table.passes(next.block, state.block, dir.invert()) {
+                        colors.push(next.light);
+                    }
+                }
+                if let Some(emission) = self.table.emission(state.block) {
+                    if emission.intensity() == level {
+                        colors.push(emission);
+                    }
+                }
+
+                let mut light = state.light;
+                if !colors.is_empty() {
+                    let (hue, lightness) = mix_colors(&colors);
+                    light.set_hue(hue);
+                    light.set_lightness(lightness);
+                }
+
+                let recolored = light != state.light;
+                if recolored {
+                    writer.set_light(pos, light);
+                }
+
+                // the neighbours only change if this block changed.
+                if !(lit || recolored) || level == 1 {
+                    continue;
+                }
+
+                for dir in Neighbours::DIRS {
+                    let next = dir + pos;
+                    if self.deferred.defer(writer, pos, next) {
+                        continue;
+                    }
+                    let Some(neighbour) = writer.get_block(next) else {
+                        continue;
+                    };
+                    if !self.table.passes(state.block, neighbour.block, dir) {
+                        continue;
+                    }
+
+                    let intensity = neighbour.light.intensity();
+                    if intensity < level - 1 {
+                        let mut lit = neighbour.light;
+                        lit.set_intensity(level - 1);
+                        writer.set_light(next, lit);
+                        self.add[level as usize - 1].push_back((next, true));
+                    } else if intensity == level - 1 {
+                        self.add[level as usize - 1].push_back((next, false));
+                    }
+                }
+            }
+        }
+
+        self.done.clear();
+    }
+}
+
+/// Light that couldn't spread because it reached a chunk that isn't
+/// loaded or isn't lit yet, by the origin of that chunk. When the chunk
+/// is lit, the light of these blocks is spread again, so the order chunks
+/// are loaded and lit in doesn't leave dark seams between them.
+///
+/// Light that already spread into a chunk before it was unloaded isn't
+/// deferred, so a chunk that is loaded again is also lit from the
+/// borders of its lit neighbours, see `borders`.
+///
+/// Removing light doesn't need to be deferred, because a chunk is
+/// cleared and lit from scratch when it is lit.
+#[derive(Debug, Default)]
+struct Deferred {
+    /// Chunks that were inserted into the world, but aren't lit yet.
+    unlit: HashSet<ChunkOrigin>,
+    edges: HashMap<ChunkOrigin, HashSet<WorldPos3>>,
+}
+
+impl Deferred {
+    /// Defer spreading the light of `pos` into `next`, returning true if
+    /// `next` is in another chunk that isn't loaded or isn't lit yet.
+    fn defer(&mut self, writer: &mut WorldWriter, pos: WorldPos3, next: WorldPos3) -> bool {
+        let origin = to_chunk_origin(next.xz());
+        if next.y < 0 || origin == to_chunk_origin(pos.xz()) {
+            return false;
+        }
+
+        if self.unlit.contains(&origin) || writer.get_chunk(origin).is_none() {
+            self.edges.entry(origin).or_default().insert(pos);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Call `f` with every block on the borders of the neighbours of the
+    /// chunk that are loaded and lit, and the neighbour it is in.
+    fn borders(&self, world: &World, origin: ChunkOrigin, mut f: impl FnMut(WorldPos3, &Chunk)) {
+        const W: i32 = CHUNK_WIDTH as i32;
+
+        for (offset, dir) in [
+            (IVec2::new(-1, 0), Dir::West),
+            (IVec2::new(W, 0), Dir::East),
+            (IVec2::new(0, -1), Dir::South),
+            (IVec2::new(0, W), Dir::North),
+        ] {
+            let edge = origin + offset;
+            let neighbour_origin = to_chunk_origin(edge);
+            if self.unlit.contains(&neighbour_origin) {
+                continue;
+            }
+            let Some(neighbour) = world.get_chunk_with_origin(neighbour_origin) else {
+                continue;
+            };
+
+            let height = neighbour.height() as i32 * W;
+            for i in 0..W {
+                let xz = match dir {
+                    Dir::West | Dir::East => IVec2::new(edge.x, edge.y + i),
+                    _ => IVec2::new(edge.x + i, edge.y),
+                };
+                for y in 0..height {
+                    f(IVec3::new(xz.x, y, xz.y), neighbour);
+                }
+            }
+        }
+    }
+
+    /// Forget a chunk that was removed from the world. Light deferred into
+    /// it is dropped, because it is lit from the borders of its neighbours
+    /// if it is loaded again, and light deferred out of it is dropped,
+    /// because it is lit from scratch.
+    fn forget(&mut self, origin: ChunkOrigin) {
+        const W: i32 = CHUNK_WIDTH as i32;
+
+        self.unlit.remove(&origin);
+        self.edges.remove(&origin);
+        for offset in [IVec2::new(-W, 0), IVec2::new(W, 0), IVec2::new(0, -W), IVec2::new(0, W)] {
+            let neighbour = origin + offset;
+            if let Some(edges) = self.edges.get_mut(&neighbour) {
+                edges.retain(|pos| to_chunk_origin(pos.xz()) != origin);
+                if edges.is_empty() {
+                    self.edges.remove(&neighbour);
+                }
+            }
+        }
+    }
+
+    /// Take the blocks whose light should spread into the chunk.
+    fn take(&mut self, origin: ChunkOrigin) -> impl Iterator<Item = WorldPos3> {
+        self.edges.remove(&origin).into_iter().flatten()
+    }
+
+    /// The number of chunks with deferred light.
+    fn len(&self) -> usize {
+        self.edges.len()
+    }
+}
+
+/// Mix the colors of the lights, returning the hue and lightness of the mix.
+///
+/// Hues are averaged around the color wheel, weighted by how saturated
+/// each color is, so white light doesn't change the hue. The lightness
+/// is averaged, and raised towards white as the hues disagree, so
+/// complementary colors mix to white. Mixing a single color returns it.
+pub fn mix_colors(lights: &[Light]) -> (u8, u8) {
+    use std::f32::consts::TAU;
+
+    let (mut x, mut y, mut weight, mut lightness) = (0.0, 0.0, 0.0, 0.0);
+    for light in lights {
+        let l = light.lightness() as f32 / 15.0;
+        let w = 1.0 - (2.0 * l - 1.0).abs();
+        let angle = light.hue() as f32 / 16.0 * TAU;
+        x += angle.cos() * w;
+        y += angle.sin() * w;
+        weight += w;
+        lightness += l;
+    }
+    lightness /= lights.len().max(1) as f32;
+
+    if weight <= f32::EPSILON {
+        return (0, (lightness * 15.0).round() as u8);
+    }
+
+    let agreement = (x * x + y * y).sqrt() / weight;
+    let hue = (y.atan2(x).rem_euclid(TAU) / TAU * 16.0).round() as u8 % 16;
+    let lightness = lightness + (1.0 - agreement) * (1.0 - lightness);
+    (hue, (lightness * 15.0).round().min(15.0) as u8)
+}
+
+/// Replace the block light of a light, keeping its skylight.
+fn with_block_light(light: Light, block: Light) -> Light {
+    Light::from_raw(light.ambient(), block.intensity(), block.hue(), block.lightness())
+}
+
+/// Set the block light of the block at a position, keeping its skylight.
+fn set_block_light(writer: &mut WorldWriter, pos: WorldPos3, block: Light) -> Option<()> {
+    let light = writer.get_block(pos)?.light;
+    writer.set_light(pos, with_block_light(light, block))?;
+    Some(())
+}
+
+/// Get the block and the skylight at a position. Positions above
+/// a loaded chunk are air with full skylight. Returns None if the
+/// chunk isn't loaded or the position is below the world.
+fn sky_or_block(writer: &mut WorldWriter, pos: WorldPos3) -> Option<(LocalID, u8)> {
+    let chunk = writer.get_chunk(to_chunk_origin(pos.xz()))?;
+    if pos.y >= (chunk.height() * CHUNK_WIDTH) as i32 {
+        return Some((LocalID::new(0), MAX_LIGHT));
+    }
+
+    let state = chunk.get_block(pos)?;
+    Some((state.block, state.light.ambient()))
+}
+
+/// Set the skylight of the block at a position,
+/// returning None if the block doesn't exist.
+fn set_ambient(writer: &mut WorldWriter, pos: WorldPos3, ambient: u8) -> Option<()> {
+    let mut light = writer.get_block(pos)?.light;
+    light.set_ambient(ambient);
+    writer.set_light(pos, light)?;
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::color::Color;
+
+    use crate::blocks::{Face, FaceCoverage, Faces, Light};
+    use crate::data::Id;
+
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    const AIR: BlockState = BlockState {
+        block: LocalID::new(0),
+        light: Light::ZERO,
+    };
+
+    const STONE: BlockState = BlockState {
+        block: LocalID::new(1),
+        light: Light::ZERO,
+    };
+
+    const GLASS: BlockState = BlockState {
+        block: LocalID::new(2),
+        light: Light::ZERO,
+    };
+
+    const RED: BlockState = BlockState {
+        block: LocalID::new(3),
+        light: Light::ZERO,
+    };
+
+    const BLUE: BlockState = BlockState {
+        block: LocalID::new(4),
+        light: Light::ZERO,
+    };
+
+    fn torch(color: Color) -> Block {
+        Block {
+            faces: Faces::all(Face { transparent: true, coverage: FaceCoverage::None }),
+            colliders: Vec::new(),
+            emits_light: Some(color),
+            ..Block::default()
+        }
+    }
+
+    fn table() -> LightTable {
+        let mut blocks = Registry::new("blocks");
+        blocks.add(Id::new("air"), Block::default());
+        blocks.add(Id::new("stone"), Block::default());
+        blocks.add(
+            Id::new("glass"),
+            Block {
+                faces: Faces::all(Face { transparent: true, coverage: FaceCoverage::Full }),
+                ..Block::default()
+            },
+        );
+        blocks.add(Id::new("red_torch"), torch(Color::srgb(1.0, 0.0, 0.0)));
+        blocks.add(Id::new("blue_torch"), torch(Color::srgb(0.0, 0.0, 1.0)));
+        LightTable::from_registry(&blocks)
+    }
+
+    /// 3x3 chunks of air, 2 subchunks high, with a stone floor below y = 4.
+    fn world_with_floor() -> World {
+        let mut world = World::new();
+        for z in -1..2 {
+            for x in -1..2 {
+                let origin = IVec2::new(x, z) * W;
+                let chunk = ChunkBuilder::new(world.pool_mut(), origin).with_height(2).build();
+                world.insert(chunk);
+            }
+        }
+        world
+            .writer()
+            .fill(BlockBox::from_extent(IVec3::new(-W, 0, -W), IVec3::new(W * 3, 4, W * 3)), STONE);
+        world
+    }
+
+    fn light_all(world: &mut World, sky: &mut SkyLight) {
+        let origins = world.origins().collect::<Vec<_>>();
+        for origin in origins.iter() {
+            sky.mark_unlit(*origin);
+        }
+        for origin in origins {
+            sky.light_chunk(world, origin);
+        }
+    }
+
+    fn ambient(world: &World, pos: WorldPos3) -> u8 {
+        world.reader().get_block(pos).unwrap().light.ambient()
+    }
+
+    fn light(world: &World, pos: WorldPos3) -> Light {
+        world.reader().get_block(pos).unwrap().light
+    }
+
+    fn place(world: &mut World, engine: &mut BlockLight, pos: WorldPos3, state: BlockState) {
+        let old = world.writer().set_block(pos, state).unwrap();
+        engine.update_block(world, pos, old);
+    }
+
+    #[test]
+    fn open_sky() {
+        let mut world = world_with_floor();
+        let mut sky = SkyLight::new(table());
+        light_all(&mut world, &mut sky);
+
+        for y in 0..W * 2 {
+            let expected = if y < 4 { 0 } else { MAX_LIGHT };
+            assert_eq!(expected, ambient(&world, IVec3::new(5, y, -9)), "at: {y}");
+        }
+    }
+
+    #[test]
+    fn roof_spreads_across_chunks() {
+        let mut world = world_with_floor();
+        let mut sky = SkyLight::new(table());
+
+        // a roof over x in [-3, 3), which crosses the border at x = 0.
+        world
+            .writer()
+            .fill(BlockBox::from_extent(IVec3::new(-3, 10, -W), IVec3::new(6, 1, W * 3)), STONE);
+        light_all(&mut world, &mut sky);
+
+        for x in -3..3 {
+            let distance = if x < 0 { x + 4 } else { 3 - x } as u8;
+            assert_eq!(MAX_LIGHT - distance, ambient(&world, IVec3::new(x, 9, 0)), "at: {x}");
+            assert_eq!(0, ambient(&world, IVec3::new(x, 10, 0)));
+            assert_eq!(MAX_LIGHT, ambient(&world, IVec3::new(x, 11, 0)));
+        }
+    }
+
+    #[test]
+    fn glass_lets_sky_through() {
+        let mut world = world_with_floor();
+        let mut sky = SkyLight::new(table());
+        world
+            .writer()
+            .fill(BlockBox::from_extent(IVec3::new(-W, 10, -W), IVec3::new(W * 3, 1, W * 3)), GLASS);
+        light_all(&mut world, &mut sky);
+        assert_eq!(MAX_LIGHT, ambient(&world, IVec3::new(0, 9, 0)));
+        assert_eq!(MAX_LIGHT, ambient(&world, IVec3::new(0, 4, 0)));
+    }
+
+    #[test]
+    fn placing_and_removing_blocks() {
+        let mut world = world_with_floor();
+        let mut sky = SkyLight::new(table());
+        light_all(&mut world, &mut sky);
+
+        let pos = IVec3::new(W - 1, 8, 2);
+        let old = world.writer().set_block(pos, STONE).unwrap();
+        sky.update_block(&mut world, pos, old);
+        assert_eq!(0, ambient(&world, pos));
+        assert_eq!(MAX_LIGHT - 1, ambient(&world, pos - IVec3::Y));
+        assert_eq!(MAX_LIGHT, ambient(&world, pos + IVec3::X));
+
+        let old = world.writer().set_block(pos, AIR).unwrap();
+        sky.update_block(&mut world, pos, old);
+        for y in 4..W * 2 {
+            assert_eq!(MAX_LIGHT, ambient(&world, pos.with_y(y)), "at: {y}");
+        }
+    }
+
+    #[test]
+    fn updates_match_relighting() {
+        let mut world = world_with_floor();
+        let mut sky = SkyLight::new(table());
+        light_all(&mut world, &mut sky);
+
+        // build a roof over a room that crosses chunk borders,
+        // block by block, then knock a hole in it.
+        let mut positions = Vec::new();
+        for z in -6..6 {
+            for x in -6..6 {
+                positions.push(IVec3::new(x, 12, z));
+            }
+        }
+        positions.push(IVec3::new(3, 5, 3));
+        for pos in positions {
+            let old = world.writer().set_block(pos, STONE).unwrap();
+            sky.update_block(&mut world, pos, old);
+        }
+        let hole = IVec3::new(-2, 12, 1);
+        let old = world.writer().set_block(hole, AIR).unwrap();
+        sky.update_block(&mut world, hole, old);
+
+        let mut relit = World::new();
+        for origin in world.origins().collect::<Vec<_>>() {
+            let chunk = world.get_chunk(IVec3::new(origin.x, 0, origin.y)).unwrap().clone();
+            relit.insert(chunk);
+        }
+        light_all(&mut relit, &mut SkyLight::new(table()));
+
+        for origin in world.origins().collect::<Vec<_>>() {
+            let pos = IVec3::new(origin.x, 0, origin.y);
+            let expected = relit.get_chunk(pos).unwrap();
+            let actual = world.get_chunk(pos).unwrap();
+            for (expected, actual) in expected.subchunks.iter().zip(actual.subchunks.iter()) {
+                assert!(expected.lights() == actual.lights(), "at: {}", expected.origin());
+            }
+        }
+    }
+
+    #[test]
+    fn mixing_colors() {
+        let red = Light::from_color(Color::srgb(1.0, 0.0, 0.0), MAX_LIGHT);
+        let cyan = Light::from_color(Color::srgb(0.0, 1.0, 1.0), MAX_LIGHT);
+        assert_eq!((0, 8), (red.hue(), red.lightness()));
+        assert_eq!((red.hue(), red.lightness()), mix_colors(&[red, red]));
+        assert_eq!((cyan.hue(), cyan.lightness()), mix_colors(&[cyan]));
+
+        // complementary colors mix to white.
+        assert_eq!(15, mix_colors(&[red, cyan]).1);
+    }
+
+    #[test]
+    fn emitted_light_falls_off() {
+        let mut world = world_with_floor();
+        let mut engine = BlockLight::new(table());
+        place(&mut world, &mut engine, IVec3::new(0, 8, 0), RED);
+
+        for x in 0..MAX_LIGHT as i32 {
+            let light = light(&world, IVec3::new(x, 8, 0));
+            assert_eq!(MAX_LIGHT - x as u8, light.intensity(), "at: {x}");
+            assert_eq!((0, 8), (light.hue(), light.lightness()), "at: {x}");
+        }
+        assert_eq!(0, light(&world, IVec3::new(0, 3, 0)).intensity());
+        assert_eq!(0, light(&world, IVec3::new(MAX_LIGHT as i32, 8, 0)).intensity());
+    }
+
+    #[test]
+    fn two_colored_sources_mix() {
+        let mut world = world_with_floor();
+        let mut engine = BlockLight::new(table());
+        let red = IVec3::new(-5, 8, 0);
+        let blue = IVec3::new(5, 8, 0);
+        place(&mut world, &mut engine, red, RED);
+        place(&mut world, &mut engine, blue, BLUE);
+
+        let red_light = engine.table().emission(RED.block).unwrap();
+        let blue_light = engine.table().emission(BLUE.block).unwrap();
+        let color = |world: &World, pos| {
+            let light = light(world, pos);
+            (light.hue(), light.lightness())
+        };
+
+        // the block halfway between the sources is lit by both.
+        let middle = IVec3::new(0, 8, 0);
+        assert_eq!(10, light(&world, middle).intensity());
+        assert_eq!(mix_colors(&[red_light, blue_light]), color(&world, middle));
+        assert!((12..=15).contains(&color(&world, middle).0), "red and blue mix to magenta");
+
+        // closer to a source, the light is the color of the source.
+        assert_eq!((red_light.hue(), red_light.lightness()), color(&world, IVec3::new(-1, 8, 0)));
+        assert_eq!((blue_light.hue(), blue_light.lightness()), color(&world, IVec3::new(1, 8, 0)));
+
+        // removing a source removes its color.
+        place(&mut world, &mut engine, blue, AIR);
+        assert_eq!(10, light(&world, middle).intensity());
+        assert_eq!((red_light.hue(), red_light.lightness()), color(&world, middle));
+        assert_eq!(5, light(&world, blue).intensity());
+        assert_eq!(0, light(&world, IVec3::new(11, 8, 0)).intensity());
+    }
+
+    #[test]
+    fn block_light_keeps_skylight() {
+        let mut world = world_with_floor();
+        let mut sky = SkyLight::new(table());
+        let mut engine = BlockLight::new(table());
+        light_all(&mut world, &mut sky);
+
+        place(&mut world, &mut engine, IVec3::new(2, 6, 2), BLUE);
+        let light = light(&world, IVec3::new(3, 6, 2));
+        assert_eq!(MAX_LIGHT, light.ambient());
+        assert_eq!(MAX_LIGHT - 1, light.intensity());
+    }
+
+    #[test]
+    fn block_updates_match_relighting() {
+        let mut world = world_with_floor();
+        let mut engine = BlockLight::new(table());
+
+        // torches on either side of a chunk border,
+        // with a wall between them that has a gap.
+        place(&mut world, &mut engine, IVec3::new(-4, 6, 3), RED);
+        place(&mut world, &mut engine, IVec3::new(3, 9, -2), BLUE);
+        place(&mut world, &mut engine, IVec3::new(6, 5, 6), RED);
+        for z in -8..8 {
+            for y in 4..12 {
+                place(&mut world, &mut engine, IVec3::new(1, y, z), STONE);
+            }
+        }
+        place(&mut world, &mut engine, IVec3::new(1, 7, 1), AIR);
+        place(&mut world, &mut engine, IVec3::new(6, 5, 6), GLASS);
+
+        let mut relit = World::new();
+        for origin in world.origins().collect::<Vec<_>>() {
+            let chunk = world.get_chunk(IVec3::new(origin.x, 0, origin.y)).unwrap().clone();
+            relit.insert(chunk);
+        }
+        let mut engine = BlockLight::new(table());
+        let origins = relit.origins().collect::<Vec<_>>();
+        for origin in origins.iter() {
+            engine.mark_unlit(*origin);
+        }
+        for origin in origins {
+            engine.light_chunk(&mut relit, origin);
+        }
+
+        for origin in world.origins().collect::<Vec<_>>() {
+            let pos = IVec3::new(origin.x, 0, origin.y);
+            let expected = relit.get_chunk(pos).unwrap();
+            let actual = world.get_chunk(pos).unwrap();
+            for (expected, actual) in expected.subchunks.iter().zip(actual.subchunks.iter()) {
+                for (i, (e, a)) in expected.lights().iter().zip(actual.lights().iter()).enumerate() {
+                    assert_eq!(e, a, "at: {} {i}", expected.origin());
+                }
+            }
+        }
+    }
+}
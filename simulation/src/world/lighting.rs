@@ -0,0 +1,417 @@
+//! Queueing light updates for a dimension.
+//!
+//! The `LightQueue` resource lights every chunk that is inserted into the
+//! World, and relights the blocks around every block change that is queued.
+//! Chunks that are inserted before they reach `ChunkStatus::Light` are still
+//! being generated, so they are left unlit until the generation scheduler
+//! queues them.
+//! Chunks that are inserted together are lit as a batch, and light that
+//! reaches a chunk that isn't loaded or lit yet is deferred until that chunk
+//! is lit, so chunks can be loaded in any order without dark seams. The
+//! deferred light of chunks that are removed from the World is forgotten.
+//! Dimensions without a sky, like the nether, use a queue without skylight.
+
+use bevy::prelude::*;
+
+use super::{
+    to_chunk_origin, BlockChange, BlockLight, BlockState, ChunkOrigin, ChunkStatus, LightTable, SkyLight, World,
+    WorldPos3,
+};
+
+/// Lights chunks as they are inserted, and blocks as they change,
+/// see the module documentation.
+#[derive(Debug, Resource)]
+pub struct LightQueue {
+    /// None if the dimension has no sky.
+    sky: Option<SkyLight>,
+    block: BlockLight,
+
+    /// The chunks waiting to be lit.
+    chunks: Vec<ChunkOrigin>,
+
+    /// The blocks waiting to be relit, and the blocks they replaced.
+    blocks: Vec<(WorldPos3, BlockState)>,
+
+    /// The chunks lit by the last update.
+    lit: Vec<ChunkOrigin>,
+}
+
+impl LightQueue {
+    pub fn new(table: LightTable) -> Self {
+        Self {
+            sky: Some(SkyLight::new(table.clone())),
+            block: BlockLight::new(table),
+            chunks: Vec::new(),
+            blocks: Vec::new(),
+            lit: Vec::new(),
+        }
+    }
+
+    /// Only spread block light, leaving the ambient light of every block at 0.
+    pub fn without_skylight(mut self) -> Self {
+        self.sky = None;
+        self
+    }
+
+    pub fn has_skylight(&self) -> bool {
+        self.sky.is_some()
+    }
+
+    /// Forget every queued and deferred chunk and block, such as
+    /// when every chunk of the world is unloaded at once.
+    pub fn clear(&mut self) {
+        let mut queue = Self::new(self.block.table().clone());
+        if self.sky.is_none() {
+            queue = queue.without_skylight();
+        }
+        *self = queue;
+    }
+
+    /// Queue a chunk that was inserted into the world to be lit. Light
+    /// doesn't spread into the chunk until it is lit. Chunks inserted
+    /// with `World::insert` are queued by `update`.
+    pub fn queue_chunk(&mut self, origin: ChunkOrigin) {
+        self.mark_unlit(origin);
+        self.chunks.push(origin);
+    }
+
+    /// Queue the block at this position to be relit, where
+    /// `old` is the block that it replaced. Blocks in chunks that
+    /// aren't lit yet are skipped, since the whole chunk is lit later.
+    pub fn queue_block(&mut self, pos: WorldPos3, old: BlockState) {
+        if !self.is_unlit(pos) {
+            self.blocks.push((pos, old));
+        }
+    }
+
+    /// Queue every block that was changed, see `World::drain_changes`.
+    pub fn queue_changes<'a>(&mut self, changes: impl IntoIterator<Item = &'a BlockChange>) {
+        for change in changes {
+            self.queue_block(change.pos, change.old);
+        }
+    }
+
+    /// The number of chunks and blocks waiting to be lit.
+    pub fn pending(&self) -> usize {
+        self.chunks.len() + self.blocks.len()
+    }
+
+    /// The chunks lit by the last update, in the order they were lit.
+    pub fn lit(&self) -> &[ChunkOrigin] {
+        &self.lit
+    }
+
+    /// The number of chunks that aren't loaded or lit,
+    /// which light is waiting to spread into.
+    pub fn deferred(&self) -> usize {
+        let sky = self.sky.as_ref().map_or(0, SkyLight::deferred);
+        sky.max(self.block.deferred())
+    }
+
+    /// Forget the chunks that were removed from the world since the last
+    /// update, light the chunks that were inserted, then every queued chunk
+    /// and block. This should be called once per tick, after chunks are
+    /// inserted.
+    ///
+    /// Inserted chunks that haven't reached `ChunkStatus::Light` are only
+    /// marked as unlit, and must be queued once they are ready.
+    pub fn update(&mut self, world: &mut World) {
+        // removed first, since a chunk can be removed and inserted again.
+        let removed: Vec<_> = world.drain_removed().collect();
+        for origin in removed {
+            if let Some(sky) = &mut self.sky {
+                sky.forget_chunk(origin);
+            }
+            self.block.forget_chunk(origin);
+        }
+
+        let inserted: Vec<_> = world.drain_inserted().collect();
+        for origin in inserted {
+            let generating = world
+                .get_chunk_with_origin(origin)
+                .is_some_and(|chunk| chunk.status() < ChunkStatus::Light);
+
+            if generating {
+                self.mark_unlit(origin);
+            } else {
+                self.queue_chunk(origin);
+            }
+        }
+
+        self.lit.clear();
+        for origin in self.chunks.drain(..) {
+            if let Some(sky) = &mut self.sky {
+                sky.light_chunk(world, origin);
+            }
+            self.block.light_chunk(world, origin);
+            self.lit.push(origin);
+        }
+
+        // a queued block's chunk can be marked as unlit after it was queued.
+        let blocks: Vec<_> = self.blocks.drain(..).collect();
+        for (pos, old) in blocks {
+            if self.is_unlit(pos) {
+                continue;
+            }
+            if let Some(sky) = &mut self.sky {
+                sky.update_block(world, pos, old);
+            }
+            self.block.update_block(world, pos, old);
+        }
+    }
+
+    /// Returns true if the block is in a chunk that isn't lit yet.
+    fn is_unlit(&self, pos: WorldPos3) -> bool {
+        self.block.is_unlit(to_chunk_origin(pos.xz()))
+    }
+
+    fn mark_unlit(&mut self, origin: ChunkOrigin) {
+        if let Some(sky) = &mut self.sky {
+            sky.mark_unlit(origin);
+        }
+        self.block.mark_unlit(origin);
+    }
+}
+
+/// Light the chunks and blocks in the LightQueue.
+pub fn update_light(mut world: ResMut<World>, mut queue: ResMut<LightQueue>) {
+    queue.update(&mut world);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::color::Color;
+
+    use crate::blocks::{Block, Face, FaceCoverage, Faces, Light};
+    use crate::data::registry::LocalID;
+    use crate::data::{Id, Registry};
+    use crate::world::{Chunk, ChunkBuilder, CHUNK_WIDTH, MAX_LIGHT};
+
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    const STONE: BlockState = BlockState {
+        block: LocalID::new(1),
+        light: Light::ZERO,
+    };
+
+    const TORCH: BlockState = BlockState {
+        block: LocalID::new(2),
+        light: Light::ZERO,
+    };
+
+    fn table() -> LightTable {
+        let mut blocks = Registry::new("blocks");
+        blocks.add(Id::new("air"), Block::default());
+        blocks.add(Id::new("stone"), Block::default());
+        blocks.add(
+            Id::new("torch"),
+            Block {
+                faces: Faces::all(Face { transparent: true, coverage: FaceCoverage::None }),
+                colliders: Vec::new(),
+                emits_light: Some(Color::srgb(1.0, 0.5, 0.0)),
+                ..Block::default()
+            },
+        );
+        LightTable::from_registry(&blocks)
+    }
+
+    /// A chunk 2 subchunks high with a stone floor below y = 4,
+    /// and a stone roof at y = 10 if `roof` is true.
+    fn chunk(world: &mut World, origin: ChunkOrigin, roof: bool) -> Chunk {
+        let mut builder = ChunkBuilder::new(world.pool_mut(), origin).with_height(2);
+        for z in origin.y..origin.y + W {
+            for x in origin.x..origin.x + W {
+                for y in 0..4 {
+                    builder.set_block(IVec3::new(x, y, z), STONE);
+                }
+                if roof {
+                    builder.set_block(IVec3::new(x, 10, z), STONE);
+                }
+            }
+        }
+        builder.build()
+    }
+
+    fn light(world: &World, pos: WorldPos3) -> Light {
+        world.reader().get_block(pos).unwrap().light
+    }
+
+    #[test]
+    fn seams_are_lit_in_any_order() {
+        for open_first in [true, false] {
+            let mut world = World::new();
+            let mut queue = LightQueue::new(table());
+
+            let open = chunk(&mut world, IVec2::ZERO, false);
+            let roofed = chunk(&mut world, IVec2::new(W, 0), true);
+            let (first, second) = if open_first { (open, roofed) } else { (roofed, open) };
+
+            // light waits for the 4 neighbours of the first chunk, then
+            // for the 3 neighbours of the second that aren't loaded.
+            world.insert(first);
+            queue.update(&mut world);
+            assert_eq!(4, queue.deferred());
+
+            world.insert(second);
+            queue.update(&mut world);
+            assert_eq!(6, queue.deferred());
+
+            // skylight spreads under the roof from the open chunk.
+            for x in 0..4 {
+                let pos = IVec3::new(W + x, 9, 5);
+                assert_eq!(MAX_LIGHT - 1 - x as u8, light(&world, pos).ambient(), "at: {pos}");
+            }
+        }
+    }
+
+    #[test]
+    fn reinserted_chunks_are_lit_from_neighbours() {
+        let mut world = World::new();
+        let mut queue = LightQueue::new(table());
+        let open = chunk(&mut world, IVec2::ZERO, false);
+        let roofed = chunk(&mut world, IVec2::new(W, 0), true);
+        world.insert(open);
+        world.insert(roofed);
+        queue.update(&mut world);
+        assert_eq!(6, queue.deferred());
+
+        // the light deferred into and out of the roofed chunk is forgotten.
+        let roofed = world.take(IVec2::new(W, 0)).unwrap();
+        queue.update(&mut world);
+        assert_eq!(3, queue.deferred());
+
+        // the open chunk's light already spread, so it comes from its border.
+        world.insert(roofed);
+        queue.update(&mut world);
+        assert_eq!(6, queue.deferred());
+        for x in 0..4 {
+            let pos = IVec3::new(W + x, 9, 5);
+            assert_eq!(MAX_LIGHT - 1 - x as u8, light(&world, pos).ambient(), "at: {pos}");
+        }
+
+        world.remove(IVec2::ZERO);
+        world.remove(IVec2::new(W, 0));
+        queue.update(&mut world);
+        assert_eq!(0, queue.deferred());
+    }
+
+    #[test]
+    fn block_light_waits_for_neighbour() {
+        let mut world = World::new();
+        let mut queue = LightQueue::new(table());
+        let left = chunk(&mut world, IVec2::ZERO, false);
+        world.insert(left);
+        queue.update(&mut world);
+
+        let pos = IVec3::new(W - 2, 6, 7);
+        let old = world.writer().set_block(pos, TORCH).unwrap();
+        queue.queue_block(pos, old);
+        assert_eq!(1, queue.pending());
+        queue.update(&mut world);
+        assert_eq!(0, queue.pending());
+        assert_eq!(MAX_LIGHT - 1, light(&world, pos + IVec3::X).intensity());
+
+        let right = chunk(&mut world, IVec2::new(W, 0), false);
+        world.insert(right);
+        queue.update(&mut world);
+        for x in 0..4 {
+            let lit = light(&world, IVec3::new(W + x, 6, 7));
+            assert_eq!(MAX_LIGHT - 2 - x as u8, lit.intensity(), "at: {x}");
+            assert_eq!(light(&world, pos).hue(), lit.hue());
+        }
+    }
+
+    #[test]
+    fn batches_match_single_inserts() {
+        let mut batched = World::new();
+        let mut single = World::new();
+        let mut batched_queue = LightQueue::new(table());
+        let mut single_queue = LightQueue::new(table());
+
+        for z in -1..2 {
+            for x in -1..2 {
+                let origin = IVec2::new(x, z) * W;
+                let roof = (x + z) % 2 == 0;
+                let chunk = chunk(&mut batched, origin, roof);
+                single.insert(chunk.clone());
+                single_queue.update(&mut single);
+                batched.insert(chunk);
+            }
+        }
+        batched_queue.update(&mut batched);
+
+        for origin in single.origins().collect::<Vec<_>>() {
+            let pos = IVec3::new(origin.x, 0, origin.y);
+            let expected = single.get_chunk(pos).unwrap();
+            let actual = batched.get_chunk(pos).unwrap();
+            for y in 0..2 {
+                let y = y * W;
+                assert!(
+                    expected.get_subchunk(y).unwrap().lights() == actual.get_subchunk(y).unwrap().lights(),
+                    "at: {origin} {y}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn skips_blocks_in_unlit_chunks() {
+        let mut world = World::new();
+        let mut queue = LightQueue::new(table());
+        let mut generating = chunk(&mut world, IVec2::ZERO, false);
+        generating.set_status(ChunkStatus::Features);
+        world.insert(generating);
+        queue.update(&mut world);
+        assert!(queue.lit().is_empty());
+
+        // the torch isn't relit, so its light isn't deferred into the neighbour.
+        let pos = IVec3::new(W - 1, 6, 7);
+        let old = world.writer().set_block(pos, TORCH).unwrap();
+        queue.queue_block(pos, old);
+        assert_eq!(0, queue.pending());
+        queue.update(&mut world);
+        assert_eq!(0, light(&world, pos - IVec3::X).intensity());
+        assert_eq!(0, queue.deferred());
+
+        // the torch is lit with the rest of the chunk.
+        let mut lit = world.take(IVec2::ZERO).unwrap();
+        lit.set_status(ChunkStatus::Full);
+        world.insert(lit);
+        queue.update(&mut world);
+        assert_eq!(&[IVec2::ZERO], queue.lit());
+        assert_eq!(MAX_LIGHT - 1, light(&world, pos - IVec3::X).intensity());
+
+        // a block queued before its chunk is marked as unlit is skipped too.
+        let pos = IVec3::new(W - 1, 6, 3);
+        let old = world.writer().set_block(pos, TORCH).unwrap();
+        queue.queue_block(pos, old);
+        assert_eq!(1, queue.pending());
+        let mut generating = world.take(IVec2::ZERO).unwrap();
+        generating.set_status(ChunkStatus::Features);
+        world.insert(generating);
+        queue.update(&mut world);
+        assert_eq!(0, queue.pending());
+        assert_eq!(0, queue.deferred());
+    }
+
+    #[test]
+    fn without_skylight() {
+        let mut world = World::new();
+        let mut queue = LightQueue::new(table()).without_skylight();
+        let open = chunk(&mut world, IVec2::ZERO, false);
+        world.insert(open);
+        queue.update(&mut world);
+        assert_eq!(&[IVec2::ZERO], queue.lit());
+        assert_eq!(0, light(&world, IVec3::new(5, 20, 5)).ambient());
+
+        let pos = IVec3::new(5, 6, 5);
+        let old = world.writer().set_block(pos, TORCH).unwrap();
+        queue.queue_block(pos, old);
+        queue.update(&mut world);
+        let lit = light(&world, pos + IVec3::Y);
+        assert_eq!(0, lit.ambient());
+        assert_eq!(MAX_LIGHT - 1, lit.intensity());
+    }
+}
@@ -1,30 +1,54 @@
 use crate::blocks::BlockState;
+use bevy::ecs::system::Resource;
 use bevy::math::IVec2;
 use bevy::math::IVec3;
 use bevy::math::Vec3Swizzles;
 use chunk::to_chunk_origin;
 use chunk::to_subchunk_origin;
-use chunk::CHUNK_WIDTH;
-use cluster::Cluster2x2;
-use cluster::Cluster3x3;
-use cluster::ClusterMut2x2;
-use cluster::ClusterMut3x3;
-use std::cell::UnsafeCell;
 use std::collections::BTreeMap;
 use std::marker::PhantomData;
 
+pub use biome::BiomeMap;
+pub use buffer::WorldBuffer;
+pub use cluster::{Cluster2x2, Cluster3x3, ClusterMut2x2, ClusterMut3x3};
+pub use changes::{clear_chunk_changes, dirty_sections, BlockChange, ChangeLog, DrainDirty};
+pub use chunk::BlockStateMut;
+pub use chunk::CHUNK_WIDTH;
+pub use chunk::MAX_HEIGHT;
+pub use chunk::MAX_SUBCHUNKS;
 pub use chunk::Chunk;
 pub use chunk::SubChunk;
+pub use chunk::EMPTY_SUBCHUNK;
+pub use heightmap::{HeightmapFlags, HeightmapKind, HeightmapTable, Heightmaps};
+pub use light::{mix_colors, BlockLight, LightTable, SkyLight, MAX_LIGHT};
+pub use palette::{CompressedSubChunk, PalettedContainer};
+pub use pool::{ChunkBuilder, PoolStats, SubChunkPool};
 pub use reader::WorldReader;
-pub use volume::Volume;
+pub use status::ChunkStatus;
+pub use volume::{BlockBox, Column, Columns, Fragment, Fragments, Volume};
+pub use writer::WorldWriter;
 
+mod biome;
 mod buffer;
 mod cached;
+mod changes;
 mod chunk;
+pub mod codec;
 mod cluster;
+mod heightmap;
+mod light;
+pub mod lighting;
+mod palette;
+mod par;
+mod pool;
 mod reader;
-mod util;
+pub mod stream;
+pub mod region;
+mod status;
+#[cfg(test)]
+pub(crate) mod util;
 mod volume;
+mod writer;
 
 /// A location relative to world-space origin.
 pub type WorldPos3 = IVec3;
@@ -46,17 +70,21 @@ pub type ChunkOrigin = IVec2;
 /// This means the vec must be divisible by CHUNK_WIDTH.
 pub type SubChunkOrigin = IVec3;
 
-#[derive(Debug)]
+#[derive(Debug, Resource)]
 pub struct World {
-    allocator: Vec<Box<SubChunk>>,
+    allocator: SubChunkPool,
     chunks: BTreeMap<u64, Chunk>,
+    changes: ChangeLog,
+    heightmaps: HeightmapTable,
 }
 
 impl World {
     pub fn new() -> Self {
         Self {
-            allocator: Vec::new(),
+            allocator: SubChunkPool::default(),
             chunks: BTreeMap::new(),
+            changes: ChangeLog::default(),
+            heightmaps: HeightmapTable::default(),
         }
     }
 
@@ -77,6 +105,12 @@ impl World {
         self.chunks.get(&combine_into_u64(origin))
     }
 
+    /// Get a chunk mutably with a coordinate that is known to be a valid chunk origin.
+    /// Blocks written through the chunk are not recorded as changes.
+    pub fn get_chunk_with_origin_mut(&mut self, origin: ChunkOrigin) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&combine_into_u64(origin))
+    }
+
     /// Get a subchunk with a coordinate that is known to be a valid subchunk origin.
     /// Unlike get_subchunk, this function will not wrap the coordinates before searching.
     pub fn get_subchunk_with_origin(&self, origin: SubChunkOrigin) -> Option<&SubChunk> {
@@ -89,90 +123,156 @@ impl World {
         WorldReader::from(self)
     }
 
-    /// Insert a chunk into the world.
-    pub fn insert(&mut self, chunk: Chunk) {
+    /// Get a struct for mutating the World.
+    pub fn writer<'w>(&'w mut self) -> WorldWriter<'w> {
+        WorldWriter::from(self)
+    }
+
+    /// The block changes made since the changes were last drained.
+    pub fn changes(&self) -> &[BlockChange] {
+        &self.changes.blocks
+    }
+
+    /// Take the block changes made since the changes were last drained,
+    /// in the order they were made. This should be called once per tick.
+    pub fn drain_changes(&mut self) -> std::vec::Drain<'_, BlockChange> {
+        self.changes.blocks.drain(..)
+    }
+
+    /// Take the origins and dirty masks of the subchunks that
+    /// were modified since the dirty set was last drained.
+    pub fn drain_dirty(&mut self) -> DrainDirty<'_> {
+        DrainDirty {
+            chunks: &mut self.chunks,
+            origins: self.changes.dirty.drain(..),
+        }
+    }
+
+    /// Drain the origins of the chunks that were inserted since
+    /// this was last drained, in the order they were inserted.
+    pub fn drain_inserted(&mut self) -> std::vec::Drain<'_, ChunkOrigin> {
+        self.changes.inserted.drain(..)
+    }
+
+    /// Drain the origins of the chunks that were removed since
+    /// this was last drained, in the order they were removed.
+    pub fn drain_removed(&mut self) -> std::vec::Drain<'_, ChunkOrigin> {
+        self.changes.removed.drain(..)
+    }
+
+    /// Insert a chunk into the world, rebuilding its heightmaps.
+    pub fn insert(&mut self, mut chunk: Chunk) {
+        chunk.rebuild_heightmaps(&self.heightmaps);
         let key = combine_into_u64(chunk.origin);
-        if let Some(mut old) = self.chunks.insert(key, chunk) {
-            while let Some(sub) = old.subchunks.pop() {
-                self.allocator.push(sub);
-            }
+        self.changes.inserted.push(chunk.origin);
+        if let Some(old) = self.chunks.insert(key, chunk) {
+            self.allocator.free_chunk(old);
         }
     }
 
+    /// Remove a chunk from the world and return it. Unlike `remove`,
+    /// the subchunks are not returned to the pool.
+    pub fn take(&mut self, pos: ChunkOrigin) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&combine_into_u64(pos))?;
+        self.changes.removed.push(pos);
+        Some(chunk)
+    }
+
+    /// The origins of every chunk in the world.
+    pub fn origins(&self) -> impl Iterator<Item = ChunkOrigin> + '_ {
+        self.chunks.values().map(|chunk| chunk.origin)
+    }
+
     /// Remove a chunk from the world, returning true
     /// if a chunk was replaced.
     pub fn remove(&mut self, pos: ChunkOrigin) -> bool {
-        if let Some(mut removed) = self.chunks.remove(&combine_into_u64(pos)) {
-            while let Some(sub) = removed.subchunks.pop() {
-                self.allocator.push(sub);
-            }
-
+        if let Some(removed) = self.chunks.remove(&combine_into_u64(pos)) {
+            self.allocator.free_chunk(removed);
+            self.changes.removed.push(pos);
             true
         } else {
             false
         }
     }
 
+    /// Remove every chunk from the world, returning their subchunks to the
+    /// pool, and forget every change. The heightmap table and pool are kept.
+    pub fn clear(&mut self) {
+        for chunk in std::mem::take(&mut self.chunks).into_values() {
+            self.allocator.free_chunk(chunk);
+        }
+        self.changes = ChangeLog::default();
+    }
+
+    /// The flags used to decide which heightmaps each block counts towards.
+    pub fn heightmap_table(&self) -> &HeightmapTable {
+        &self.heightmaps
+    }
+
+    /// Set the flags of each block, rebuilding the
+    /// heightmaps of every chunk in the world.
+    pub fn set_heightmap_table(&mut self, table: HeightmapTable) {
+        self.heightmaps = table;
+        for chunk in self.chunks.values_mut() {
+            chunk.rebuild_heightmaps(&self.heightmaps);
+        }
+    }
+
+    /// Get a SubChunk with this origin from the pool, in
+    /// the same state as `SubChunk::new`.
+    pub fn alloc_subchunk(&mut self, origin: SubChunkOrigin) -> Box<SubChunk> {
+        self.allocator.alloc(origin)
+    }
+
+    /// Return a SubChunk to the pool, so its box can be reused.
+    pub fn free_subchunk(&mut self, subchunk: Box<SubChunk>) {
+        self.allocator.free(subchunk);
+    }
+
+    /// The pool of SubChunk boxes that chunks removed from
+    /// the world are returned to.
+    pub fn pool(&self) -> &SubChunkPool {
+        &self.allocator
+    }
+
+    /// The pool of SubChunk boxes, for building chunks
+    /// with a `ChunkBuilder` or changing the cap.
+    pub fn pool_mut(&mut self) -> &mut SubChunkPool {
+        &mut self.allocator
+    }
+
     /// Get a 3x3 Mutable Cluster, which includes the chunk containing
     /// the position and the neighbouring 8 chunks. Returns `None` if
     /// any of the neighbouring chunks is out-of-world.
     pub fn cluster_mut_3x3<'w>(&'w mut self, pos: WorldPos3) -> Option<ClusterMut3x3<'w>> {
-        use std::mem::transmute;
         const W: i32 = CHUNK_WIDTH as i32;
         let origin = to_chunk_origin(pos.xz() - IVec2::splat(W));
-        unsafe {
-            Some(ClusterMut3x3 {
-                chunks: [
-                    [
-                        // [0,0], [1,0], [2,0]
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(0, 0)))?,
-                        ),
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(W, 0)))?,
-                        ),
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(W * 2, 0)))?,
-                        ),
-                    ],
-                    [
-                        // [0,1], [1,1], [2,1]
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(0, W)))?,
-                        ),
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(W, W)))?,
-                        ),
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(W * 2, W)))?,
-                        ),
-                    ],
-                    [
-                        // [0,2], [1,2], [2,2]
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(0, W * 2)))?,
-                        ),
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(W, W * 2)))?,
-                        ),
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(W * 2, -W * 2)))?,
-                        ),
-                    ],
-                ],
-                marker: PhantomData,
-                origin,
-            })
-        }
+
+        // each chunk is a distinct entry in the map, so
+        // the pointers never alias each other.
+        let mut get = |offset: IVec2| {
+            self.chunks
+                .get_mut(&combine_into_u64(origin + offset))
+                .map(|chunk| chunk as *mut Chunk)
+        };
+
+        let chunks = [
+            // [0,0], [1,0], [2,0]
+            [get(IVec2::new(0, 0))?, get(IVec2::new(W, 0))?, get(IVec2::new(W * 2, 0))?],
+            // [0,1], [1,1], [2,1]
+            [get(IVec2::new(0, W))?, get(IVec2::new(W, W))?, get(IVec2::new(W * 2, W))?],
+            // [0,2], [1,2], [2,2]
+            [get(IVec2::new(0, W * 2))?, get(IVec2::new(W, W * 2))?, get(IVec2::new(W * 2, W * 2))?],
+        ];
+
+        Some(ClusterMut3x3 {
+            chunks,
+            changes: &mut self.changes,
+            pool: &mut self.allocator,
+            heightmaps: &self.heightmaps,
+            marker: PhantomData,
+            origin,
+        })
     }
 
     /// Get a 3x3 Cluster, which includes the chunk containing the position
@@ -208,7 +308,7 @@ impl World {
                     self.chunks
                         .get(&combine_into_u64(origin + IVec2::new(W, W * 2)))?,
                     self.chunks
-                        .get(&combine_into_u64(origin + IVec2::new(W * 2, -W * 2)))?,
+                        .get(&combine_into_u64(origin + IVec2::new(W * 2, W * 2)))?,
                 ],
             ],
             origin,
@@ -218,45 +318,30 @@ impl World {
     /// Get a 2x2 Mutable Cluster, which includes the chunk containing the position and 
     /// the nearest 3 chunks. Returns `None` if any of the neighbouring chunks is out-of-world.
     pub fn cluster_mut_2x2<'w>(&'w mut self, pos: WorldPos3) -> Option<ClusterMut2x2<'w>> {
-        use std::mem::transmute;
         const W: i32 = CHUNK_WIDTH as i32;
-        const H: i32 = W / 2;
-
-        let mut origin = to_chunk_origin(pos.xz());
-        match (pos.x & W >= H, pos.z & W >= H) {
-            (true, false) => origin -= IVec2::splat(W),
-            (false, true) => origin -= IVec2::new(W, 0),
-            (false, false) => origin -= IVec2::new(0, W),
-            _ => {}
-        }
-        unsafe {
-            Some(ClusterMut2x2 {
-                chunks: [
-                    [
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(0, 0)))?,
-                        ),
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(W, 0)))?,
-                        ),
-                    ],
-                    [
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(0, W)))?,
-                        ),
-                        transmute(
-                            self.chunks
-                                .get(&combine_into_u64(origin + IVec2::new(W, W)))?,
-                        ),
-                    ],
-                ],
-                origin,
-                marker: PhantomData,
-            })
-        }
+        let origin = to_cluster_2x2_origin(pos);
+
+        // each chunk is a distinct entry in the map, so
+        // the pointers never alias each other.
+        let mut get = |offset: IVec2| {
+            self.chunks
+                .get_mut(&combine_into_u64(origin + offset))
+                .map(|chunk| chunk as *mut Chunk)
+        };
+
+        let chunks = [
+            [get(IVec2::new(0, 0))?, get(IVec2::new(W, 0))?],
+            [get(IVec2::new(0, W))?, get(IVec2::new(W, W))?],
+        ];
+
+        Some(ClusterMut2x2 {
+            chunks,
+            changes: &mut self.changes,
+            pool: &mut self.allocator,
+            heightmaps: &self.heightmaps,
+            origin,
+            marker: PhantomData,
+        })
     }
 
     /// Get a 2x2 Cluster, which includes the chunk containing the position
@@ -265,15 +350,7 @@ impl World {
     /// of the pos.
     pub fn cluster_2x2<'w>(&'w self, pos: WorldPos3) -> Option<Cluster2x2<'w>> {
         const W: i32 = CHUNK_WIDTH as i32;
-        const H: i32 = W / 2;
-
-        let mut origin = to_chunk_origin(pos.xz());
-        match (pos.x & W >= H, pos.z & W >= H) {
-            (true, false) => origin -= IVec2::splat(W),
-            (false, true) => origin -= IVec2::new(W, 0),
-            (false, false) => origin -= IVec2::new(0, W),
-            _ => {}
-        }
+        let origin = to_cluster_2x2_origin(pos);
 
         Some(Cluster2x2 {
             chunks: [
@@ -295,6 +372,30 @@ impl World {
     }
 }
 
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The origin of the 2x2 cluster around a position. The cluster extends
+/// towards the half of the chunk the position is in, so every block
+/// within 16 blocks of the position is in the cluster.
+fn to_cluster_2x2_origin(pos: WorldPos3) -> ChunkOrigin {
+    const W: i32 = CHUNK_WIDTH as i32;
+    const H: i32 = W / 2;
+
+    let mut origin = to_chunk_origin(pos.xz());
+    let local = pos.xz() - origin;
+    if local.x < H {
+        origin.x -= W;
+    }
+    if local.y < H {
+        origin.y -= W;
+    }
+    origin
+}
+
 /// combines two i32s into one i64 for faster search.
 const fn combine_into_u64(pos: ChunkOrigin) -> u64 {
     ((pos.x as u32 as u64) << 32) | (pos.y as u32 as u64)
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use chunk::{to_subchunk_index, CHUNK_LEN};
+
+use crate::{blocks::Light, data::registry::LocalID};
+
+use super::*;
+
+/// A compact container for the CHUNK_LEN values of a subchunk.
+///
+/// Values are stored as indices into a local palette, packed into
+/// 64-bit words using the fewest bits that can index the palette.
+/// Indices never span two words, so a word holds `64 / bits` values.
+/// A container where every value is the same has no words at all,
+/// which is the common case for subchunks of air or stone.
+///
+/// The layout of the indices is the same as SubChunk, Y then X then Z.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PalettedContainer<T> {
+    /// The distinct values in the container.
+    palette: Vec<T>,
+
+    /// The number of bits in each index.
+    /// 0 means every value is palette[0].
+    bits: u32,
+
+    /// The packed palette indices.
+    data: Vec<u64>,
+}
+
+impl<T: Copy + Eq + Hash> PalettedContainer<T> {
+    /// A container where every value is the same.
+    pub fn new(value: T) -> Self {
+        Self {
+            palette: vec![value],
+            bits: 0,
+            data: Vec::new(),
+        }
+    }
+
+    /// Build a container from CHUNK_LEN values.
+    pub fn from_slice(values: &[T]) -> Self {
+        assert_eq!(CHUNK_LEN, values.len(), "A PalettedContainer must have CHUNK_LEN values!");
+
+        // fast path for subchunks that are all one value.
+        if values.iter().all(|v| *v == values[0]) {
+            return Self::new(values[0]);
+        }
+
+        let mut palette = vec![values[0]];
+        let mut lookup = HashMap::from([(values[0], 0)]);
+
+        // runs of the same value are common along the y axis,
+        // so the last value is checked before the lookup.
+        let mut last = (values[0], 0);
+        let indices = values
+            .iter()
+            .map(|v| {
+                if *v != last.0 {
+                    let index = *lookup.entry(*v).or_insert_with(|| {
+                        palette.push(*v);
+                        palette.len() - 1
+                    });
+                    last = (*v, index);
+                }
+                last.1
+            })
+            .collect::<Vec<_>>();
+
+        let mut result = Self {
+            bits: bits_for_len(palette.len()),
+            palette,
+            data: Vec::new(),
+        };
+
+        result.data = vec![0; words_for_bits(result.bits)];
+        for (i, index) in indices.into_iter().enumerate() {
+            result.write_index(i, index);
+        }
+
+        result
+    }
+
+    /// Get the value at an index in the range [0, CHUNK_LEN).
+    #[inline]
+    pub fn get(&self, index: usize) -> T {
+        self.palette[self.read_index(index)]
+    }
+
+    /// Get the value at a world-space position, assuming the
+    /// position is inside the subchunk the container belongs to.
+    #[inline]
+    pub fn get_block(&self, pos: WorldPos3) -> T {
+        self.get(to_subchunk_index(pos))
+    }
+
+    /// Set the value at an index, returning the value that was replaced.
+    /// The palette grows, and the indices are repacked, if the value is
+    /// not in the palette yet. Values that are no longer used stay in the
+    /// palette until `compact` is called.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        assert!(index < CHUNK_LEN, "Index out of bounds: {index}");
+
+        let old = self.get(index);
+        if old == value {
+            return old;
+        }
+
+        let palette_index = match self.palette.iter().position(|v| *v == value) {
+            Some(i) => i,
+            None => {
+                self.palette.push(value);
+                let bits = bits_for_len(self.palette.len());
+                if bits != self.bits {
+                    self.repack(bits);
+                }
+                self.palette.len() - 1
+            }
+        };
+
+        self.write_index(index, palette_index);
+        old
+    }
+
+    /// Write every value into the slice, which must have CHUNK_LEN elements.
+    pub fn decode_into(&self, out: &mut [T]) {
+        assert_eq!(CHUNK_LEN, out.len(), "Output must have CHUNK_LEN values!");
+
+        if self.bits == 0 {
+            out.fill(self.palette[0]);
+            return;
+        }
+
+        let per_word = (64 / self.bits) as usize;
+        let mask = (1u64 << self.bits) - 1;
+        for (word, chunk) in self.data.iter().zip(out.chunks_mut(per_word)) {
+            let mut word = *word;
+            for value in chunk {
+                *value = self.palette[(word & mask) as usize];
+                word >>= self.bits;
+            }
+        }
+    }
+
+    /// Decode every value into a Vec.
+    pub fn to_vec(&self) -> Vec<T> {
+        let mut result = vec![self.palette[0]; CHUNK_LEN];
+        self.decode_into(&mut result);
+        result
+    }
+
+    /// Iterate over the values in index order.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..CHUNK_LEN).map(|i| self.get(i))
+    }
+
+    /// Rebuild the palette with only the values that are
+    /// in use, shrinking the indices if possible.
+    pub fn compact(&mut self) {
+        if self.bits != 0 {
+            *self = Self::from_slice(&self.to_vec());
+        }
+    }
+
+    /// The distinct values in the container. This may include values
+    /// that are no longer used if the container was modified.
+    pub fn palette(&self) -> &[T] {
+        &self.palette
+    }
+
+    /// The number of bits used to store each index.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// The packed indices. Empty if every value is the same.
+    pub fn data(&self) -> &[u64] {
+        &self.data
+    }
+
+    /// Construct a container from its raw parts, returning None if the
+    /// parts are inconsistent or any index is outside the palette.
+    pub fn from_raw_parts(palette: Vec<T>, bits: u32, data: Vec<u64>) -> Option<Self> {
+        if palette.is_empty()
+            || bits > 16
+            || bits < bits_for_len(palette.len())
+            || data.len() != words_for_bits(bits)
+        {
+            return None;
+        }
+
+        let result = Self { palette, bits, data };
+        if (0..CHUNK_LEN).all(|i| result.read_index(i) < result.palette.len()) {
+            Some(result)
+        } else {
+            None
+        }
+    }
+
+    /// The number of bytes the container uses on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity() * size_of::<T>() + self.data.capacity() * size_of::<u64>()
+    }
+
+    #[inline]
+    fn read_index(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let per_word = (64 / self.bits) as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        ((self.data[index / per_word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    #[inline]
+    fn write_index(&mut self, index: usize, palette_index: usize) {
+        let per_word = (64 / self.bits) as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word = &mut self.data[index / per_word];
+        *word = (*word & !mask) | ((palette_index as u64) << shift);
+    }
+
+    /// Re-pack the indices with a new number of bits.
+    fn repack(&mut self, bits: u32) {
+        let indices = (0..CHUNK_LEN).map(|i| self.read_index(i)).collect::<Vec<_>>();
+        self.bits = bits;
+        self.data = vec![0; words_for_bits(bits)];
+        for (i, index) in indices.into_iter().enumerate() {
+            self.write_index(i, index);
+        }
+    }
+}
+
+/// The number of bits needed to index a palette with this many entries.
+const fn bits_for_len(len: usize) -> u32 {
+    if len <= 1 {
+        0
+    } else {
+        usize::BITS - (len - 1).leading_zeros()
+    }
+}
+
+/// The number of words needed to store CHUNK_LEN indices of this many bits.
+const fn words_for_bits(bits: u32) -> usize {
+    match 64u32.checked_div(bits) {
+        Some(per_word) => CHUNK_LEN.div_ceil(per_word as usize),
+        None => 0,
+    }
+}
+
+/// A SubChunk stored with PalettedContainers instead of flat arrays.
+/// This is a fraction of the size of a SubChunk for most terrain, but
+/// reading a block is a few operations slower, so it is meant for
+/// subchunks that are stored or rarely accessed. Use `decompress`
+/// to get a SubChunk back for fast access.
+///
+/// Like SubChunk, block ids and light are stored separately, so
+/// blocks that only differ in light share a palette entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedSubChunk {
+    pub(super) blocks: PalettedContainer<LocalID>,
+    pub(super) light: PalettedContainer<Light>,
+    pub(super) origin: SubChunkOrigin,
+}
+
+impl CompressedSubChunk {
+    pub const fn origin(&self) -> SubChunkOrigin {
+        self.origin
+    }
+
+    /// The block ids of the subchunk.
+    pub fn ids(&self) -> &PalettedContainer<LocalID> {
+        &self.blocks
+    }
+
+    /// The light of each block in the subchunk.
+    pub fn lights(&self) -> &PalettedContainer<Light> {
+        &self.light
+    }
+
+    /// Get the block at this position, assuming
+    /// that the position is inside of the subchunk.
+    pub fn get_block(&self, pos: WorldPos3) -> BlockState {
+        BlockState {
+            block: self.blocks.get_block(pos),
+            light: self.light.get_block(pos),
+        }
+    }
+
+    /// Set the block at this position, assuming that the position
+    /// is inside of the subchunk, returning the block that was replaced.
+    pub fn set_block(&mut self, pos: WorldPos3, state: BlockState) -> BlockState {
+        let index = to_subchunk_index(pos);
+        BlockState {
+            block: self.blocks.set(index, state.block),
+            light: self.light.set(index, state.light),
+        }
+    }
+
+    /// Decode the blocks into a SubChunk.
+    pub fn decompress(&self) -> Box<SubChunk> {
+        let mut result = Box::new(SubChunk::new(self.origin));
+        self.decompress_into(&mut result);
+        result
+    }
+
+    /// Decode the blocks into an existing SubChunk,
+    /// which also takes the origin of this subchunk.
+    pub fn decompress_into(&self, subchunk: &mut SubChunk) {
+        self.blocks.decode_into(subchunk.ids_mut());
+        self.light.decode_into(subchunk.lights_mut());
+        subchunk.origin = self.origin;
+    }
+}
+
+impl SubChunk {
+    /// Compress the subchunk with a palette.
+    pub fn compress(&self) -> CompressedSubChunk {
+        CompressedSubChunk {
+            blocks: PalettedContainer::from_slice(self.ids()),
+            light: PalettedContainer::from_slice(self.lights()),
+            origin: self.origin,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::util::*;
+    use super::*;
+
+    fn state(id: u16) -> BlockState {
+        BlockState {
+            block: LocalID::new(id),
+            light: Light::ZERO,
+        }
+    }
+
+    #[test]
+    fn single_value() {
+        let subchunk = SubChunk::new(IVec3::ZERO);
+        let compressed = subchunk.compress();
+        assert_eq!(0, compressed.blocks.bits());
+        assert!(compressed.blocks.data().is_empty());
+        assert!(compressed.blocks.heap_size() + compressed.light.heap_size() < 64);
+        assert_eq!(subchunk.ids(), compressed.decompress().ids());
+    }
+
+    #[test]
+    fn round_trip() {
+        let subchunk = subchunk_for_testing(IVec3::new(-32, 32, 0));
+        let compressed = subchunk.compress();
+        assert_eq!(15, compressed.blocks.bits());
+        assert_eq!(0, compressed.light.bits());
+        assert_eq!(subchunk.origin(), compressed.origin());
+
+        let decompressed = compressed.decompress();
+        assert_eq!(subchunk.origin(), decompressed.origin());
+        assert_eq!(subchunk.ids(), decompressed.ids());
+        assert_eq!(subchunk.lights(), decompressed.lights());
+
+        for (i, value) in compressed.blocks.iter().enumerate() {
+            assert_eq!(subchunk.ids()[i], value);
+        }
+    }
+
+    #[test]
+    fn light_does_not_split_palette() {
+        let mut subchunk = SubChunk::new(IVec3::ZERO);
+        for (i, light) in subchunk.lights_mut().iter_mut().enumerate() {
+            *light = Light::from_raw((i % 16) as u8, 0, 0, 0);
+        }
+
+        let compressed = subchunk.compress();
+        assert_eq!(0, compressed.blocks.bits());
+        assert_eq!(4, compressed.light.bits());
+        assert_eq!(subchunk.get_block(IVec3::new(0, 7, 3)), compressed.get_block(IVec3::new(0, 7, 3)));
+    }
+
+    #[test]
+    fn set_grows_palette() {
+        let mut container = PalettedContainer::new(state(0));
+
+        container.set(5, state(1));
+        assert_eq!(1, container.bits());
+        container.set(6, state(2));
+        assert_eq!(2, container.bits());
+        container.set(CHUNK_LEN - 1, state(3));
+        assert_eq!(2, container.bits());
+        container.set(7, state(4));
+        assert_eq!(3, container.bits());
+
+        assert_eq!(state(0), container.get(0));
+        assert_eq!(state(1), container.get(5));
+        assert_eq!(state(2), container.get(6));
+        assert_eq!(state(4), container.get(7));
+        assert_eq!(state(3), container.get(CHUNK_LEN - 1));
+        assert_eq!(state(4), container.set(7, state(0)));
+    }
+
+    #[test]
+    fn compact_shrinks() {
+        let mut container = PalettedContainer::new(state(0));
+        for i in 0..9 {
+            container.set(i, state(i as u16 + 1));
+        }
+        assert_eq!(4, container.bits());
+
+        for i in 0..9 {
+            container.set(i, state(0));
+        }
+        container.compact();
+        assert_eq!(0, container.bits());
+        assert_eq!(&[state(0)], container.palette());
+    }
+
+    #[test]
+    fn raw_parts() {
+        let mut container = PalettedContainer::new(state(7));
+        container.set(100, state(9));
+
+        let rebuilt = PalettedContainer::from_raw_parts(
+            container.palette().to_vec(),
+            container.bits(),
+            container.data().to_vec(),
+        );
+        assert_eq!(Some(&container), rebuilt.as_ref());
+
+        // index 1 is out of a palette with one entry.
+        assert!(PalettedContainer::from_raw_parts(vec![state(7)], 1, vec![u64::MAX; 512]).is_none());
+        // not enough words for the bits.
+        assert!(PalettedContainer::from_raw_parts(vec![state(7), state(9)], 1, vec![0; 8]).is_none());
+    }
+
+    #[test]
+    fn terrain_is_smaller() {
+        let mut subchunk = SubChunk::new(IVec3::ZERO);
+        for (i, block) in subchunk.ids_mut().iter_mut().enumerate() {
+            // stone with some layers of dirt and air on top.
+            *block = LocalID::new(match i % CHUNK_WIDTH {
+                0..20 => 1,
+                20..23 => 2,
+                _ => 0,
+            });
+        }
+
+        let compressed = subchunk.compress();
+        assert_eq!(2, compressed.blocks.bits());
+        let size = compressed.blocks.heap_size() + compressed.light.heap_size();
+        assert!(size * 8 <= size_of::<SubChunk>());
+    }
+}
@@ -0,0 +1,179 @@
+use rayon::prelude::*;
+
+use super::*;
+
+impl<'w> Volume<'w> {
+    /// Get a parallel iterator over the fragments of the volume.
+    /// The fragments are found on the calling thread, then each
+    /// fragment is visited on the rayon thread pool. Like `fragments`,
+    /// no guarantees can be made about the order blocks are visited.
+    pub fn par_fragments(self) -> rayon::vec::IntoIter<Fragment<'w>> {
+        self.fragments().collect::<Vec<_>>().into_par_iter()
+    }
+
+    /// Map every fragment of the volume in parallel, then fold the
+    /// results in the order the fragments would be returned by
+    /// `fragments`. Fragments are mapped in parallel, so `map`
+    /// must be thread-safe, but `fold` is always called on the calling
+    /// thread in a fixed order, so the result is deterministic even if
+    /// `fold` is not associative.
+    pub fn par_fold_fragments<T, A, M, F>(self, init: A, map: M, fold: F) -> A
+    where
+        T: Send,
+        M: Fn(Fragment<'w>) -> T + Sync + Send,
+        F: FnMut(A, T) -> A,
+    {
+        self.par_fragments()
+            .map(map)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .fold(init, fold)
+    }
+}
+
+impl World {
+    /// Get a parallel iterator over every subchunk in the world.
+    /// Subchunks are indexed in the same order as `subchunks`.
+    pub fn par_subchunks(&self) -> rayon::vec::IntoIter<&SubChunk> {
+        self.subchunks().collect::<Vec<_>>().into_par_iter()
+    }
+
+    /// Get a parallel iterator over every subchunk in the world, mutably.
+    /// Changes made through this iterator are not recorded.
+    pub fn par_subchunks_mut(&mut self) -> rayon::vec::IntoIter<&mut SubChunk> {
+        self.chunks
+            .values_mut()
+            .flat_map(|chunk| chunk.subchunks.iter_mut().map(|sub| &mut **sub))
+            .collect::<Vec<_>>()
+            .into_par_iter()
+    }
+
+    /// Get an iterator over every subchunk in the world, in order of the
+    /// chunk key, then from bottom-to-top. The order is the same between
+    /// calls as long as no chunks are inserted or removed.
+    pub fn subchunks(&self) -> impl Iterator<Item = &SubChunk> {
+        self.chunks
+            .values()
+            .flat_map(|chunk| chunk.subchunks.iter().map(|sub| &**sub))
+    }
+
+    /// Map every subchunk in the world in parallel, returning the
+    /// origin of each subchunk and the result in the order of `subchunks`.
+    pub fn par_map_subchunks<T, M>(&self, map: M) -> Vec<(SubChunkOrigin, T)>
+    where
+        T: Send,
+        M: Fn(&SubChunk) -> T + Sync + Send,
+    {
+        self.par_subchunks()
+            .map(|subchunk| (subchunk.origin(), map(subchunk)))
+            .collect()
+    }
+
+    /// Map every subchunk in the world in parallel, then fold the results
+    /// in the order of `subchunks`. `fold` is always called on the calling
+    /// thread in a fixed order, so the result is deterministic even if
+    /// `fold` is not associative, such as when summing floats.
+    pub fn par_fold_subchunks<T, A, M, F>(&self, init: A, map: M, fold: F) -> A
+    where
+        T: Send,
+        M: Fn(&SubChunk) -> T + Sync + Send,
+        F: FnMut(A, T) -> A,
+    {
+        self.par_subchunks()
+            .map(map)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .fold(init, fold)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::blocks::Light;
+
+    use super::util::*;
+    use super::*;
+
+    #[test]
+    fn par_fragments_visits_volume() {
+        const W: i32 = CHUNK_WIDTH as i32;
+        let world = world_for_testing();
+        let reader = world.reader();
+        let origin = IVec3::new(-5, W - 3, -7);
+        let extent = IVec3::new(12, 10, 9);
+
+        let visited: Vec<_> = reader
+            .volume(origin, extent)
+            .par_fragments()
+            .flat_map_iter(|fragment| fragment)
+            .collect();
+
+        assert_eq!((extent.x * extent.y * extent.z) as usize, visited.len());
+        let unique: HashSet<_> = visited.iter().map(|(pos, _)| *pos).collect();
+        assert_eq!(visited.len(), unique.len());
+        for (pos, state) in visited {
+            assert!(pos.cmpge(origin).all() && pos.cmplt(origin + extent).all());
+            assert_eq!(reader.get_block(pos), Some(state), "at: {pos}");
+        }
+    }
+
+    #[test]
+    fn par_fold_fragments_in_order() {
+        let world = world_for_testing();
+        let reader = world.reader();
+        let volume = reader.volume(IVec3::splat(-8), IVec3::splat(16));
+
+        let expected: Vec<_> = volume.clone().fragments().map(|fragment| fragment.origin).collect();
+        let actual = volume.par_fold_fragments(
+            Vec::new(),
+            |fragment| fragment.origin,
+            |mut acc, origin| {
+                acc.push(origin);
+                acc
+            },
+        );
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn par_map_subchunks_in_order() {
+        let world = world_for_testing();
+        let expected: Vec<_> = world.subchunks().map(|sub| sub.origin()).collect();
+        let actual: Vec<_> = world
+            .par_map_subchunks(|sub| sub.origin())
+            .into_iter()
+            .map(|(origin, mapped)| {
+                assert_eq!(origin, mapped);
+                origin
+            })
+            .collect();
+
+        // the testing world is 3x3 chunks, with 3 subchunks each.
+        assert_eq!(27, actual.len());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn par_fold_subchunks_counts_blocks() {
+        let world = world_for_testing();
+        let count = |sub: &SubChunk| sub.ids().iter().filter(|id| id.index() < 100).count();
+
+        let expected: usize = world.subchunks().map(count).sum();
+        let actual = world.par_fold_subchunks(0, count, |acc, n| acc + n);
+        assert_eq!(27 * 100, expected);
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn par_subchunks_mut_writes() {
+        let mut world = world_for_testing();
+        world
+            .par_subchunks_mut()
+            .for_each(|sub| sub.lights_mut().fill(Light::ZERO));
+
+        assert!(world.subchunks().all(|sub| sub.lights().iter().all(|light| *light == Light::ZERO)));
+        assert_eq!(0, world.changes().len());
+    }
+}
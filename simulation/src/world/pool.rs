@@ -0,0 +1,335 @@
+use crate::data::registry::LocalID;
+
+use super::*;
+
+/// A pool of SubChunk boxes that can be reused instead of allocating.
+///
+/// A SubChunk is ~131kb, so allocating one for every SubChunk that is
+/// loaded, generated or written is slow. Boxes of SubChunks that are
+/// removed from the world are kept here and handed out again, up to
+/// `cap` boxes. Boxes that are returned to a full pool are dropped.
+#[derive(Debug)]
+pub struct SubChunkPool {
+    free: Vec<Box<SubChunk>>,
+    cap: usize,
+    stats: PoolStats,
+}
+
+/// Statistics about the allocations served by a SubChunkPool.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// The number of boxes that were taken from the pool.
+    pub reused: u64,
+
+    /// The number of boxes that were allocated because the pool was empty.
+    pub allocated: u64,
+
+    /// The number of boxes that were returned to the pool.
+    pub returned: u64,
+
+    /// The number of boxes that were dropped because the pool was full.
+    pub dropped: u64,
+}
+
+impl PoolStats {
+    /// The fraction of allocations that were served by the pool.
+    pub fn hit_rate(&self) -> f32 {
+        let total = self.reused + self.allocated;
+        if total == 0 {
+            0.0
+        } else {
+            self.reused as f32 / total as f32
+        }
+    }
+}
+
+impl std::ops::AddAssign for PoolStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.reused += rhs.reused;
+        self.allocated += rhs.allocated;
+        self.returned += rhs.returned;
+        self.dropped += rhs.dropped;
+    }
+}
+
+impl SubChunkPool {
+    /// The cap of the World's pool, which is ~33mb of SubChunks.
+    pub const DEFAULT_CAP: usize = 256;
+
+    pub const fn new(cap: usize) -> Self {
+        Self {
+            free: Vec::new(),
+            cap,
+            stats: PoolStats {
+                reused: 0,
+                allocated: 0,
+                returned: 0,
+                dropped: 0,
+            },
+        }
+    }
+
+    /// Get a SubChunk with this origin, in the same state as `SubChunk::new`.
+    /// The box is taken from the pool if there is one, otherwise it is allocated.
+    pub fn alloc(&mut self, origin: SubChunkOrigin) -> Box<SubChunk> {
+        match self.free.pop() {
+            Some(mut subchunk) => {
+                self.stats.reused += 1;
+                subchunk.reset(origin);
+                subchunk
+            }
+            None => {
+                self.stats.allocated += 1;
+                Box::new(SubChunk::new(origin))
+            }
+        }
+    }
+
+    /// Return a box to the pool, dropping it if the pool is full.
+    pub fn free(&mut self, subchunk: Box<SubChunk>) {
+        if self.free.len() < self.cap {
+            self.stats.returned += 1;
+            self.free.push(subchunk);
+        } else {
+            self.stats.dropped += 1;
+        }
+    }
+
+    /// Return the subchunks of a chunk to the pool.
+    pub fn free_chunk(&mut self, chunk: Chunk) {
+        for subchunk in chunk.subchunks {
+            self.free(subchunk);
+        }
+    }
+
+    /// Split up to `len` boxes off into a new pool with the same cap,
+    /// so they can be used on another thread. The boxes and stats of the
+    /// new pool are added back with `merge` once it is finished.
+    pub fn split(&mut self, len: usize) -> Self {
+        let len = len.min(self.free.len());
+        Self {
+            free: self.free.split_off(self.free.len() - len),
+            cap: self.cap,
+            stats: PoolStats::default(),
+        }
+    }
+
+    /// Add the boxes and stats of a pool that was split off this pool.
+    pub fn merge(&mut self, other: Self) {
+        self.stats += other.stats;
+        for subchunk in other.free {
+            if self.free.len() < self.cap {
+                self.free.push(subchunk);
+            } else {
+                self.stats.dropped += 1;
+            }
+        }
+    }
+
+    /// The number of boxes in the pool.
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+
+    /// The maximum number of boxes the pool keeps.
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    /// Set the maximum number of boxes the pool keeps,
+    /// dropping boxes if there are more than the cap.
+    pub fn set_cap(&mut self, cap: usize) {
+        self.cap = cap;
+        self.free.truncate(cap);
+        self.free.shrink_to(cap);
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+}
+
+impl Default for SubChunkPool {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAP)
+    }
+}
+
+/// Builds a chunk, taking its SubChunks from a pool.
+pub struct ChunkBuilder<'p> {
+    pool: &'p mut SubChunkPool,
+    chunk: Chunk,
+}
+
+impl<'p> ChunkBuilder<'p> {
+    pub fn new(pool: &'p mut SubChunkPool, origin: ChunkOrigin) -> Self {
+        Self {
+            pool,
+            chunk: Chunk::new(origin),
+        }
+    }
+
+    /// Make sure the chunk has at least `height` subchunks,
+    /// up to `MAX_SUBCHUNKS`.
+    pub fn with_height(mut self, height: usize) -> Self {
+        if height > 0 {
+            self.subchunk(((height - 1) * CHUNK_WIDTH) as i32);
+        }
+        self
+    }
+
+    /// Get the subchunk containing the y value, creating it and every subchunk
+    /// below it. Returns None if the value is below or above the world.
+    pub fn subchunk(&mut self, y: i32) -> Option<&mut SubChunk> {
+        self.chunk.get_or_insert_subchunk(y, self.pool)
+    }
+
+    /// Set the block at this position, creating subchunks up to the
+    /// position. Positions below or above the world are ignored. The
+    /// xz of the position must be within the chunk.
+    pub fn set_block(&mut self, pos: WorldPos3, state: BlockState) -> &mut Self {
+        if let Some(subchunk) = self.subchunk(pos.y) {
+            subchunk.set_block(pos, state);
+        }
+        self
+    }
+
+    /// Set the biome of the column containing the position.
+    pub fn set_biome(&mut self, pos: WorldPos2, biome: LocalID) -> &mut Self {
+        self.chunk.set_biome(pos, biome);
+        self
+    }
+
+    /// The chunk being built.
+    pub fn chunk(&self) -> &Chunk {
+        &self.chunk
+    }
+
+    pub fn build(self) -> Chunk {
+        self.chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::Light;
+    use crate::data::registry::LocalID;
+
+    use super::util::*;
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    #[test]
+    fn alloc_reuses_and_resets() {
+        let mut pool = SubChunkPool::new(4);
+        pool.free(subchunk_for_testing(IVec3::new(W, W, W)));
+
+        let subchunk = pool.alloc(IVec3::new(0, W * 2, 0));
+        assert_eq!(IVec3::new(0, W * 2, 0), subchunk.origin());
+        assert!(subchunk.ids().iter().all(|id| *id == LocalID::new(0)));
+        assert!(subchunk.lights().iter().all(|light| *light == Light::ZERO));
+        assert!(pool.is_empty());
+
+        pool.alloc(IVec3::ZERO);
+        assert_eq!(
+            PoolStats { reused: 1, allocated: 1, returned: 1, dropped: 0 },
+            pool.stats()
+        );
+        assert_eq!(0.5, pool.stats().hit_rate());
+    }
+
+    #[test]
+    fn cap_drops_boxes() {
+        let mut pool = SubChunkPool::new(2);
+        for _ in 0..3 {
+            pool.free(Box::new(SubChunk::new(IVec3::ZERO)));
+        }
+        assert_eq!(2, pool.len());
+        assert_eq!(1, pool.stats().dropped);
+
+        pool.set_cap(1);
+        assert_eq!(1, pool.len());
+    }
+
+    #[test]
+    fn split_and_merge() {
+        let mut pool = SubChunkPool::new(8);
+        for _ in 0..5 {
+            pool.free(Box::new(SubChunk::new(IVec3::ZERO)));
+        }
+
+        let mut split = pool.split(3);
+        assert_eq!(2, pool.len());
+        assert_eq!(3, split.len());
+        for _ in 0..4 {
+            split.alloc(IVec3::ZERO);
+        }
+
+        pool.merge(split);
+        assert_eq!(2, pool.len());
+        assert_eq!(3, pool.stats().reused);
+        assert_eq!(1, pool.stats().allocated);
+    }
+
+    #[test]
+    fn world_recycles_subchunks() {
+        let mut world = world_for_testing();
+        assert!(world.remove(IVec2::ZERO));
+        assert_eq!(3, world.pool().len());
+
+        let chunk = ChunkBuilder::new(world.pool_mut(), IVec2::new(W * 5, 0))
+            .with_height(2)
+            .build();
+        world.insert(chunk);
+        assert_eq!(1, world.pool().len());
+        assert_eq!(0, world.pool().stats().allocated);
+
+        let subchunk = world.alloc_subchunk(IVec3::new(0, W, 0));
+        assert_eq!(IVec3::new(0, W, 0), subchunk.origin());
+        assert!(world.pool().is_empty());
+        world.free_subchunk(subchunk);
+        assert_eq!(1, world.pool().len());
+    }
+
+    #[test]
+    fn builder_sets_blocks() {
+        let mut pool = SubChunkPool::default();
+        let stone = BlockState {
+            block: LocalID::new(1),
+            light: Light::ZERO,
+        };
+
+        let mut builder = ChunkBuilder::new(&mut pool, IVec2::new(-W, W));
+        builder
+            .set_block(IVec3::new(-1, W * 2 + 3, W), stone)
+            .set_block(IVec3::new(-W, -1, W), stone)
+            .set_block(IVec3::new(-W, MAX_HEIGHT, W), stone);
+        let chunk = builder.build();
+
+        assert_eq!(3, chunk.height());
+        assert_eq!(Some(stone), chunk.get_block(IVec3::new(-1, W * 2 + 3, W)));
+        for (i, subchunk) in chunk.subchunks.iter().enumerate() {
+            assert_eq!(IVec3::new(-W, i as i32 * W, W), subchunk.origin());
+        }
+    }
+
+    #[test]
+    fn writer_uses_pool() {
+        let mut world = world_for_testing();
+        world.remove(IVec2::new(W, W));
+        let stone = BlockState {
+            block: LocalID::new(1),
+            light: Light::ZERO,
+        };
+
+        // the writer creates the subchunks above the chunk from the pool.
+        world.writer().set_block(IVec3::new(0, W * 4, 0), stone);
+        assert_eq!(1, world.pool().len());
+        assert_eq!(2, world.pool().stats().reused);
+    }
+}
@@ -1,8 +1,8 @@
-use arrayvec::ArrayVec;
 use cached::CACHED_NEIGHBOUR_CHUNK_BOUNDARIES;
 use chunk::{to_origin_local, to_subchunk_index_prewrapped, EMPTY_CHUNK};
 use std::cell::Cell;
 
+use crate::data::registry::LocalID;
 use crate::math::Dir;
 
 use super::*;
@@ -30,12 +30,10 @@ impl<'w> WorldReader<'w> {
                 .set(self.world.chunks.get(&combine_into_u64(origin.xz()))?);
         }
 
-        let local2 = to_subchunk_index_prewrapped(local);
-
         // attempt to get the subchunk from the chunk, returning None
         // if it does not exist. If the subchunk does exist, the block
         // is guaranteed to exist.
-        Some(self.last.get().get_subchunk(pos.y)?.as_slice()[to_subchunk_index_prewrapped(local)])
+        Some(self.last.get().get_subchunk(pos.y)?.get_by_index(to_subchunk_index_prewrapped(local)))
     }
 
     /// Get the chunk containing this position.
@@ -74,6 +72,29 @@ impl<'w> WorldReader<'w> {
             .get_subchunk(origin.y)
     }
 
+    /// Get the height of the column at this xz position, which is one
+    /// above the highest block of the kind, or 0 if the column has
+    /// none. Returns None if the chunk is not loaded.
+    pub fn height(&self, kind: HeightmapKind, pos: WorldPos2) -> Option<i32> {
+        Some(self.get_chunk_with_origin(to_chunk_origin(pos))?.height_at(kind, pos))
+    }
+
+    /// Get the biome of the column at this xz position.
+    /// Returns None if the chunk is not loaded.
+    pub fn biome(&self, pos: WorldPos2) -> Option<LocalID> {
+        Some(self.get_chunk_with_origin(to_chunk_origin(pos))?.biome_at(pos))
+    }
+
+    /// Get the position of the highest block of the kind in the column at
+    /// this xz position. Returns None if the chunk is not loaded or the
+    /// column has no blocks of the kind.
+    pub fn highest_block(&self, kind: HeightmapKind, pos: WorldPos2) -> Option<WorldPos3> {
+        match self.height(kind, pos)? {
+            0 => None,
+            height => Some(IVec3::new(pos.x, height - 1, pos.y)),
+        }
+    }
+
     /// Select a volume in the world.
     pub fn volume(&self, origin: IVec3, extent: IVec3) -> Volume<'w> {
         Volume::new(self.clone(), origin, extent)
@@ -94,14 +115,13 @@ impl<'w> WorldReader<'w> {
             Neighbours {
                 next: 0,
                 values: if let Some(center) = center {
-                    let center = center.as_slice();
                     [
-                        center[index + 1],
-                        center[index - 1],
-                        center[index + W],
-                        center[index - W],
-                        center[index + W * W],
-                        center[index - W * W],
+                        center.get_by_index(index + 1),
+                        center.get_by_index(index - 1),
+                        center.get_by_index(index + W),
+                        center.get_by_index(index - W),
+                        center.get_by_index(index + W * W),
+                        center.get_by_index(index - W * W),
                     ]
                 } else {
                     [BlockState::default(); 6]
@@ -115,72 +135,70 @@ impl<'w> WorldReader<'w> {
                     let mut values = [BlockState::default(); 6];
 
                     if let Some(center) = center {
-                        let center = center.as_slice();
-
                         // x neighbours are in the same subchunk
                         if packed & 0b000011 == 0 {
-                            values[2] = center[index + W];
-                            values[3] = center[index - W];
+                            values[2] = center.get_by_index(index + W);
+                            values[3] = center.get_by_index(index - W);
                         // -x is in the prev chunk
                         } else if packed & 0b000010 == 0 {
-                            values[2] = center[index + W];
+                            values[2] = center.get_by_index(index + W);
                             values[3] = self
                                 .world
                                 .get_subchunk_with_origin(origin.with_x(origin.x - W as i32))
-                                .map(|sub| sub.as_slice()[index + (W * (W - 1))])
+                                .map(|sub| sub.get_by_index(index + (W * (W - 1))))
                                 .unwrap_or_default();
                         // +x is in the next chunk
                         } else {
                             values[2] = self
                                 .world
                                 .get_subchunk_with_origin(origin.with_x(origin.x + W as i32))
-                                .map(|sub| sub.as_slice()[index - (W * (W - 1))])
+                                .map(|sub| sub.get_by_index(index - (W * (W - 1))))
                                 .unwrap_or_default();
-                            values[3] = center[index - W];
+                            values[3] = center.get_by_index(index - W);
                         }
 
                         // y neighbours are in the same subchunk
                         if packed & 0b001100 == 0 {
-                            values[0] = center[index + 1];
-                            values[1] = center[index - 1];
+                            values[0] = center.get_by_index(index + 1);
+                            values[1] = center.get_by_index(index - 1);
                         // -y is in the chunk below.
                         } else if packed & 0b001000 == 0 {
-                            values[0] = center[index + 1];
+                            values[0] = center.get_by_index(index + 1);
                             values[1] = self
                                 .world
                                 .get_subchunk_with_origin(origin.with_y(origin.y - W as i32))
-                                .map(|sub| sub.as_slice()[index + (W - 1)])
+                                .map(|sub| sub.get_by_index(index + (W - 1)))
                                 .unwrap_or_default();
                         // +y is in the chunk above
                         } else {
                             values[0] = self
                                 .world
                                 .get_subchunk_with_origin(origin.with_y(origin.y + W as i32))
-                                .map(|sub| sub.as_slice()[index - (W - 1)])
+                                .map(|sub| sub.get_by_index(index - (W - 1)))
                                 .unwrap_or_default();
-                            values[1] = center[index - 1];
+                            values[1] = center.get_by_index(index - 1);
                         }
 
                         // z neighbours are in the same subchunk
                         if packed & 0b110000 == 0 {
-                            values[4] = center[index + W * W];
-                            values[5] = center[index - W * W];
+                            values[4] = center.get_by_index(index + W * W);
+                            values[5] = center.get_by_index(index - W * W);
                         // -z is in the previous z chunk
                         } else if packed & 0b100000 == 0 {
-                            values[4] = center[index + W * W];
+                            values[4] = center.get_by_index(index + W * W);
                             values[5] = self
                                 .world
                                 .get_subchunk_with_origin(origin.with_z(origin.z - W as i32))
-                                .map(|sub| sub.as_slice()[index + (W * (W * (W - 1)))])
+                                .map(|sub| sub.get_by_index(index + (W * (W * (W - 1)))))
                                 .unwrap_or_default();
                         // +z is in the next z chunk
                         } else {
                             values[4] = self
                                 .world
                                 .get_subchunk_with_origin(origin.with_z(origin.z + W as i32))
-                                .map(|sub| sub.as_slice()[index - (W * (W * (W - 1)))])
+                                .map(|sub| sub.get_by_index(index - (W * (W * (W - 1)))))
                                 .unwrap_or_default();
-                            values[5] = center[index - W * W];
+                            values[5] = center.get_by_index(index - W * W);
                         }
                     } else {
                         if packed & 0b000011 != 0 {
@@ -188,13 +206,13 @@ impl<'w> WorldReader<'w> {
                                 values[3] = self
                                     .world
                                     .get_subchunk_with_origin(origin.with_x(origin.x - W as i32))
-                                    .map(|sub| sub.as_slice()[index + (W * (W - 1))])
+                                    .map(|sub| sub.get_by_index(index + (W * (W - 1))))
                                     .unwrap_or_default();
                             } else {
                                 values[2] = self
                                     .world
                                     .get_subchunk_with_origin(origin.with_x(origin.x + W as i32))
-                                    .map(|sub| sub.as_slice()[index - (W * (W - 1))])
+                                    .map(|sub| sub.get_by_index(index - (W * (W - 1))))
                                     .unwrap_or_default();
                             }
                         }
@@ -204,13 +222,13 @@ impl<'w> WorldReader<'w> {
                                 values[1] = self
                                     .world
                                     .get_subchunk_with_origin(origin.with_y(origin.y - W as i32))
-                                    .map(|sub| sub.as_slice()[index + (W - 1)])
+                                    .map(|sub| sub.get_by_index(index + (W - 1)))
                                     .unwrap_or_default();
                             } else {
                                 values[0] = self
                                     .world
                                     .get_subchunk_with_origin(origin.with_y(origin.y + W as i32))
-                                    .map(|sub| sub.as_slice()[index - (W - 1)])
+                                    .map(|sub| sub.get_by_index(index - (W - 1)))
                                     .unwrap_or_default();
                             }
                         }
@@ -220,13 +238,13 @@ impl<'w> WorldReader<'w> {
                                 values[5] = self
                                     .world
                                     .get_subchunk_with_origin(origin.with_z(origin.z - W as i32))
-                                    .map(|sub| sub.as_slice()[index + (W * (W * (W - 1)))])
+                                    .map(|sub| sub.get_by_index(index + (W * (W * (W - 1)))))
                                     .unwrap_or_default();
                             } else {
                                 values[4] = self
                                     .world
                                     .get_subchunk_with_origin(origin.with_z(origin.z + W as i32))
-                                    .map(|sub| sub.as_slice()[index - (W * (W * (W - 1)))])
+                                    .map(|sub| sub.get_by_index(index - (W * (W * (W - 1)))))
                                     .unwrap_or_default();
                             }
                         }
@@ -257,7 +275,13 @@ pub struct Neighbours {
 }
 
 impl Neighbours {
-    const DIRS: [Dir; 6] = [
+    /// Neighbours from blocks that are already in the order
+    /// UP, DOWN, EAST, WEST, NORTH, SOUTH.
+    pub(super) const fn new(values: [BlockState; 6]) -> Self {
+        Self { values, next: 0 }
+    }
+
+    pub(super) const DIRS: [Dir; 6] = [
         Dir::Up,
         Dir::Down,
         Dir::East,
@@ -295,28 +319,28 @@ mod tests {
         assert_eq!(
             Some(BlockState {
                 block: LocalID::new(0),
-                light: Light::new(0, 0, 0, 0)
+                light: Light::ZERO
             }),
             reader.get_block(IVec3::splat(0))
         );
         assert_eq!(
             Some(BlockState {
                 block: LocalID::new(1),
-                light: Light::new(0, 0, 0, 0)
+                light: Light::ZERO
             }),
             reader.get_block(IVec3::new(0, 1, 0))
         );
         assert_eq!(
             Some(BlockState {
                 block: LocalID::new(2),
-                light: Light::new(0, 0, 0, 0)
+                light: Light::ZERO
             }),
             reader.get_block(IVec3::new(0, 2, 0))
         );
         assert_eq!(
             Some(BlockState {
                 block: LocalID::new(0),
-                light: Light::new(0, 0, 0, 0)
+                light: Light::ZERO
             }),
             reader.get_block(IVec3::new(0, CHUNK_WIDTH as i32, 0))
         );
@@ -0,0 +1,638 @@
+//! Storage for chunks on disk, in region files.
+//!
+//! A region is 32x32 chunks, stored in one file named `r.<x>.<z>.mcr`,
+//! where x and z are the coordinates of the region. A region file is
+//! made of 4KB sectors. The first two sectors are the offset table,
+//! which has an entry for every chunk in the region, holding the first
+//! sector of the chunk (u32) and the number of sectors it uses (u32).
+//! An entry of zero means the chunk is not in the file. Every chunk
+//! starts with the length of its encoding (u32), followed by the
+//! encoding from `world::codec`. All integers are little-endian.
+//!
+//! A chunk is always written to free sectors before its entry in the
+//! offset table is updated, and its old sectors are only freed after
+//! the entry is updated, so a write that is interrupted leaves the
+//! old chunk in place.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::blocks::Block;
+use crate::data::Registry;
+use crate::terrain::Biome;
+
+use super::codec::{decode_chunk_with, encode_chunk, ChunkDecodeError};
+use super::*;
+
+/// The width of a region, in chunks.
+pub const REGION_WIDTH: i32 = 32;
+
+/// The number of chunks in a region.
+const REGION_LEN: usize = (REGION_WIDTH * REGION_WIDTH) as usize;
+
+/// The size of a sector in a region file.
+const SECTOR_LEN: u64 = 4096;
+
+/// The number of sectors used by the offset table.
+const TABLE_SECTORS: u32 = (REGION_LEN * 8) as u32 / SECTOR_LEN as u32;
+
+/// The reasons a chunk can fail to load or save.
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    Decode(ChunkDecodeError),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "region file error: {err}"),
+            Self::Decode(err) => write!(f, "failed to decode chunk: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Decode(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ChunkDecodeError> for StorageError {
+    fn from(value: ChunkDecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+/// The coordinates of the region containing the chunk.
+pub fn to_region(origin: ChunkOrigin) -> IVec2 {
+    // div_euclid rounds towards negative infinity,
+    // like to_chunk_origin does for blocks.
+    (origin / CHUNK_WIDTH as i32).div_euclid(IVec2::splat(REGION_WIDTH))
+}
+
+/// The index of the chunk in the offset table of its region.
+fn to_region_index(origin: ChunkOrigin) -> usize {
+    let local = (origin / CHUNK_WIDTH as i32).rem_euclid(IVec2::splat(REGION_WIDTH));
+    (local.x + local.y * REGION_WIDTH) as usize
+}
+
+/// An entry in the offset table of a region file.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Entry {
+    /// The first sector of the chunk.
+    offset: u32,
+
+    /// The number of sectors the chunk uses.
+    sectors: u32,
+}
+
+impl Entry {
+    fn is_empty(&self) -> bool {
+        self.sectors == 0
+    }
+
+    fn range(&self) -> std::ops::Range<usize> {
+        self.offset as usize..(self.offset + self.sectors) as usize
+    }
+}
+
+/// A single region file, holding the encoded chunks of a 32x32 chunk region.
+#[derive(Debug)]
+pub struct RegionFile {
+    file: File,
+    entries: Box<[Entry; REGION_LEN]>,
+
+    /// Whether each sector in the file is used, including the offset table.
+    used: Vec<bool>,
+}
+
+impl RegionFile {
+    /// Open the region file at this path, creating it if it doesn't exist.
+    /// Entries in the offset table that point past the end of the file
+    /// or overlap another chunk are treated as empty.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let len = file.metadata()?.len();
+        let table_len = TABLE_SECTORS as u64 * SECTOR_LEN;
+        let mut table = vec![0u8; table_len as usize];
+        if len < table_len {
+            // a new file, or one that was cut off
+            // before the offset table was written.
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&table)?;
+            file.sync_all()?;
+        } else {
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut table)?;
+        }
+
+        let sectors = (file.metadata()?.len() / SECTOR_LEN) as usize;
+        let mut used = vec![false; sectors];
+        used[..TABLE_SECTORS as usize].fill(true);
+
+        let mut entries = Box::new([Entry::default(); REGION_LEN]);
+        for (i, entry) in entries.iter_mut().enumerate() {
+            let bytes = &table[i * 8..i * 8 + 8];
+            let read = Entry {
+                offset: u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+                sectors: u32::from_le_bytes(bytes[4..].try_into().unwrap()),
+            };
+
+            let valid = !read.is_empty()
+                && read.offset >= TABLE_SECTORS
+                && read.range().end <= sectors
+                && !used[read.range()].iter().any(|used| *used);
+
+            if valid {
+                used[read.range()].fill(true);
+                *entry = read;
+            }
+        }
+
+        Ok(Self {
+            file,
+            entries,
+            used,
+        })
+    }
+
+    /// Returns true if the chunk is in the file.
+    pub fn contains(&self, origin: ChunkOrigin) -> bool {
+        !self.entries[to_region_index(origin)].is_empty()
+    }
+
+    /// The number of sectors in the file, including the offset table.
+    pub fn sectors(&self) -> usize {
+        self.used.len()
+    }
+
+    /// Read the encoded chunk with this origin,
+    /// or None if the chunk is not in the file.
+    pub fn read(&mut self, origin: ChunkOrigin) -> io::Result<Option<Vec<u8>>> {
+        let entry = self.entries[to_region_index(origin)];
+        if entry.is_empty() {
+            return Ok(None);
+        }
+
+        self.file.seek(SeekFrom::Start(entry.offset as u64 * SECTOR_LEN))?;
+        let mut len = [0u8; 4];
+        self.file.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as u64;
+        if len + 4 > entry.sectors as u64 * SECTOR_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "chunk is longer than its sectors",
+            ));
+        }
+
+        let mut data = vec![0u8; len as usize];
+        self.file.read_exact(&mut data)?;
+        Ok(Some(data))
+    }
+
+    /// Write the encoded chunk with this origin, replacing
+    /// the chunk that was in the file, if there was one.
+    pub fn write(&mut self, origin: ChunkOrigin, data: &[u8]) -> io::Result<()> {
+        let index = to_region_index(origin);
+        let sectors = ((data.len() as u64 + 4).div_ceil(SECTOR_LEN)) as u32;
+        let entry = Entry {
+            offset: self.allocate(sectors),
+            sectors,
+        };
+
+        // the sectors are only used once the write succeeds,
+        // so a failed write doesn't leak them.
+        if let Err(err) = self.write_at(index, entry, data) {
+            self.used[entry.range()].fill(false);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Write the data to the entry's sectors, then point the offset
+    /// table at them and free the sectors of the old chunk.
+    fn write_at(&mut self, index: usize, entry: Entry, data: &[u8]) -> io::Result<()> {
+        let Entry { offset, sectors } = entry;
+
+        // write the data to free sectors first, so the
+        // old chunk is still valid if we are interrupted.
+        let mut buf = Vec::with_capacity((sectors as u64 * SECTOR_LEN) as usize);
+        buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(data);
+        buf.resize((sectors as u64 * SECTOR_LEN) as usize, 0);
+        self.file.seek(SeekFrom::Start(offset as u64 * SECTOR_LEN))?;
+        self.file.write_all(&buf)?;
+        self.file.sync_data()?;
+
+        let old = self.entries[index];
+        self.write_entry(index, entry)?;
+        self.used[old.range()].fill(false);
+        Ok(())
+    }
+
+    /// Remove the chunk with this origin from the file, returning
+    /// true if it was in the file. Its sectors are reused by later writes.
+    pub fn remove(&mut self, origin: ChunkOrigin) -> io::Result<bool> {
+        let index = to_region_index(origin);
+        let old = self.entries[index];
+        if old.is_empty() {
+            return Ok(false);
+        }
+
+        self.write_entry(index, Entry::default())?;
+        self.used[old.range()].fill(false);
+        Ok(true)
+    }
+
+    /// Write an entry to the offset table.
+    fn write_entry(&mut self, index: usize, entry: Entry) -> io::Result<()> {
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&entry.offset.to_le_bytes());
+        bytes[4..].copy_from_slice(&entry.sectors.to_le_bytes());
+        self.file.seek(SeekFrom::Start(index as u64 * 8))?;
+        self.file.write_all(&bytes)?;
+        self.file.sync_data()?;
+        self.entries[index] = entry;
+        Ok(())
+    }
+
+    /// Find the first run of free sectors that fits, marking it used.
+    /// If there is no run that fits, the sectors are added to the end.
+    fn allocate(&mut self, sectors: u32) -> u32 {
+        let sectors = sectors as usize;
+        let mut run = 0;
+        for i in 0..self.used.len() {
+            run = if self.used[i] { 0 } else { run + 1 };
+            if run == sectors {
+                let start = i + 1 - sectors;
+                self.used[start..=i].fill(true);
+                return start as u32;
+            }
+        }
+
+        // extend the trailing free run, if there is one.
+        let start = self.used.len() - run;
+        self.used.resize(start + sectors, true);
+        self.used[start..].fill(true);
+        start as u32
+    }
+}
+
+/// Storage for the chunks of a world in a directory of region files.
+#[derive(Debug)]
+pub struct RegionStorage {
+    dir: PathBuf,
+    regions: HashMap<IVec2, RegionFile>,
+}
+
+impl RegionStorage {
+    /// Open the region files in this directory, creating it if it doesn't exist.
+    /// Region files are opened when a chunk in the region is first accessed.
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            regions: HashMap::new(),
+        })
+    }
+
+    /// The path of the file for the region.
+    pub fn region_path(&self, region: IVec2) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.mcr", region.x, region.y))
+    }
+
+    /// Get the region file containing the chunk, opening it if needed.
+    pub fn region(&mut self, origin: ChunkOrigin) -> io::Result<&mut RegionFile> {
+        let region = to_region(origin);
+        if !self.regions.contains_key(&region) {
+            let file = RegionFile::open(self.region_path(region))?;
+            self.regions.insert(region, file);
+        }
+
+        Ok(self.regions.get_mut(&region).unwrap())
+    }
+
+    /// Load the chunk with this origin, or None if it was never saved.
+    pub fn load(
+        &mut self,
+        origin: ChunkOrigin,
+        blocks: &Registry<Block>,
+        biomes: &Registry<Biome>,
+    ) -> Result<Option<Chunk>, StorageError> {
+        self.load_with(origin, blocks, biomes, &mut SubChunkPool::new(0))
+    }
+
+    /// Load the chunk like `load`, taking its subchunks from the pool.
+    pub fn load_with(
+        &mut self,
+        origin: ChunkOrigin,
+        blocks: &Registry<Block>,
+        biomes: &Registry<Biome>,
+        pool: &mut SubChunkPool,
+    ) -> Result<Option<Chunk>, StorageError> {
+        match self.region(origin)?.read(origin)? {
+            Some(data) => {
+                let chunk = decode_chunk_with(&data, blocks, biomes, pool)?;
+                if chunk.origin != origin {
+                    pool.free_chunk(chunk);
+                    return Err(ChunkDecodeError::Corrupt("chunk is stored at the wrong origin").into());
+                }
+                Ok(Some(chunk))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Save the chunk, replacing the saved chunk with the same origin.
+    pub fn save(&mut self, chunk: &Chunk, blocks: &Registry<Block>, biomes: &Registry<Biome>) -> Result<(), StorageError> {
+        let data = encode_chunk(chunk, blocks, biomes);
+        self.region(chunk.origin)?.write(chunk.origin, &data)?;
+        Ok(())
+    }
+
+    /// Remove the saved chunk with this origin, returning true if it was saved.
+    pub fn remove(&mut self, origin: ChunkOrigin) -> Result<bool, StorageError> {
+        Ok(self.region(origin)?.remove(origin)?)
+    }
+
+    /// Load the chunk with this origin into the world,
+    /// returning true if the chunk was saved.
+    pub fn load_into(
+        &mut self,
+        world: &mut World,
+        origin: ChunkOrigin,
+        blocks: &Registry<Block>,
+        biomes: &Registry<Biome>,
+    ) -> Result<bool, StorageError> {
+        match self.load(origin, blocks, biomes)? {
+            Some(chunk) => {
+                world.insert(chunk);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Save the chunk with this origin and remove it from the world,
+    /// returning true if the chunk was in the world. The chunk is only
+    /// removed if it was saved.
+    pub fn unload_from(
+        &mut self,
+        world: &mut World,
+        origin: ChunkOrigin,
+        blocks: &Registry<Block>,
+        biomes: &Registry<Biome>,
+    ) -> Result<bool, StorageError> {
+        match world.get_chunk_with_origin(origin) {
+            Some(chunk) => {
+                self.save(chunk, blocks, biomes)?;
+                Ok(world.remove(origin))
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Close the region files that are open. They are
+    /// opened again when a chunk in the region is accessed.
+    pub fn close(&mut self) {
+        self.regions.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::Light;
+    use crate::data::registry::LocalID;
+    use crate::world::util::{biomes_for_testing, blocks_for_testing};
+
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    /// A directory that is removed when it is dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("mcre-region-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A chunk with `height` subchunks, where the block ids depend on the seed
+    /// so chunks with different seeds don't compress to the same size.
+    fn chunk(origin: ChunkOrigin, height: i32, seed: i32) -> Chunk {
+        let mut pool = SubChunkPool::default();
+        let mut chunk = Chunk::new(origin);
+
+        for y in 0..height * W {
+            for z in 0..W {
+                for x in 0..W {
+                    let id = ((x * 31 + y * 17 + z * 7 + seed) % 3) as u16;
+                    let pos = IVec3::new(origin.x + x, y, origin.y + z);
+                    chunk.get_or_insert_subchunk(y, &mut pool).unwrap().set_block(pos, BlockState {
+                        block: LocalID::new(id),
+                        light: Light::ZERO,
+                    });
+                }
+            }
+        }
+
+        chunk
+    }
+
+    fn assert_same(expected: &Chunk, actual: &Chunk) {
+        assert_eq!(expected.origin, actual.origin);
+        assert_eq!(expected.subchunks.len(), actual.subchunks.len());
+        for (expected, actual) in expected.subchunks.iter().zip(&actual.subchunks) {
+            assert_eq!(expected.ids(), actual.ids());
+        }
+    }
+
+    #[test]
+    fn region_coordinates() {
+        assert_eq!(IVec2::ZERO, to_region(IVec2::ZERO));
+        assert_eq!(IVec2::ZERO, to_region(IVec2::splat(W * 31)));
+        assert_eq!(IVec2::ONE, to_region(IVec2::splat(W * 32)));
+        assert_eq!(IVec2::NEG_ONE, to_region(IVec2::splat(-W)));
+        assert_eq!(IVec2::NEG_ONE, to_region(IVec2::splat(-W * 32)));
+        assert_eq!(IVec2::splat(-2), to_region(IVec2::splat(-W * 33)));
+
+        assert_eq!(0, to_region_index(IVec2::ZERO));
+        assert_eq!(REGION_LEN - 1, to_region_index(IVec2::splat(-W)));
+        assert_eq!(REGION_WIDTH as usize, to_region_index(IVec2::new(W * 32, W * 33)));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new("save");
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        let chunks = [
+            chunk(IVec2::new(0, 0), 2, 0),
+            chunk(IVec2::new(-W, -W), 1, 1),
+            chunk(IVec2::new(-W * 40, W * 3), 3, 2),
+        ];
+
+        let mut storage = RegionStorage::open(&dir.0).unwrap();
+        for chunk in &chunks {
+            storage.save(chunk, &blocks, &biomes).unwrap();
+        }
+
+        // reopen the files from disk.
+        storage.close();
+        for chunk in &chunks {
+            let loaded = storage.load(chunk.origin, &blocks, &biomes).unwrap().unwrap();
+            assert_same(chunk, &loaded);
+        }
+        assert!(storage.load(IVec2::new(W, 0), &blocks, &biomes).unwrap().is_none());
+        assert!(storage.region_path(IVec2::new(-2, 0)).exists());
+    }
+
+    #[test]
+    fn load_into_world() {
+        let dir = TempDir::new("world");
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        let origin = IVec2::new(-W, W);
+        let mut world = World::new();
+        world.insert(chunk(origin, 1, 0));
+
+        let mut storage = RegionStorage::open(&dir.0).unwrap();
+        assert!(storage.unload_from(&mut world, origin, &blocks, &biomes).unwrap());
+        assert!(world.get_chunk_with_origin(origin).is_none());
+        assert!(!storage.unload_from(&mut world, origin, &blocks, &biomes).unwrap());
+
+        assert!(storage.load_into(&mut world, origin, &blocks, &biomes).unwrap());
+        assert_same(&chunk(origin, 1, 0), world.get_chunk_with_origin(origin).unwrap());
+    }
+
+    #[test]
+    fn failed_write_frees_sectors() {
+        let dir = TempDir::new("failed");
+        let path = dir.0.join("region.mcr");
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let data = vec![7u8; SECTOR_LEN as usize * 3];
+
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write(IVec2::ZERO, &data).unwrap();
+        let used = region.used.clone();
+
+        // a read-only handle makes every write fail.
+        region.file = File::open(&path).unwrap();
+        assert!(region.write(IVec2::new(W, 0), &data).is_err());
+        assert!(region.write(IVec2::ZERO, &data).is_err());
+        assert_eq!(used, region.used[..used.len()]);
+        assert!(region.used[used.len()..].iter().all(|used| !used));
+        assert_eq!(Some(data), region.read(IVec2::ZERO).unwrap());
+        assert_eq!(None, region.read(IVec2::new(W, 0)).unwrap());
+    }
+
+    #[test]
+    fn reuses_freed_sectors() {
+        let dir = TempDir::new("reuse");
+        let path = dir.0.join("region.mcr");
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let data = vec![7u8; SECTOR_LEN as usize * 3];
+
+        let mut region = RegionFile::open(&path).unwrap();
+        region.write(IVec2::ZERO, &data).unwrap();
+        region.write(IVec2::new(W, 0), &data).unwrap();
+        let sectors = region.sectors();
+
+        // the removed chunk's sectors are reused.
+        assert!(region.remove(IVec2::ZERO).unwrap());
+        region.write(IVec2::new(W * 2, 0), &data).unwrap();
+        assert_eq!(sectors, region.sectors());
+
+        // a rewrite can't overwrite the chunk's own sectors, so the
+        // first rewrite grows the file, but every later rewrite reuses
+        // the sectors freed by the rewrite before it.
+        for _ in 0..4 {
+            region.write(IVec2::new(W, 0), &data).unwrap();
+            assert_eq!(sectors + 4, region.sectors());
+        }
+        assert_eq!((sectors + 4) as u64 * SECTOR_LEN, std::fs::metadata(&path).unwrap().len());
+
+        drop(region);
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(None, region.read(IVec2::ZERO).unwrap());
+        assert_eq!(Some(data.clone()), region.read(IVec2::new(W, 0)).unwrap());
+        assert_eq!(Some(data), region.read(IVec2::new(W * 2, 0)).unwrap());
+    }
+
+    #[test]
+    fn survives_partial_writes() {
+        let dir = TempDir::new("partial");
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        let mut storage = RegionStorage::open(&dir.0).unwrap();
+        let first = chunk(IVec2::ZERO, 2, 0);
+        let second = chunk(IVec2::new(W, 0), 1, 1);
+        storage.save(&first, &blocks, &biomes).unwrap();
+        storage.save(&second, &blocks, &biomes).unwrap();
+        storage.close();
+
+        // a rewrite of the first chunk that was interrupted before the
+        // offset table was updated leaves garbage at the end of the file.
+        let path = storage.region_path(IVec2::ZERO);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0xAB; 5000]).unwrap();
+        drop(file);
+
+        let loaded = storage.load(IVec2::ZERO, &blocks, &biomes).unwrap().unwrap();
+        assert_same(&first, &loaded);
+
+        // the garbage sectors are free, and are reused.
+        storage.save(&chunk(IVec2::new(W * 2, 0), 1, 2), &blocks, &biomes).unwrap();
+        storage.close();
+
+        // a file that was cut off loses the chunks at the end, but not the rest.
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 10).unwrap();
+        assert_same(&first, &storage.load(IVec2::ZERO, &blocks, &biomes).unwrap().unwrap());
+        assert_same(&second, &storage.load(IVec2::new(W, 0), &blocks, &biomes).unwrap().unwrap());
+        assert!(storage.load(IVec2::new(W * 2, 0), &blocks, &biomes).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_corrupt_chunks() {
+        let dir = TempDir::new("corrupt");
+        let (blocks, biomes) = (blocks_for_testing(), biomes_for_testing());
+        let mut storage = RegionStorage::open(&dir.0).unwrap();
+        storage
+            .region(IVec2::ZERO)
+            .unwrap()
+            .write(IVec2::ZERO, b"not a chunk")
+            .unwrap();
+
+        assert!(matches!(
+            storage.load(IVec2::ZERO, &blocks, &biomes),
+            Err(StorageError::Decode(ChunkDecodeError::BadMagic))
+        ));
+    }
+}
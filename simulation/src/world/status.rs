@@ -0,0 +1,84 @@
+use super::*;
+
+/// How far a chunk has come through world generation. The statuses
+/// are in order, so a chunk with a status has finished every stage
+/// before it.
+///
+/// Chunks start out `Full`, because chunks that are built by hand are
+/// complete, and chunks loaded from a save keep the status they were
+/// saved with, see `codec`. Generators set the status of the chunks
+/// they produce, and the generation scheduler advances chunks through
+/// the later stages once their neighbours are ready, see
+/// `terrain::GenerationScheduler`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ChunkStatus {
+    /// The chunk has no terrain yet.
+    Empty,
+
+    /// The columns of the chunk were filled.
+    Noise,
+
+    /// Caves and ravines were carved out of the chunk.
+    Carvers,
+
+    /// The features of the chunk were placed, which may
+    /// have written into the neighbouring chunks.
+    Features,
+
+    /// The chunk was lit.
+    Light,
+
+    /// Every neighbour of the chunk was lit too,
+    /// so the chunk won't change any more.
+    #[default]
+    Full,
+}
+
+impl ChunkStatus {
+    pub const ALL: [Self; 6] = [
+        Self::Empty,
+        Self::Noise,
+        Self::Carvers,
+        Self::Features,
+        Self::Light,
+        Self::Full,
+    ];
+
+    /// The status after this one, or None if the chunk is full.
+    pub const fn next(self) -> Option<Self> {
+        match self {
+            Self::Empty => Some(Self::Noise),
+            Self::Noise => Some(Self::Carvers),
+            Self::Carvers => Some(Self::Features),
+            Self::Features => Some(Self::Light),
+            Self::Light => Some(Self::Full),
+            Self::Full => None,
+        }
+    }
+}
+
+impl Chunk {
+    /// How far the chunk has come through world generation.
+    pub fn status(&self) -> ChunkStatus {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: ChunkStatus) {
+        self.status = status;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_are_ordered() {
+        for pair in ChunkStatus::ALL.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert_eq!(Some(pair[1]), pair[0].next());
+        }
+        assert_eq!(None, ChunkStatus::Full.next());
+        assert_eq!(ChunkStatus::Full, Chunk::new(IVec2::ZERO).status());
+    }
+}
@@ -0,0 +1,483 @@
+//! Streaming chunks in and out of the world around players.
+//!
+//! The `ChunkStreamer` decides which chunks should be resident, given the
+//! positions of the players and a view distance. Chunks that are missing
+//! are loaded or generated by a `ChunkSource` on the rayon thread pool,
+//! and inserted into the world once they are finished. Chunks that are
+//! too far from every player are taken out of the world and saved on the
+//! thread pool. Subchunk boxes are recycled through the World's pool, so
+//! once the streamer warms up, moving around the world doesn't allocate.
+
+use std::collections::HashSet;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+
+use crate::blocks::Block;
+use crate::data::Registry;
+use crate::terrain::Biome;
+
+use super::region::RegionStorage;
+use super::{Chunk, ChunkOrigin, SubChunkPool, World, WorldPos3, CHUNK_WIDTH};
+use super::chunk::to_chunk_origin;
+
+/// Something that can produce chunks, such as a save or a terrain generator.
+/// Chunks are loaded and saved on worker threads.
+pub trait ChunkSource: Send + Sync + 'static {
+    /// Load or generate the chunk with this origin. Subchunks should be
+    /// taken from the pool, which was split off the World's pool, see
+    /// `ChunkBuilder`. Returns None if the chunk can't be produced, in
+    /// which case it is requested again on a later update.
+    fn load(&self, origin: ChunkOrigin, pool: &mut SubChunkPool) -> Option<Chunk>;
+
+    /// Save a chunk that is being unloaded. The default does nothing,
+    /// for sources that can always produce the chunk again.
+    fn save(&self, chunk: &Chunk) {
+        let _ = chunk;
+    }
+}
+
+/// A source that loads chunks from region files, falling back
+/// to another source for chunks that were never saved.
+pub struct RegionSource<S: ChunkSource> {
+    storage: Mutex<RegionStorage>,
+    blocks: Arc<Registry<Block>>,
+    biomes: Arc<Registry<Biome>>,
+    fallback: S,
+}
+
+impl<S: ChunkSource> RegionSource<S> {
+    pub fn new(
+        storage: RegionStorage,
+        blocks: Arc<Registry<Block>>,
+        biomes: Arc<Registry<Biome>>,
+        fallback: S,
+    ) -> Self {
+        Self {
+            storage: Mutex::new(storage),
+            blocks,
+            biomes,
+            fallback,
+        }
+    }
+}
+
+impl<S: ChunkSource> ChunkSource for RegionSource<S> {
+    fn load(&self, origin: ChunkOrigin, pool: &mut SubChunkPool) -> Option<Chunk> {
+        let loaded = self.storage.lock().unwrap().load_with(origin, &self.blocks, &self.biomes, pool);
+        match loaded {
+            Ok(Some(chunk)) => Some(chunk),
+            Ok(None) => self.fallback.load(origin, pool),
+            Err(err) => {
+                log::error!("Failed to load chunk at {origin}: {err}");
+                None
+            }
+        }
+    }
+
+    fn save(&self, chunk: &Chunk) {
+        if let Err(err) = self.storage.lock().unwrap().save(chunk, &self.blocks, &self.biomes) {
+            log::error!("Failed to save chunk at {}: {err}", chunk.origin());
+        }
+    }
+}
+
+/// The result of a task on the thread pool.
+enum Finished {
+    Loaded {
+        origin: ChunkOrigin,
+        chunk: Option<Chunk>,
+        pool: SubChunkPool,
+    },
+    Saved {
+        chunk: Chunk,
+    },
+}
+
+/// Marks an entity whose position decides which chunks are loaded.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct ChunkLoader;
+
+/// Loads chunks around players and unloads chunks that are far
+/// from every player, see the module documentation.
+#[derive(Resource)]
+pub struct ChunkStreamer {
+    source: Arc<dyn ChunkSource>,
+
+    /// The radius around each player that is loaded, in chunks.
+    view_distance: i32,
+
+    /// The maximum number of chunks that can be loading at once.
+    max_loads: usize,
+
+    /// The positions of the players at the last update.
+    players: Vec<WorldPos3>,
+
+    /// The chunks that are being loaded.
+    loading: HashSet<ChunkOrigin>,
+
+    /// The chunks that are being saved, which aren't loaded again
+    /// until they are saved, so the load doesn't read a stale save.
+    saving: HashSet<ChunkOrigin>,
+
+    /// The number of subchunks in the last chunk that was loaded,
+    /// which is the number of boxes split off the pool for each load.
+    height_hint: usize,
+
+    sender: Sender<Finished>,
+    receiver: Mutex<Receiver<Finished>>,
+}
+
+impl ChunkStreamer {
+    pub fn new(source: impl ChunkSource, view_distance: i32) -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            source: Arc::new(source),
+            view_distance,
+            max_loads: 16,
+            players: Vec::new(),
+            loading: HashSet::new(),
+            saving: HashSet::new(),
+            height_hint: 0,
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    /// Set the maximum number of chunks that can be loading at once.
+    pub fn with_max_loads(mut self, max_loads: usize) -> Self {
+        self.max_loads = max_loads.max(1);
+        self
+    }
+
+    pub fn view_distance(&self) -> i32 {
+        self.view_distance
+    }
+
+    pub fn set_view_distance(&mut self, view_distance: i32) {
+        self.view_distance = view_distance;
+    }
+
+    /// The number of chunks that are being loaded.
+    pub fn loading(&self) -> usize {
+        self.loading.len()
+    }
+
+    /// The number of chunks that are being saved.
+    pub fn saving(&self) -> usize {
+        self.saving.len()
+    }
+
+    /// Returns true if the chunk is within the view distance of a player.
+    pub fn is_wanted(&self, origin: ChunkOrigin) -> bool {
+        self.players
+            .iter()
+            .any(|player| self.distance_squared(origin, *player) <= self.view_distance.pow(2))
+    }
+
+    /// Insert the chunks that finished loading, unload the chunks that are
+    /// far from every player, and start loading the missing chunks that are
+    /// closest to a player. This should be called once per tick.
+    pub fn update(&mut self, world: &mut World, players: &[WorldPos3]) {
+        self.players.clear();
+        self.players.extend_from_slice(players);
+
+        // insert the chunks that finished loading.
+        let finished: Vec<_> = self.receiver.lock().unwrap().try_iter().collect();
+        for finished in finished {
+            self.finish(world, finished);
+        }
+
+        // unload the chunks that are too far away.
+        let unwanted: Vec<_> = world
+            .origins()
+            .filter(|origin| !self.is_wanted(*origin))
+            .collect();
+        for origin in unwanted {
+            let chunk = world.take(origin).expect("origin is in the world");
+            let source = self.source.clone();
+            let sender = self.sender.clone();
+            self.saving.insert(origin);
+            rayon::spawn(move || {
+                source.save(&chunk);
+                let _ = sender.send(Finished::Saved { chunk });
+            });
+        }
+
+        // load the missing chunks, closest first.
+        let available = self.max_loads.saturating_sub(self.loading.len());
+        for origin in self.missing(world).into_iter().take(available) {
+            let mut pool = world.pool_mut().split(self.height_hint);
+            let source = self.source.clone();
+            let sender = self.sender.clone();
+            self.loading.insert(origin);
+            rayon::spawn(move || {
+                let chunk = source.load(origin, &mut pool);
+                let _ = sender.send(Finished::Loaded { origin, chunk, pool });
+            });
+        }
+    }
+
+    /// Block until every chunk that is loading or saving is finished,
+    /// inserting the loaded chunks that are still wanted. This should
+    /// be called before exiting, so every unloaded chunk is saved.
+    pub fn wait(&mut self, world: &mut World) {
+        while !self.loading.is_empty() || !self.saving.is_empty() {
+            let finished = self
+                .receiver
+                .lock()
+                .unwrap()
+                .recv()
+                .expect("the streamer holds a sender");
+            self.finish(world, finished);
+        }
+    }
+
+    fn finish(&mut self, world: &mut World, finished: Finished) {
+        match finished {
+            Finished::Loaded { origin, chunk, pool } => {
+                self.loading.remove(&origin);
+                world.pool_mut().merge(pool);
+
+                match chunk {
+                    Some(chunk) if self.is_wanted(origin) => {
+                        self.height_hint = chunk.height();
+                        world.insert(chunk);
+                    }
+                    // the players moved away while the chunk was loading.
+                    Some(chunk) => world.pool_mut().free_chunk(chunk),
+                    None => log::warn!("Chunk at {origin} could not be loaded"),
+                }
+            }
+            Finished::Saved { chunk } => {
+                self.saving.remove(&chunk.origin());
+                world.pool_mut().free_chunk(chunk);
+            }
+        }
+    }
+
+    /// The chunks within the view distance of a player that are not in
+    /// the world and are not loading or saving, ordered by distance to
+    /// a player.
+    fn missing(&self, world: &World) -> Vec<ChunkOrigin> {
+        const W: i32 = CHUNK_WIDTH as i32;
+        let r = self.view_distance;
+        let mut seen = HashSet::new();
+        let mut missing = Vec::new();
+
+        for player in &self.players {
+            let center = to_chunk_origin(player.xz());
+            for dz in -r..=r {
+                for dx in -r..=r {
+                    let origin = center + IVec2::new(dx, dz) * W;
+                    if dx * dx + dz * dz > r * r
+                        || !seen.insert(origin)
+                        || self.loading.contains(&origin)
+                        || self.saving.contains(&origin)
+                        || world.get_chunk_with_origin(origin).is_some()
+                    {
+                        continue;
+                    }
+
+                    let distance = self
+                        .players
+                        .iter()
+                        .map(|player| self.distance_squared(origin, *player))
+                        .min()
+                        .unwrap();
+                    missing.push((distance, origin.x, origin.y));
+                }
+            }
+        }
+
+        missing.sort_unstable();
+        missing
+            .into_iter()
+            .map(|(_, x, z)| IVec2::new(x, z))
+            .collect()
+    }
+
+    /// The squared distance between the chunk and the
+    /// chunk containing the player, in chunks.
+    fn distance_squared(&self, origin: ChunkOrigin, player: WorldPos3) -> i32 {
+        let offset = (origin - to_chunk_origin(player.xz())) / CHUNK_WIDTH as i32;
+        offset.length_squared()
+    }
+}
+
+/// Stream chunks around every entity with a `ChunkLoader`.
+pub fn stream_chunks(
+    mut streamer: ResMut<ChunkStreamer>,
+    mut world: ResMut<World>,
+    loaders: Query<&GlobalTransform, With<ChunkLoader>>,
+) {
+    let players: Vec<_> = loaders
+        .iter()
+        .map(|transform| transform.translation().floor().as_ivec3())
+        .collect();
+    streamer.update(&mut world, &players);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+
+    const W: i32 = CHUNK_WIDTH as i32;
+
+    /// Generates chunks with one subchunk,
+    /// counting the subchunks it allocates.
+    #[derive(Default)]
+    struct FlatSource {
+        allocated: AtomicUsize,
+        saved: Mutex<Vec<ChunkOrigin>>,
+    }
+
+    impl ChunkSource for Arc<FlatSource> {
+        fn load(&self, origin: ChunkOrigin, pool: &mut SubChunkPool) -> Option<Chunk> {
+            let chunk = Chunk::with_subchunks(origin, 1, pool);
+            self.allocated.fetch_add(pool.stats().allocated as usize, Ordering::Relaxed);
+            Some(chunk)
+        }
+
+        fn save(&self, chunk: &Chunk) {
+            self.saved.lock().unwrap().push(chunk.origin());
+        }
+    }
+
+    /// The number of chunks within a view distance of 2.
+    const VIEW_2: usize = 13;
+
+    fn stream(streamer: &mut ChunkStreamer, world: &mut World, player: WorldPos3) {
+        // the first update starts the loads, the second inserts them.
+        streamer.update(world, &[player]);
+        streamer.wait(world);
+        streamer.update(world, &[player]);
+    }
+
+    #[test]
+    fn streams_around_player() {
+        let source = Arc::new(FlatSource::default());
+        let mut streamer = ChunkStreamer::new(source.clone(), 2);
+        let mut world = World::new();
+
+        let player = IVec3::new(-5, 10, 40);
+        stream(&mut streamer, &mut world, player);
+        assert_eq!(VIEW_2, world.origins().count());
+        assert_eq!(0, streamer.loading());
+
+        let center = IVec2::new(-W, W);
+        for origin in world.origins() {
+            let offset = (origin - center) / W;
+            assert!(offset.length_squared() <= 4, "{origin} is too far");
+        }
+        assert!(world.get_chunk_with_origin(center + IVec2::new(W * 2, 0)).is_some());
+        assert!(world.get_chunk_with_origin(center + IVec2::new(W * 2, W)).is_none());
+    }
+
+    #[test]
+    fn loads_closest_first() {
+        let source = Arc::new(FlatSource::default());
+        let mut streamer = ChunkStreamer::new(source, 3).with_max_loads(5);
+        let mut world = World::new();
+
+        streamer.update(&mut world, &[IVec3::ZERO]);
+        assert_eq!(5, streamer.loading());
+        streamer.wait(&mut world);
+
+        // the chunk containing the player and its 4 direct neighbours.
+        let mut origins: Vec<_> = world.origins().collect();
+        origins.sort_by_key(|origin| (origin.x, origin.y));
+        assert_eq!(
+            vec![IVec2::new(-W, 0), IVec2::new(0, -W), IVec2::new(0, 0), IVec2::new(0, W), IVec2::new(W, 0)],
+            origins
+        );
+    }
+
+    #[test]
+    fn unloads_and_recycles() {
+        let source = Arc::new(FlatSource::default());
+        let mut streamer = ChunkStreamer::new(source.clone(), 2);
+        let mut world = World::new();
+
+        stream(&mut streamer, &mut world, IVec3::ZERO);
+        assert_eq!(VIEW_2, source.allocated.load(Ordering::Relaxed));
+
+        // move far away, so every chunk is unloaded and saved.
+        let far = IVec3::new(W * 100, 0, 0);
+        stream(&mut streamer, &mut world, far);
+        streamer.wait(&mut world);
+        assert_eq!(VIEW_2, source.saved.lock().unwrap().len());
+        assert!(world.origins().all(|origin| origin.x >= W * 98));
+        assert_eq!(VIEW_2, world.pool().len());
+
+        // moving back reuses the boxes of the chunks that were unloaded.
+        let allocated = source.allocated.load(Ordering::Relaxed);
+        stream(&mut streamer, &mut world, IVec3::ZERO);
+        assert_eq!(allocated, source.allocated.load(Ordering::Relaxed));
+        assert_eq!(VIEW_2, world.origins().count());
+    }
+
+    /// Holds saves until it is released, counting the loads.
+    #[derive(Default)]
+    struct SlowSaves {
+        released: AtomicBool,
+        loads: AtomicUsize,
+    }
+
+    impl ChunkSource for Arc<SlowSaves> {
+        fn load(&self, origin: ChunkOrigin, pool: &mut SubChunkPool) -> Option<Chunk> {
+            self.loads.fetch_add(1, Ordering::Relaxed);
+            Some(Chunk::with_subchunks(origin, 1, pool))
+        }
+
+        fn save(&self, _: &Chunk) {
+            while !self.released.load(Ordering::Relaxed) {
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    #[test]
+    fn waits_for_saves_before_loading() {
+        let source = Arc::new(SlowSaves::default());
+        let mut streamer = ChunkStreamer::new(source.clone(), 0);
+        let mut world = World::new();
+
+        stream(&mut streamer, &mut world, IVec3::ZERO);
+        assert_eq!(1, source.loads.load(Ordering::Relaxed));
+
+        // leaving and straight coming back doesn't load the chunk while it saves.
+        streamer.update(&mut world, &[]);
+        assert_eq!(1, streamer.saving());
+        streamer.update(&mut world, &[IVec3::ZERO]);
+        streamer.update(&mut world, &[IVec3::ZERO]);
+        assert_eq!(0, streamer.loading());
+        assert!(world.get_chunk_with_origin(IVec2::ZERO).is_none());
+
+        source.released.store(true, Ordering::Relaxed);
+        streamer.wait(&mut world);
+        assert_eq!(0, streamer.saving());
+        stream(&mut streamer, &mut world, IVec3::ZERO);
+        assert!(world.get_chunk_with_origin(IVec2::ZERO).is_some());
+    }
+
+    #[test]
+    fn multiple_players() {
+        let source = Arc::new(FlatSource::default());
+        let mut streamer = ChunkStreamer::new(source, 1);
+        let mut world = World::new();
+
+        let players = [IVec3::ZERO, IVec3::new(W, 0, 0), IVec3::new(W * 10, 0, 0)];
+        streamer.update(&mut world, &players);
+        streamer.wait(&mut world);
+
+        // two overlapping views of 5 chunks share 2 chunks.
+        assert_eq!(5 + 5 - 2 + 5, world.origins().count());
+        for player in players {
+            assert!(world.get_chunk(player).is_some());
+        }
+    }
+}
@@ -1,10 +1,34 @@
 
 use chunk::{to_subchunk_origin, CHUNK_LEN};
 
-use crate::{blocks::Light, data::registry::LocalID};
+use crate::blocks::{Block, Light};
+use crate::data::registry::LocalID;
+use crate::data::{Id, Registry};
+use crate::terrain::{register_biomes, Biome};
 
 use super::*;
 
+/// The blocks that the terrain generators and features place, so the
+/// LocalID of each block is its index in this list.
+pub fn blocks_for_testing() -> Registry<Block> {
+    let mut blocks = Registry::new("blocks");
+    let names = [
+        "air", "stone", "dirt", "grass", "sand", "snow",
+        "coal_ore", "iron_ore", "oak_log", "oak_leaves", "cobblestone", "flower",
+    ];
+    for name in names {
+        blocks.add(Id::new(name), Block::default());
+    }
+    blocks
+}
+
+/// The biomes of the overworld, see `register_biomes`.
+pub fn biomes_for_testing() -> Registry<Biome> {
+    let mut biomes = Registry::new("biomes");
+    register_biomes(&mut biomes);
+    biomes
+}
+
 /// 3x3x3 world for testing purposes.
 pub fn world_for_testing() -> World {
     let mut world = World::new();
@@ -17,7 +41,7 @@ pub fn world_for_testing() -> World {
                 subs.push(subchunk_for_testing(IVec3::new(x, y, z) * CHUNK_WIDTH as i32));
             }
 
-            world.insert(Chunk { origin, subchunks: subs });
+            world.insert(Chunk { origin, subchunks: subs, heightmaps: Heightmaps::new(), biomes: BiomeMap::new(), status: ChunkStatus::Full });
         }
     }
 
@@ -30,13 +54,12 @@ pub fn subchunk_for_testing(origin: IVec3) -> Box<SubChunk> {
     Box::new(SubChunk {
         origin,
         blocks: (0..CHUNK_LEN)
-            .map(|i| BlockState {
-                block: LocalID::new(i as u32),
-                light: Light::ZERO,
-            })
+            .map(|i| LocalID::new(i as u16))
             .collect::<Vec<_>>()
             .try_into()
             .unwrap(),
+        light: [Light::ZERO; CHUNK_LEN],
+        dirty: 0,
     })
 }
 
@@ -48,5 +71,8 @@ pub fn chunk_for_testing(origin: IVec2) -> Chunk {
                 subchunk_for_testing(IVec3::new(origin.x, i * CHUNK_WIDTH as i32, origin.y))
             }),
         ),
+        heightmaps: Heightmaps::new(),
+        biomes: BiomeMap::new(),
+        status: ChunkStatus::Full,
     }
 }
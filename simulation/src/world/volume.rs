@@ -1,6 +1,40 @@
 use super::*;
-use bevy::math::UVec3;
-use chunk::to_subchunk_origin;
+use chunk::{to_chunk_origin, to_subchunk_origin};
+
+/// A box of blocks in the world, from `min` up to but not including `max`.
+/// Unlike a `Volume`, a box doesn't borrow the world, so it can be written
+/// with `WorldWriter::fill`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockBox {
+    pub min: WorldPos3,
+    pub max: WorldPos3,
+}
+
+impl BlockBox {
+    pub const fn new(min: WorldPos3, max: WorldPos3) -> Self {
+        Self { min, max }
+    }
+
+    /// The box starting at origin and extending by extent (exclusive),
+    /// which is the same selection as `WorldReader::volume`.
+    pub fn from_extent(origin: WorldPos3, extent: IVec3) -> Self {
+        Self::new(origin, origin + extent)
+    }
+
+    pub fn extent(&self) -> IVec3 {
+        self.max - self.min
+    }
+
+    /// Returns true if the box has no blocks in it.
+    pub fn is_empty(&self) -> bool {
+        self.max.cmple(self.min).any()
+    }
+
+    /// Returns true if the position is in the box.
+    pub fn contains(&self, pos: WorldPos3) -> bool {
+        pos.cmpge(self.min).all() && pos.cmplt(self.max).all()
+    }
+}
 
 /// An Iterator over 3d space in the world.
 #[derive(Clone, Debug)]
@@ -19,6 +53,11 @@ impl<'w> Volume<'w> {
         }
     }
 
+    /// The blocks selected by the volume.
+    pub fn bounds(&self) -> BlockBox {
+        BlockBox::from_extent(self.origin, self.extent)
+    }
+
     /// Expand the volume by some amount in all directions.
     pub fn expand(self, amt: IVec3) -> Self {
         Volume {
@@ -32,7 +71,7 @@ impl<'w> Volume<'w> {
     /// returning the fragment if it exists.
     pub fn intersection(&self, subchunk: &'w SubChunk) -> Option<Fragment<'w>> {
         let min = self.origin.max(subchunk.origin);
-        let max = (self.origin + self.extent).min(subchunk.origin + IVec3::splat(16));
+        let max = (self.origin + self.extent).min(subchunk.origin + IVec3::splat(CHUNK_WIDTH as i32));
 
         if max.x > min.x && max.y > min.y && max.z > min.z {
             Some(Fragment {
@@ -46,27 +85,23 @@ impl<'w> Volume<'w> {
         }
     }
 
-    /// Compute the intersection of the volume and the chunk,
-    /// skipping the check to make sure they are non-overlapping.
-    /// If this volume and the chunk do not overlap, the result
-    /// of this operation will not be useful and my index out-of-bounds.
-    fn intersection_unchecked(&self, subchunk: &'w SubChunk) -> Fragment<'w> {
-        let min = self.origin.max(subchunk.origin);
-        let max = (self.origin + self.extent).min(subchunk.origin + IVec3::splat(16));
-
-        Fragment {
-            subchunk,
-            origin: min,
-            end: max,
-            curr: min,
-        }
-    }
-
     /// Returns true if this volume intersects the chunk.
     pub fn intersects(&self, subchunk: &'w SubChunk) -> bool {
         self.intersection(subchunk).is_some()
     }
 
+    /// Get an iterator over the columns in the volume, where
+    /// each column is all blocks in the volume with the same
+    /// [x,z], from bottom-to-top. Columns are visited in order
+    /// of z, then x. This is slower than `fragments`, but the
+    /// order blocks are visited is guaranteed.
+    pub fn columns(&self) -> Columns<'w> {
+        Columns {
+            curr: self.origin.xz(),
+            volume: self.clone(),
+        }
+    }
+
     /// Get an iterator over the blocks in the volume,
     /// where each fragment is all blocks in a chunk that
     /// overlaps this volume. This means that this iterator
@@ -74,11 +109,11 @@ impl<'w> Volume<'w> {
     /// the order blocks are visited.
     pub fn fragments(self) -> Fragments<'w> {
         let start = to_subchunk_origin(self.origin);
-        let end = to_subchunk_origin(start + self.extent);
+        let end = to_subchunk_origin(self.origin + self.extent - IVec3::ONE);
         Fragments {
             start,
             end,
-            curr: self.origin,
+            curr: start,
             volume: self,
         }
     }
@@ -96,7 +131,7 @@ pub struct Fragments<'w> {
     /// Origin of the subchunk at volume origin.
     pub start: IVec3,
 
-    /// Origin position of the subchunk at origin+extent+width.
+    /// Origin of the subchunk containing the last block of the volume.
     pub end: IVec3,
 
     /// Origin of last returned subchunk.
@@ -113,12 +148,13 @@ impl<'w> Iterator for Fragments<'w> {
                     // Fragments<'w> will skip chunks that are out-of-world.
                     let key = self.curr;
                     self.curr.y += CHUNK_WIDTH as i32;
-                    if let Some(subchunk) = self.volume.reader.get_subchunk(key) {
-                        return Some(
-                            self.volume
-                                .intersection(subchunk)
-                                .expect("Uh-Oh! thats a no no >:("),
-                        );
+                    let fragment = self
+                        .volume
+                        .reader
+                        .get_subchunk(key)
+                        .and_then(|subchunk| self.volume.intersection(subchunk));
+                    if fragment.is_some() {
+                        return fragment;
                     }
                 }
                 self.curr.y = self.start.y;
@@ -147,41 +183,139 @@ impl<'w> Iterator for Fragment<'w> {
     type Item = (WorldPos3, BlockState);
 
     fn next(&mut self) -> Option<Self::Item> {
+        // origin is the minimum block of the fragment and
+        // end is the maximum, exclusive.
+        if self.curr.z >= self.end.z {
+            return None;
+        }
+
         let pos = self.curr;
         let state = self.subchunk.get_block(pos);
 
         self.curr.y += 1;
-        if self.curr.y > self.origin.y + self.end.y {
+        if self.curr.y >= self.end.y {
             self.curr.y = self.origin.y;
             self.curr.x += 1;
 
-            if self.curr.x > self.origin.x + self.end.x {
+            if self.curr.x >= self.end.x {
                 self.curr.x = self.origin.x;
                 self.curr.z += 1;
+            }
+        }
+
+        Some((pos, state))
+    }
+}
+
+/// Iterator over the columns in a Volume, visiting
+/// every [x,z] in the volume in order of z, then x.
+///
+/// Only yields columns of chunks that are in-world.
+#[derive(Clone, Debug)]
+pub struct Columns<'w> {
+    pub volume: Volume<'w>,
 
-                if self.curr.z == self.origin.z + self.end.z {
-                    return None;
+    /// The [x,z] of the next column.
+    pub curr: IVec2,
+}
+
+impl<'w> Iterator for Columns<'w> {
+    type Item = Column<'w>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let min = self.volume.origin;
+        let max = self.volume.origin + self.volume.extent;
+
+        while self.curr.y < max.z {
+            while self.curr.x < max.x {
+                // Columns<'w> will skip chunks that are out-of-world.
+                let pos = self.curr;
+                self.curr.x += 1;
+                if let Some(chunk) = self.volume.reader.get_chunk_with_origin(to_chunk_origin(pos)) {
+                    // the column is clamped to the subchunks that exist.
+                    let height = (chunk.subchunks.len() * CHUNK_WIDTH) as i32;
+                    let bottom = min.y.max(0);
+                    return Some(Column {
+                        chunk,
+                        bottom: IVec3::new(pos.x, bottom, pos.y),
+                        top: max.y.min(height).max(bottom),
+                    });
                 }
             }
+            self.curr.x = min.x;
+            self.curr.y += 1;
         }
 
-        Some((pos, state))
+        None
     }
 }
 
+/// An iterator over the blocks in a column of the world,
+/// in the order of bottom-to-top. Use `Column::rev()`
+/// to iterate from top-to-bottom.
 #[derive(Clone, Debug)]
 pub struct Column<'w> {
     chunk: &'w Chunk,
+
+    /// The position of the next block from the bottom.
     bottom: IVec3,
+
+    /// The y-coordinate above the next block from the top.
     top: i32,
-    next: i32,
+}
+
+impl<'w> Column<'w> {
+    /// The [x,z] of the column.
+    pub fn position(&self) -> WorldPos2 {
+        self.bottom.xz()
+    }
 
-    /// The number of blocks remaining
-    /// in the current subchunk until the
-    /// next subchunk needs to be loaded.
-    rem: i32,
+    /// The chunk containing the column.
+    pub fn chunk(&self) -> &'w Chunk {
+        self.chunk
+    }
+
+    /// The block at this y-coordinate. The coordinate must be in
+    /// the range of the column, so every subchunk exists.
+    fn get(&self, y: i32) -> (WorldPos3, BlockState) {
+        let pos = self.bottom.with_y(y);
+        let subchunk = &self.chunk.subchunks[y as usize / CHUNK_WIDTH];
+        (pos, subchunk.get_block(pos))
+    }
 }
 
+impl<'w> Iterator for Column<'w> {
+    type Item = (WorldPos3, BlockState);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bottom.y >= self.top {
+            return None;
+        }
+
+        let item = self.get(self.bottom.y);
+        self.bottom.y += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.top - self.bottom.y) as usize;
+        (len, Some(len))
+    }
+}
+
+impl<'w> DoubleEndedIterator for Column<'w> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.bottom.y >= self.top {
+            return None;
+        }
+
+        self.top -= 1;
+        Some(self.get(self.top))
+    }
+}
+
+impl<'w> ExactSizeIterator for Column<'w> {}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
@@ -212,25 +346,31 @@ mod tests {
 
     #[test]
     fn volume_fragments_multi_subchunk() {
+        const W: i32 = CHUNK_WIDTH as i32;
         let world = world_for_testing();
         let reader = world.reader();
-        let volume = reader.volume(IVec3::new(-1, -1, -1), IVec3::splat(CHUNK_WIDTH as i32 + 1));
+
+        // the volume covers -1..=W - 1 in every axis, since the end is
+        // exclusive. There are no subchunks below y = 0, so y = -1 is skipped.
+        let volume = reader.volume(IVec3::new(-1, -1, -1), IVec3::splat(W + 1));
 
         let mut corners: HashSet<IVec3> = HashSet::from_iter([
-            IVec3::new(-1, -1, -1), 
-            IVec3::splat(CHUNK_WIDTH as i32),
-            IVec3::new(-1, CHUNK_WIDTH as i32, -1),
-            IVec3::new(CHUNK_WIDTH as i32, -1, -1),
-            IVec3::new(-1, -1, CHUNK_WIDTH as i32),
-            IVec3::new(CHUNK_WIDTH as i32, CHUNK_WIDTH as i32, -1),
-            IVec3::new(CHUNK_WIDTH as i32, -1, CHUNK_WIDTH as i32),
-            IVec3::new(-1, CHUNK_WIDTH as i32, CHUNK_WIDTH as i32),
+            IVec3::new(-1, 0, -1),
+            IVec3::new(W - 1, 0, -1),
+            IVec3::new(-1, 0, W - 1),
+            IVec3::new(W - 1, 0, W - 1),
+            IVec3::new(-1, W - 1, -1),
+            IVec3::new(W - 1, W - 1, -1),
+            IVec3::new(-1, W - 1, W - 1),
+            IVec3::new(W - 1, W - 1, W - 1),
         ]);
 
+        let mut visited = 0;
         for fragment in volume.fragments() {
-            eprintln!("origin: {}", fragment.origin);
             for (pos, state) in fragment {
+                assert!(pos.cmpge(IVec3::new(-1, 0, -1)).all() && pos.cmplt(IVec3::splat(W)).all(), "at: {pos}");
                 corners.remove(&pos);
+                visited += 1;
                 assert_eq!(
                     Some(state.block), 
                     reader.get_block(pos).map(|some| some.block),
@@ -239,6 +379,91 @@ mod tests {
             }
         }
 
-        assert_eq!(0, corners.len());
+        assert!(corners.is_empty(), "missed: {corners:?}");
+        assert_eq!((W + 1) * W * (W + 1), visited);
+    }
+
+    #[test]
+    fn volume_columns_order() {
+        const W: i32 = CHUNK_WIDTH as i32;
+        let world = world_for_testing();
+        let reader = world.reader();
+        let origin = IVec3::new(-2, W - 4, -3);
+        let volume = reader.volume(origin, IVec3::new(4, 8, 5));
+
+        let mut count = 0;
+        let mut expected = origin;
+        for column in volume.columns() {
+            assert_eq!(expected.xz(), column.position());
+            assert_eq!(8, column.len());
+
+            let mut y = origin.y;
+            for (pos, state) in column {
+                assert_eq!(expected.with_y(y), pos);
+                assert_eq!(reader.get_block(pos), Some(state), "at: {pos}");
+                y += 1;
+            }
+            assert_eq!(origin.y + 8, y);
+
+            count += 1;
+            expected.x += 1;
+            if expected.x == origin.x + 4 {
+                expected.x = origin.x;
+                expected.z += 1;
+            }
+        }
+        assert_eq!(4 * 5, count);
+    }
+
+    #[test]
+    fn volume_columns_rev() {
+        const W: i32 = CHUNK_WIDTH as i32;
+        let world = world_for_testing();
+        let reader = world.reader();
+        let volume = reader.volume(IVec3::new(5, 1, 5), IVec3::new(1, W * 2, 1));
+
+        let column = volume.columns().next().unwrap();
+        let forward: Vec<_> = column.clone().collect();
+        let mut backward: Vec<_> = column.rev().collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+        assert_eq!(IVec3::new(5, W * 2, 5), forward.last().unwrap().0);
+    }
+
+    #[test]
+    fn volume_columns_both_ends() {
+        let world = world_for_testing();
+        let reader = world.reader();
+        let mut column = reader
+            .volume(IVec3::new(0, 30, 0), IVec3::new(1, 4, 1))
+            .columns()
+            .next()
+            .unwrap();
+
+        assert_eq!(30, column.next().unwrap().0.y);
+        assert_eq!(33, column.next_back().unwrap().0.y);
+        assert_eq!(31, column.next().unwrap().0.y);
+        assert_eq!(32, column.next_back().unwrap().0.y);
+        assert_eq!(0, column.len());
+        assert!(column.next().is_none());
+        assert!(column.next_back().is_none());
+    }
+
+    #[test]
+    fn volume_columns_clamped() {
+        const W: i32 = CHUNK_WIDTH as i32;
+        let world = world_for_testing();
+        let reader = world.reader();
+
+        // the world is 3 subchunks high and 3x3 chunks
+        // wide, centered on the origin.
+        let volume = reader.volume(IVec3::new(W * 2 - 2, -5, 0), IVec3::new(4, W * 4, 1));
+        let columns: Vec<_> = volume.columns().collect();
+        assert_eq!(2, columns.len());
+        for mut column in columns {
+            assert_eq!(W as usize * 3, column.len());
+            assert_eq!(0, column.clone().next().unwrap().0.y);
+            assert_eq!(W * 3 - 1, column.next_back().unwrap().0.y);
+        }
     }
 }
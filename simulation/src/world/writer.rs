@@ -0,0 +1,327 @@
+use chunk::{to_chunk_origin, to_subchunk_index_prewrapped};
+use std::ptr::null_mut;
+
+use crate::blocks::Light;
+
+use super::*;
+
+/// A writer that caches the last accessed
+/// chunk for faster mutation of the world.
+///
+/// Writing to a position above the highest subchunk
+/// of a chunk creates every missing subchunk between
+/// the top of the chunk and the position, taking
+/// them from the World's pool. Positions at or above
+/// `MAX_HEIGHT` are outside of the world.
+///
+/// Every block that is changed is recorded in the
+/// World's ChangeLog, see `World::drain_changes`,
+/// and the heightmaps of its chunk are updated.
+pub struct WorldWriter<'w> {
+    chunks: &'w mut BTreeMap<u64, Chunk>,
+    changes: &'w mut ChangeLog,
+    pool: &'w mut SubChunkPool,
+    heightmaps: &'w HeightmapTable,
+
+    /// The last accessed chunk. This points into `chunks`, which
+    /// the writer borrows mutably, and the writer never inserts or
+    /// removes chunks, so the pointer is valid for as long as it is set.
+    last: *mut Chunk,
+}
+
+impl<'w> WorldWriter<'w> {
+    /// Get the chunk with this origin, if it is loaded.
+    pub fn get_chunk(&mut self, origin: ChunkOrigin) -> Option<&Chunk> {
+        get_chunk_cached(self.chunks, &mut self.last, origin).map(|chunk| &*chunk)
+    }
+
+    /// Get the block at this position.
+    pub fn get_block(&mut self, pos: WorldPos3) -> Option<BlockState> {
+        get_chunk_cached(self.chunks, &mut self.last, to_chunk_origin(pos.xz()))?
+            .get_block(pos)
+    }
+
+    /// Set the block at this position, returning the block that
+    /// was replaced. Returns None if the chunk containing the
+    /// position is not loaded or the position is below or above
+    /// the world.
+    pub fn set_block(&mut self, pos: WorldPos3, state: BlockState) -> Option<BlockState> {
+        let chunk = get_chunk_cached(self.chunks, &mut self.last, to_chunk_origin(pos.xz()))?;
+        let subchunk = chunk.get_or_insert_subchunk(pos.y, self.pool)?;
+
+        let old = subchunk.get_block_mut(pos).set(state);
+        self.changes.record(subchunk, pos, old, state);
+        chunk.update_heightmaps(pos.xz(), pos.y, pos.y + 1, state, self.heightmaps);
+        Some(old)
+    }
+
+    /// Set the light of the block at this position, returning the
+    /// light that was replaced. Returns None if the block does not
+    /// exist. Light changes mark the subchunk dirty, but are not
+    /// recorded as block changes.
+    pub fn set_light(&mut self, pos: WorldPos3, light: Light) -> Option<Light> {
+        let subchunk = get_chunk_cached(self.chunks, &mut self.last, to_chunk_origin(pos.xz()))?
+            .get_subchunk_mut(pos.y)?;
+
+        let old = subchunk.set_light(pos, light);
+        if old != light {
+            self.changes.mark_dirty(subchunk, pos);
+        }
+        Some(old)
+    }
+
+    /// Set many blocks at once, returning the number of blocks
+    /// that were written. Positions in chunks that are not loaded
+    /// are skipped. Blocks that are close to each other should be
+    /// adjacent in the iterator so the chunk cache is hit.
+    pub fn set_blocks<I>(&mut self, blocks: I) -> usize
+    where
+        I: IntoIterator<Item = (WorldPos3, BlockState)>,
+    {
+        let mut written = 0;
+        for (pos, state) in blocks {
+            if self.set_block(pos, state).is_some() {
+                written += 1;
+            }
+        }
+        written
+    }
+
+    /// Set every block in the box to the state, returning the number
+    /// of blocks that were written. Chunks that are not loaded and
+    /// blocks outside of the world are skipped. Use `Volume::bounds`
+    /// to fill the blocks of a Volume.
+    pub fn fill(&mut self, bounds: BlockBox, state: BlockState) -> usize {
+        const W: i32 = CHUNK_WIDTH as i32;
+
+        // blocks below and above the world can't be written.
+        let min = bounds.min.with_y(bounds.min.y.max(0));
+        let max = bounds.max.with_y(bounds.max.y.min(MAX_HEIGHT));
+        if max.x <= min.x || max.y <= min.y || max.z <= min.z {
+            return 0;
+        }
+
+        let start = to_subchunk_origin(min);
+        let mut written = 0;
+
+        let mut cz = start.z;
+        while cz < max.z {
+            let mut cx = start.x;
+            while cx < max.x {
+                if let Some(chunk) = get_chunk_cached(self.chunks, &mut self.last, IVec2::new(cx, cz)) {
+                    let mut cy = start.y;
+                    while cy < max.y {
+                        let subchunk = chunk
+                            .get_or_insert_subchunk(cy, self.pool)
+                            .expect("fill is clamped to the world");
+
+                        // the fragment of the volume within this subchunk,
+                        // relative to the origin of the subchunk.
+                        let sub_origin = IVec3::new(cx, cy, cz);
+                        let lo = min.max(sub_origin) - sub_origin;
+                        let hi = max.min(sub_origin + IVec3::splat(W)) - sub_origin;
+
+                        for z in lo.z..hi.z {
+                            for x in lo.x..hi.x {
+                                // data is linear on the y axis, so
+                                // walk each run of y in order.
+                                let first = to_subchunk_index_prewrapped(IVec3::new(x, lo.y, z));
+                                for y in lo.y..hi.y {
+                                    let index = first + (y - lo.y) as usize;
+                                    let old = subchunk.replace_by_index(index, state);
+                                    let pos = sub_origin + IVec3::new(x, y, z);
+                                    self.changes.record(subchunk, pos, old, state);
+                                }
+                            }
+                        }
+
+                        for z in lo.z..hi.z {
+                            for x in lo.x..hi.x {
+                                let pos = sub_origin.xz() + IVec2::new(x, z);
+                                let (bottom, top) = (sub_origin.y + lo.y, sub_origin.y + hi.y);
+                                chunk.update_heightmaps(pos, bottom, top, state, self.heightmaps);
+                            }
+                        }
+
+                        written += ((hi - lo).x * (hi - lo).y * (hi - lo).z) as usize;
+                        cy += W;
+                    }
+                }
+                cx += W;
+            }
+            cz += W;
+        }
+
+        written
+    }
+
+    /// Write the blocks in the buffer back to the world, returning the
+    /// number of blocks that were changed. Blocks that are the same as the
+    /// world are skipped, and `BlockState::default()` is not written outside
+    /// of existing subchunks, so a buffer read with `World::read_buffer`
+    /// can be written back without creating subchunks.
+    pub fn write_buffer(&mut self, buffer: &WorldBuffer<BlockState>) -> usize {
+        let mut written = 0;
+        for (pos, state) in buffer.iter() {
+            let skip = match self.get_block(pos) {
+                Some(old) => old == *state,
+                None => *state == BlockState::default(),
+            };
+
+            if !skip && self.set_block(pos, *state).is_some() {
+                written += 1;
+            }
+        }
+        written
+    }
+}
+
+impl<'w> From<&'w mut World> for WorldWriter<'w> {
+    fn from(value: &'w mut World) -> Self {
+        Self {
+            chunks: &mut value.chunks,
+            changes: &mut value.changes,
+            pool: &mut value.allocator,
+            heightmaps: &value.heightmaps,
+            last: null_mut(),
+        }
+    }
+}
+
+/// Get the chunk with this origin, if it exists, using `last` as
+/// a cache. This only borrows the chunks, so the writer can record
+/// changes while holding the chunk.
+fn get_chunk_cached<'a>(
+    chunks: &'a mut BTreeMap<u64, Chunk>,
+    last: &mut *mut Chunk,
+    origin: ChunkOrigin,
+) -> Option<&'a mut Chunk> {
+    // if we do not already have the right chunk,
+    // attempt to get it from the world, returning
+    // None if it does not exist.
+    if last.is_null() || unsafe { (**last).origin } != origin {
+        *last = chunks.get_mut(&combine_into_u64(origin))?;
+    }
+
+    Some(unsafe { &mut **last })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::registry::LocalID;
+
+    use super::util::*;
+    use super::*;
+
+    const STONE: BlockState = BlockState {
+        block: LocalID::new(1),
+        light: Light::ZERO,
+    };
+
+    #[test]
+    fn set_block_returns_old() {
+        let mut world = world_for_testing();
+        let mut writer = world.writer();
+
+        let pos = IVec3::new(0, 2, 0);
+        assert_eq!(2, writer.set_block(pos, STONE).unwrap().block.index());
+        assert_eq!(Some(STONE), writer.set_block(pos, BlockState::default()));
+        assert_eq!(Some(BlockState::default()), writer.get_block(pos));
+    }
+
+    #[test]
+    fn set_block_negative() {
+        let mut world = world_for_testing();
+        let pos = IVec3::new(-1, 5, -(CHUNK_WIDTH as i32));
+        world.writer().set_block(pos, STONE).unwrap();
+        assert_eq!(Some(STONE), world.reader().get_block(pos));
+    }
+
+    #[test]
+    fn set_block_out_of_world() {
+        let mut world = world_for_testing();
+        let mut writer = world.writer();
+        assert_eq!(None, writer.set_block(IVec3::new(0, -1, 0), STONE));
+        assert_eq!(None, writer.set_block(IVec3::new(500, 0, 500), STONE));
+        assert_eq!(None, writer.set_block(IVec3::new(0, MAX_HEIGHT, 0), STONE));
+        assert_eq!(None, writer.set_block(IVec3::new(0, i32::MAX, 0), STONE));
+        assert_eq!(3, writer.get_chunk(IVec2::ZERO).unwrap().height());
+    }
+
+    #[test]
+    fn set_block_creates_subchunks() {
+        let mut world = world_for_testing();
+        let pos = IVec3::new(3, CHUNK_WIDTH as i32 * 6 + 4, 3);
+        assert!(world.get_subchunk(pos).is_none());
+
+        world.writer().set_block(pos, STONE).unwrap();
+
+        let chunk = world.get_chunk(pos).unwrap();
+        assert_eq!(7, chunk.subchunks.len());
+        for (i, sub) in chunk.subchunks.iter().enumerate() {
+            assert_eq!(IVec3::new(0, (i * CHUNK_WIDTH) as i32, 0), sub.origin());
+        }
+        assert_eq!(Some(STONE), world.reader().get_block(pos));
+        assert_eq!(
+            Some(BlockState::default().block),
+            world.reader().get_block(pos - IVec3::Y).map(|b| b.block)
+        );
+    }
+
+    #[test]
+    fn set_many_blocks() {
+        let mut world = world_for_testing();
+        let positions = [IVec3::new(0, 0, 0), IVec3::new(-5, 3, 40), IVec3::new(900, 0, 0)];
+        let written = world
+            .writer()
+            .set_blocks(positions.iter().map(|pos| (*pos, STONE)));
+
+        assert_eq!(2, written);
+        let reader = world.reader();
+        assert_eq!(Some(STONE), reader.get_block(positions[0]));
+        assert_eq!(Some(STONE), reader.get_block(positions[1]));
+    }
+
+    #[test]
+    fn fill_across_chunks() {
+        const W: i32 = CHUNK_WIDTH as i32;
+        let mut world = world_for_testing();
+        let origin = IVec3::new(-4, W - 3, -4);
+        let extent = IVec3::new(8, 6, 8);
+        let bounds = world.reader().volume(origin, extent).bounds();
+        assert_eq!(8 * 6 * 8, world.writer().fill(bounds, STONE));
+
+        let reader = world.reader();
+        for z in -6..6 {
+            for x in -6..6 {
+                for y in W - 5..W + 5 {
+                    let pos = IVec3::new(x, y, z);
+                    assert_eq!(bounds.contains(pos), reader.get_block(pos) == Some(STONE), "at: {pos}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fill_skips_missing_chunks() {
+        const W: i32 = CHUNK_WIDTH as i32;
+        let mut world = world_for_testing();
+        // the world is 3x3 chunks centered on the origin, so
+        // only the [W, 2W) column of this volume is loaded.
+        let written = world
+            .writer()
+            .fill(BlockBox::from_extent(IVec3::new(W, -4, 0), IVec3::new(W * 2, 8, 1)), STONE);
+        assert_eq!(W as usize * 4, written);
+    }
+
+    #[test]
+    fn fill_is_clamped_to_world() {
+        let mut world = world_for_testing();
+        let bounds = BlockBox::new(IVec3::new(0, MAX_HEIGHT - 2, 0), IVec3::new(1, i32::MAX, 1));
+        assert_eq!(2, world.writer().fill(bounds, STONE));
+
+        let chunk = world.get_chunk(IVec3::ZERO).unwrap();
+        assert_eq!(MAX_SUBCHUNKS, chunk.height());
+        assert_eq!(Some(STONE), chunk.get_block(IVec3::new(0, MAX_HEIGHT - 1, 0)));
+    }
+}